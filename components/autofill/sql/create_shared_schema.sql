@@ -67,7 +67,11 @@ CREATE TABLE IF NOT EXISTS credit_cards_data (
     times_used          INTEGER NOT NULL,
 
     /* Same "sync change counter" strategy used by other components. */
-    sync_change_counter INTEGER NOT NULL
+    sync_change_counter INTEGER NOT NULL,
+
+    -- A local-only flag set by `hide_stale_credit_cards()` for cards which
+    -- expired long ago and haven't been used since. Never synced.
+    hidden              INTEGER NOT NULL DEFAULT 0
 );
 
 CREATE TABLE IF NOT EXISTS credit_cards_mirror (
@@ -92,6 +96,49 @@ CREATE TABLE IF NOT EXISTS credit_cards_tombstones (
     time_deleted    INTEGER NOT NULL
 ) WITHOUT ROWID;
 
+-- A local-only log of when credit-card numbers were revealed to the user by
+-- `Store::reveal_credit_card_number()`. Never synced, and never contains the
+-- number itself.
+CREATE TABLE IF NOT EXISTS credit_cards_reveals (
+    id              INTEGER PRIMARY KEY,
+    guid            TEXT NOT NULL,
+    time_revealed   INTEGER NOT NULL
+);
+
+-- IBANs, and similar bank account identifiers. The number itself is stored
+-- encrypted, exactly like `credit_cards_data.cc_number_enc`.
+CREATE TABLE IF NOT EXISTS ibans_data (
+    guid                TEXT NOT NULL PRIMARY KEY CHECK(length(guid) != 0),
+    iban_name           TEXT NOT NULL, -- a nickname for the account
+    -- Encrypted IBAN, stored as a JWE. IBANs are at most 34 chars, and a
+    -- base64 encoded JWE is always going to be longer than that, so the CHECK
+    -- is designed to ensure we don't accidentally store an unencrypted value.
+    -- As for credit-cards, a blank value means we lost the key and need to
+    -- refetch the value from the sync server.
+    iban_enc            TEXT NOT NULL CHECK(length(iban_enc) > 34 OR iban_enc == ''),
+    -- last 4 chars unencrypted. Check no larger than 4 to avoid the full IBAN.
+    iban_last_4         TEXT NOT NULL CHECK(length(iban_last_4) <= 4),
+
+    time_created        INTEGER NOT NULL,
+    time_last_used      INTEGER,
+    time_last_modified  INTEGER NOT NULL,
+    times_used          INTEGER NOT NULL,
+
+    sync_change_counter INTEGER NOT NULL
+);
+
+-- As for credit-cards, the payload here is encrypted as a whole as it
+-- contains the plaintext IBAN.
+CREATE TABLE IF NOT EXISTS ibans_mirror (
+    guid                TEXT NOT NULL PRIMARY KEY CHECK(length(guid) != 0),
+    payload             TEXT NOT NULL CHECK(length(payload) != 0)
+);
+
+CREATE TABLE IF NOT EXISTS ibans_tombstones (
+    guid            TEXT PRIMARY KEY CHECK(length(guid) != 0),
+    time_deleted    INTEGER NOT NULL
+) WITHOUT ROWID;
+
 -- This table holds key-value metadata for the Autofill component and its consumers.
 CREATE TABLE IF NOT EXISTS moz_meta (
     key TEXT PRIMARY KEY,
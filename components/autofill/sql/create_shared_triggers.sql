@@ -48,3 +48,25 @@ BEGIN
     INSERT INTO credit_cards_tombstones(guid, time_deleted)
     VALUES (OLD.guid, now());
 END;
+
+CREATE TEMP TRIGGER IF NOT EXISTS ibans_data_afterinsert_trigger
+AFTER INSERT ON ibans_data
+FOR EACH ROW WHEN NEW.guid IN (SELECT guid FROM ibans_tombstones)
+BEGIN
+    SELECT RAISE(FAIL, 'guid exists in `ibans_tombstones`');
+END;
+
+CREATE TEMP TRIGGER IF NOT EXISTS ibans_tombstones_afterinsert_trigger
+AFTER INSERT ON ibans_tombstones
+WHEN NEW.guid IN (SELECT guid FROM ibans_data)
+BEGIN
+    SELECT RAISE(FAIL, 'guid exists in `ibans_data`');
+END;
+
+CREATE TEMP TRIGGER IF NOT EXISTS ibans_tombstones_create_trigger
+AFTER DELETE ON ibans_data
+WHEN OLD.guid IN (SELECT guid FROM ibans_mirror)
+BEGIN
+    INSERT INTO ibans_tombstones(guid, time_deleted)
+    VALUES (OLD.guid, now());
+END;
@@ -27,3 +27,16 @@ CREATE TEMP TABLE credit_cards_sync_outgoing_staging (
     payload             TEXT NOT NULL CHECK(length(payload) != 0),
     sync_change_counter INTEGER NOT NULL
 );
+
+DROP TABLE IF EXISTS ibans_sync_staging;
+CREATE TEMP TABLE ibans_sync_staging (
+    guid                TEXT NOT NULL PRIMARY KEY CHECK(length(guid) != 0),
+    payload             TEXT NOT NULL CHECK(length(payload) != 0)
+);
+
+DROP TABLE IF EXISTS ibans_sync_outgoing_staging;
+CREATE TEMP TABLE ibans_sync_outgoing_staging (
+    guid                TEXT NOT NULL PRIMARY KEY CHECK(length(guid) != 0),
+    payload             TEXT NOT NULL CHECK(length(payload) != 0),
+    sync_change_counter INTEGER NOT NULL
+);
@@ -0,0 +1,663 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+// Country-specific normalization and formatting of addresses.
+//
+// Addresses are normalized before we store them, and when they arrive via
+// sync, so that the same address entered on different devices (or in
+// different ways) is stored the same way and doesn't look like a conflict.
+// That normalization is lenient - a value we can't make sense of is stored as
+// given - while the functions exposed over the FFI report invalid values, so
+// apps can use them to validate what the user typed.
+//
+// We only know about a handful of countries. The rules are simplified
+// versions of those in Google's libaddressinput and libphonenumber.
+
+use crate::db::models::address::{Address, InternalAddress, UpdatableAddressFields};
+use crate::error::*;
+use error_support::handle_error;
+
+struct CountryInfo {
+    // ISO 3166-1 alpha-2
+    code: &'static str,
+    calling_code: &'static str,
+    // Dialled before a national number, but not part of the E.164 number.
+    trunk_prefix: Option<&'static str>,
+    // The range of lengths of a national number, without the trunk prefix.
+    national_lengths: (usize, usize),
+    // Postal code patterns, in their canonical form - `#` is a digit, `@` is
+    // a letter, and anything else is a separator which is inserted when
+    // normalizing.
+    postal_codes: &'static [&'static str],
+    // (code, name) - a code can have more than one name.
+    regions: &'static [(&'static str, &'static str)],
+    // The lines of an address, for `format_address()`.
+    display_format: &'static [&'static str],
+}
+
+// Used for countries we know nothing about.
+const DEFAULT_DISPLAY_FORMAT: &[&str] = &["%N", "%O", "%A", "%D", "%C %S %Z"];
+
+const US_REGIONS: &[(&str, &str)] = &[
+    ("AL", "Alabama"),
+    ("AK", "Alaska"),
+    ("AZ", "Arizona"),
+    ("AR", "Arkansas"),
+    ("CA", "California"),
+    ("CO", "Colorado"),
+    ("CT", "Connecticut"),
+    ("DE", "Delaware"),
+    ("DC", "District of Columbia"),
+    ("FL", "Florida"),
+    ("GA", "Georgia"),
+    ("HI", "Hawaii"),
+    ("ID", "Idaho"),
+    ("IL", "Illinois"),
+    ("IN", "Indiana"),
+    ("IA", "Iowa"),
+    ("KS", "Kansas"),
+    ("KY", "Kentucky"),
+    ("LA", "Louisiana"),
+    ("ME", "Maine"),
+    ("MD", "Maryland"),
+    ("MA", "Massachusetts"),
+    ("MI", "Michigan"),
+    ("MN", "Minnesota"),
+    ("MS", "Mississippi"),
+    ("MO", "Missouri"),
+    ("MT", "Montana"),
+    ("NE", "Nebraska"),
+    ("NV", "Nevada"),
+    ("NH", "New Hampshire"),
+    ("NJ", "New Jersey"),
+    ("NM", "New Mexico"),
+    ("NY", "New York"),
+    ("NC", "North Carolina"),
+    ("ND", "North Dakota"),
+    ("OH", "Ohio"),
+    ("OK", "Oklahoma"),
+    ("OR", "Oregon"),
+    ("PA", "Pennsylvania"),
+    ("PR", "Puerto Rico"),
+    ("RI", "Rhode Island"),
+    ("SC", "South Carolina"),
+    ("SD", "South Dakota"),
+    ("TN", "Tennessee"),
+    ("TX", "Texas"),
+    ("UT", "Utah"),
+    ("VT", "Vermont"),
+    ("VA", "Virginia"),
+    ("WA", "Washington"),
+    ("WV", "West Virginia"),
+    ("WI", "Wisconsin"),
+    ("WY", "Wyoming"),
+];
+
+const CA_REGIONS: &[(&str, &str)] = &[
+    ("AB", "Alberta"),
+    ("BC", "British Columbia"),
+    ("MB", "Manitoba"),
+    ("NB", "New Brunswick"),
+    ("NL", "Newfoundland and Labrador"),
+    ("NS", "Nova Scotia"),
+    ("NT", "Northwest Territories"),
+    ("NU", "Nunavut"),
+    ("ON", "Ontario"),
+    ("PE", "Prince Edward Island"),
+    ("QC", "Quebec"),
+    ("QC", "Québec"),
+    ("SK", "Saskatchewan"),
+    ("YT", "Yukon"),
+];
+
+const AU_REGIONS: &[(&str, &str)] = &[
+    ("ACT", "Australian Capital Territory"),
+    ("NSW", "New South Wales"),
+    ("NT", "Northern Territory"),
+    ("QLD", "Queensland"),
+    ("SA", "South Australia"),
+    ("TAS", "Tasmania"),
+    ("VIC", "Victoria"),
+    ("WA", "Western Australia"),
+];
+
+const COUNTRIES: &[CountryInfo] = &[
+    CountryInfo {
+        code: "US",
+        calling_code: "1",
+        trunk_prefix: Some("1"),
+        national_lengths: (10, 10),
+        postal_codes: &["#####", "#####-####"],
+        regions: US_REGIONS,
+        display_format: &["%N", "%O", "%A", "%C, %S %Z"],
+    },
+    CountryInfo {
+        code: "CA",
+        calling_code: "1",
+        trunk_prefix: Some("1"),
+        national_lengths: (10, 10),
+        postal_codes: &["@#@ #@#"],
+        regions: CA_REGIONS,
+        display_format: &["%N", "%O", "%A", "%C %S %Z"],
+    },
+    CountryInfo {
+        code: "GB",
+        calling_code: "44",
+        trunk_prefix: Some("0"),
+        national_lengths: (9, 10),
+        postal_codes: &[
+            "@# #@@", "@## #@@", "@@# #@@", "@@## #@@", "@#@ #@@", "@@#@ #@@",
+        ],
+        regions: &[],
+        display_format: &["%N", "%O", "%A", "%D", "%C", "%Z"],
+    },
+    CountryInfo {
+        code: "DE",
+        calling_code: "49",
+        trunk_prefix: Some("0"),
+        national_lengths: (6, 13),
+        postal_codes: &["#####"],
+        regions: &[],
+        display_format: &["%N", "%O", "%A", "%Z %C"],
+    },
+    CountryInfo {
+        code: "FR",
+        calling_code: "33",
+        trunk_prefix: Some("0"),
+        national_lengths: (9, 9),
+        postal_codes: &["#####"],
+        regions: &[],
+        display_format: &["%N", "%O", "%A", "%Z %C"],
+    },
+    CountryInfo {
+        code: "IT",
+        calling_code: "39",
+        // Italian numbers keep their leading zero.
+        trunk_prefix: None,
+        national_lengths: (6, 11),
+        postal_codes: &["#####"],
+        regions: &[],
+        display_format: &["%N", "%O", "%A", "%Z %C %S"],
+    },
+    CountryInfo {
+        code: "ES",
+        calling_code: "34",
+        trunk_prefix: None,
+        national_lengths: (9, 9),
+        postal_codes: &["#####"],
+        regions: &[],
+        display_format: &["%N", "%O", "%A", "%Z %C %S"],
+    },
+    CountryInfo {
+        code: "NL",
+        calling_code: "31",
+        trunk_prefix: Some("0"),
+        national_lengths: (9, 9),
+        postal_codes: &["#### @@"],
+        regions: &[],
+        display_format: &["%N", "%O", "%A", "%Z %C"],
+    },
+    CountryInfo {
+        code: "AU",
+        calling_code: "61",
+        trunk_prefix: Some("0"),
+        national_lengths: (9, 9),
+        postal_codes: &["####"],
+        regions: AU_REGIONS,
+        display_format: &["%N", "%O", "%A", "%C %S %Z"],
+    },
+    CountryInfo {
+        code: "JP",
+        calling_code: "81",
+        trunk_prefix: Some("0"),
+        national_lengths: (9, 10),
+        postal_codes: &["###-####"],
+        regions: &[],
+        display_format: &["%Z", "%S %C", "%A", "%O", "%N"],
+    },
+];
+
+// E.164 numbers have at most 15 digits, and in practice at least 8.
+const E164_LENGTHS: (usize, usize) = (8, 15);
+
+fn country_info(country: &str) -> Option<&'static CountryInfo> {
+    let country = country.trim();
+    COUNTRIES
+        .iter()
+        .find(|info| info.code.eq_ignore_ascii_case(country))
+}
+
+// A public function we expose over the FFI. Returns the canonical form of the
+// postal code for the country, or an `InvalidRecord` error if it's not valid
+// there. Postal codes for countries we don't know about are just trimmed.
+#[handle_error(Error)]
+pub fn normalize_postal_code(country: String, postal_code: String) -> ApiResult<String> {
+    match country_info(&country) {
+        None => Ok(postal_code.trim().to_string()),
+        Some(info) => canonical_postal_code(info, &postal_code).ok_or_else(|| {
+            Error::InvalidAddressField(format!("invalid postal code for {}", info.code))
+        }),
+    }
+}
+
+// A public function we expose over the FFI. Returns the phone number in E.164
+// format (eg, "+15551234567"), or an `InvalidRecord` error if it can't be
+// converted. Numbers without a "+" or international prefix are assumed to be
+// national numbers of the country.
+#[handle_error(Error)]
+pub fn normalize_tel(country: String, tel: String) -> ApiResult<String> {
+    e164_tel(country_info(&country), &tel)
+        .ok_or_else(|| Error::InvalidAddressField("invalid phone number".to_string()))
+}
+
+// A public function we expose over the FFI. Renders the address as lines in
+// the conventional order for its country, without the country itself (which
+// apps will want to localize). Empty fields are skipped.
+pub fn format_address(address: Address) -> String {
+    let name = [
+        &address.given_name,
+        &address.additional_name,
+        &address.family_name,
+    ]
+    .iter()
+    .map(|s| s.trim())
+    .filter(|s| !s.is_empty())
+    .collect::<Vec<_>>()
+    .join(" ");
+    let display_format = country_info(&address.country)
+        .map(|info| info.display_format)
+        .unwrap_or(DEFAULT_DISPLAY_FORMAT);
+    display_format
+        .iter()
+        .flat_map(|line| {
+            fill_line(line, &address, &name)
+                // The street address may have multiple lines.
+                .lines()
+                .map(tidy_line)
+                .collect::<Vec<_>>()
+        })
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Substitute the fields for the `%` placeholders in a line of a display
+// format. This is done in a single pass, so that a `%` in a field's value is
+// left alone.
+fn fill_line(line: &str, address: &Address, name: &str) -> String {
+    let mut filled = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            filled.push(c);
+            continue;
+        }
+        let placeholder = chars.next();
+        let value = match placeholder {
+            Some('N') => name,
+            Some('O') => &address.organization,
+            Some('A') => &address.street_address,
+            Some('D') => &address.address_level3,
+            Some('C') => &address.address_level2,
+            Some('S') => &address.address_level1,
+            Some('Z') => &address.postal_code,
+            _ => {
+                filled.push('%');
+                filled.extend(placeholder);
+                continue;
+            }
+        };
+        filled.push_str(value);
+    }
+    filled
+}
+
+// Collapse the whitespace left by empty fields, and any separator which now
+// has nothing to separate.
+fn tidy_line(line: &str) -> String {
+    line.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace(" ,", ",")
+        .trim_matches(|c: char| c == ',' || c.is_whitespace())
+        .to_string()
+}
+
+/// Normalize the fields of an address which we can for its country, leaving
+/// any we can't make sense of as they are.
+pub(crate) fn normalize_address(address: &mut InternalAddress) {
+    normalize_fields(
+        &address.country,
+        &mut address.address_level1,
+        &mut address.postal_code,
+        &mut address.tel,
+    );
+}
+
+/// As for `normalize_address()`, but for the fields passed to add or update an
+/// address.
+pub(crate) fn normalize_address_fields(fields: &mut UpdatableAddressFields) {
+    normalize_fields(
+        &fields.country,
+        &mut fields.address_level1,
+        &mut fields.postal_code,
+        &mut fields.tel,
+    );
+}
+
+fn normalize_fields(
+    country: &str,
+    address_level1: &mut String,
+    postal_code: &mut String,
+    tel: &mut String,
+) {
+    let info = country_info(country);
+    if let Some(info) = info {
+        if let Some(code) = region_code(info, address_level1) {
+            *address_level1 = code.to_string();
+        }
+        if let Some(canonical) = canonical_postal_code(info, postal_code) {
+            *postal_code = canonical;
+        }
+    }
+    if let Some(e164) = e164_tel(info, tel) {
+        *tel = e164;
+    }
+}
+
+fn region_code(info: &CountryInfo, region: &str) -> Option<&'static str> {
+    let region = region.split_whitespace().collect::<Vec<_>>().join(" ");
+    info.regions
+        .iter()
+        .find(|(code, name)| {
+            code.eq_ignore_ascii_case(&region) || name.to_lowercase() == region.to_lowercase()
+        })
+        .map(|(code, _)| *code)
+}
+
+fn canonical_postal_code(info: &CountryInfo, postal_code: &str) -> Option<String> {
+    let compact = postal_code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_ascii_uppercase();
+    info.postal_codes
+        .iter()
+        .find_map(|pattern| apply_postal_pattern(pattern, &compact))
+}
+
+fn apply_postal_pattern(pattern: &str, compact: &str) -> Option<String> {
+    let mut chars = compact.chars();
+    let mut result = String::with_capacity(pattern.len());
+    for p in pattern.chars() {
+        match p {
+            '#' | '@' => {
+                let c = chars.next()?;
+                let matches = if p == '#' {
+                    c.is_ascii_digit()
+                } else {
+                    c.is_ascii_alphabetic()
+                };
+                if !matches {
+                    return None;
+                }
+                result.push(c);
+            }
+            separator => result.push(separator),
+        }
+    }
+    // All of the postal code must have been used.
+    match chars.next() {
+        Some(_) => None,
+        None => Some(result),
+    }
+}
+
+// Numbers are often written as "+44 (0)20 7946 0000", where the "(0)" is the
+// trunk prefix, which is only dialled from inside the country. It must not end
+// up in the international number.
+fn without_trunk_prefix(tel: &str) -> String {
+    if let Some(rest) = tel.strip_prefix('+') {
+        let calling_code_len = rest.chars().take_while(char::is_ascii_digit).count();
+        if let Some(national) = rest[calling_code_len..].trim_start().strip_prefix("(0)") {
+            return format!("+{} {}", &rest[..calling_code_len], national);
+        }
+    }
+    tel.to_string()
+}
+
+fn e164_tel(info: Option<&CountryInfo>, tel: &str) -> Option<String> {
+    let tel = without_trunk_prefix(tel.trim());
+    let tel = tel.as_str();
+    let digits = tel
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .collect::<String>();
+    // Something like "call me" isn't a phone number at all.
+    if tel
+        .chars()
+        .any(|c| !c.is_ascii_digit() && !matches!(c, '+' | '-' | '.' | '(' | ')' | ' '))
+    {
+        return None;
+    }
+    let international = if tel.starts_with('+') {
+        Some(digits.as_str())
+    } else if let Some(rest) = digits.strip_prefix("00") {
+        Some(rest)
+    } else {
+        match info {
+            Some(info) if info.calling_code == "1" => digits.strip_prefix("011"),
+            _ => None,
+        }
+    };
+    let e164_digits = match (international, info) {
+        (Some(international), _) => international.to_string(),
+        (None, Some(info)) => {
+            let is_valid_length =
+                |n: &str| (info.national_lengths.0..=info.national_lengths.1).contains(&n.len());
+            let national = match info.trunk_prefix.and_then(|p| digits.strip_prefix(p)) {
+                Some(without_prefix) if is_valid_length(without_prefix) => without_prefix,
+                _ => digits.as_str(),
+            };
+            if !is_valid_length(national) {
+                return None;
+            }
+            format!("{}{}", info.calling_code, national)
+        }
+        (None, None) => return None,
+    };
+    if !(E164_LENGTHS.0..=E164_LENGTHS.1).contains(&e164_digits.len()) {
+        return None;
+    }
+    Some(format!("+{e164_digits}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_postal_code() {
+        for (country, postal_code, expected) in [
+            ("US", " 98101 ", "98101"),
+            ("US", "981011234", "98101-1234"),
+            ("us", "98101 1234", "98101-1234"),
+            ("CA", "k1a0b1", "K1A 0B1"),
+            ("GB", "sw1a1aa", "SW1A 1AA"),
+            ("GB", "M1 1AE", "M1 1AE"),
+            ("NL", "1234ab", "1234 AB"),
+            ("JP", "1000001", "100-0001"),
+            ("DE", "10115", "10115"),
+            // We don't know about New Zealand.
+            ("NZ", " 6011 ", "6011"),
+        ] {
+            assert_eq!(
+                normalize_postal_code(country.to_string(), postal_code.to_string()).unwrap(),
+                expected,
+                "{country} {postal_code}"
+            );
+        }
+        for (country, postal_code) in [
+            ("US", "9810"),
+            ("US", "ABCDE"),
+            ("CA", "K1A 0B"),
+            ("GB", "SW1A 1A1"),
+            ("DE", "101155"),
+        ] {
+            assert!(
+                matches!(
+                    normalize_postal_code(country.to_string(), postal_code.to_string()),
+                    Err(AutofillApiError::InvalidRecord { .. })
+                ),
+                "{country} {postal_code}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalize_tel() {
+        for (country, tel, expected) in [
+            ("US", "(555) 123-4567", "+15551234567"),
+            ("US", "1-555-123-4567", "+15551234567"),
+            ("US", "+1 555 123 4567", "+15551234567"),
+            ("US", "011 44 20 7946 0000", "+442079460000"),
+            ("GB", "+44 (0)20 7946 0000", "+442079460000"),
+            ("US", "+44(0) 20 7946 0000", "+442079460000"),
+            ("GB", "020 7946 0000", "+442079460000"),
+            ("DE", "030 123456", "+4930123456"),
+            ("DE", "0049 30 123456", "+4930123456"),
+            ("IT", "06 1234 5678", "+390612345678"),
+            ("FR", "01.23.45.67.89", "+33123456789"),
+            // Without a known country, only international numbers work.
+            ("", "+61 2 9876 5432", "+61298765432"),
+        ] {
+            assert_eq!(
+                normalize_tel(country.to_string(), tel.to_string()).unwrap(),
+                expected,
+                "{country} {tel}"
+            );
+        }
+        for (country, tel) in [
+            ("US", "555-1234"),
+            ("US", "call me"),
+            ("", "555 123 4567"),
+            ("AU", "123456"),
+            ("", "+1 234"),
+            ("GB", "+44 (0)20"),
+            ("", "+1 234 567 890 123 456"),
+            ("US", "011 1234 5678 9012 3456"),
+        ] {
+            assert!(
+                matches!(
+                    normalize_tel(country.to_string(), tel.to_string()),
+                    Err(AutofillApiError::InvalidRecord { .. })
+                ),
+                "{country} {tel}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalize_address_fields() {
+        let mut fields = UpdatableAddressFields {
+            address_level1: "  new   york ".to_string(),
+            postal_code: "100011234".to_string(),
+            country: "US".to_string(),
+            tel: "(212) 555-0100".to_string(),
+            ..Default::default()
+        };
+        normalize_address_fields(&mut fields);
+        assert_eq!(fields.address_level1, "NY");
+        assert_eq!(fields.postal_code, "10001-1234");
+        assert_eq!(fields.tel, "+12125550100");
+
+        // Numbers which can't be valid E.164 are kept as the user typed them.
+        for tel in ["+44 (0)20", "+1 234 567 890 123 456"] {
+            let mut fields = UpdatableAddressFields {
+                country: "GB".to_string(),
+                tel: tel.to_string(),
+                ..Default::default()
+            };
+            normalize_address_fields(&mut fields);
+            assert_eq!(fields.tel, tel);
+        }
+
+        // Things we can't make sense of are left alone.
+        let mut fields = UpdatableAddressFields {
+            address_level1: "Narnia".to_string(),
+            postal_code: "1".to_string(),
+            country: "US".to_string(),
+            tel: "ext 123".to_string(),
+            ..Default::default()
+        };
+        normalize_address_fields(&mut fields);
+        assert_eq!(fields.address_level1, "Narnia");
+        assert_eq!(fields.postal_code, "1");
+        assert_eq!(fields.tel, "ext 123");
+
+        let mut address = InternalAddress {
+            address_level1: "québec".to_string(),
+            postal_code: "h2x 1y4".to_string(),
+            country: "CA".to_string(),
+            ..Default::default()
+        };
+        normalize_address(&mut address);
+        assert_eq!(address.address_level1, "QC");
+        assert_eq!(address.postal_code, "H2X 1Y4");
+    }
+
+    #[test]
+    fn test_format_address() {
+        let address = Address {
+            given_name: "Jane".to_string(),
+            family_name: "Doe".to_string(),
+            street_address: "123 Main St\nApt 4".to_string(),
+            address_level2: "Seattle".to_string(),
+            address_level1: "WA".to_string(),
+            postal_code: "98101".to_string(),
+            country: "US".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_address(address.clone()),
+            "Jane Doe\n123 Main St\nApt 4\nSeattle, WA 98101"
+        );
+        assert_eq!(
+            format_address(Address {
+                address_level1: "".to_string(),
+                ..address.clone()
+            }),
+            "Jane Doe\n123 Main St\nApt 4\nSeattle, 98101"
+        );
+        assert_eq!(
+            format_address(Address {
+                organization: "Mozilla".to_string(),
+                address_level2: "Berlin".to_string(),
+                address_level1: "".to_string(),
+                postal_code: "10115".to_string(),
+                country: "DE".to_string(),
+                ..address.clone()
+            }),
+            "Jane Doe\nMozilla\n123 Main St\nApt 4\n10115 Berlin"
+        );
+        assert_eq!(
+            format_address(Address {
+                country: "XX".to_string(),
+                ..address.clone()
+            }),
+            "Jane Doe\n123 Main St\nApt 4\nSeattle WA 98101"
+        );
+        // Values which look like placeholders are left alone.
+        assert_eq!(
+            format_address(Address {
+                organization: "50%Co".to_string(),
+                street_address: "%S %Z".to_string(),
+                address_level2: "%N".to_string(),
+                ..address
+            }),
+            "Jane Doe\n50%Co\n%S %Z\n%N, WA 98101"
+        );
+    }
+}
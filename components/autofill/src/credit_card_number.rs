@@ -0,0 +1,225 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+// Validation of credit-card numbers and detection of their network, so that
+// apps don't each need their own implementation, and so that the `cc_type`
+// and `cc_number_last_4` we store match what desktop stores (and syncs).
+//
+// The rules here are based on desktop's `CreditCard.sys.mjs`:
+// https://searchfox.org/mozilla-central/source/toolkit/modules/CreditCard.sys.mjs
+
+use crate::encryption::EncryptorDecryptor;
+use crate::error::*;
+use error_support::handle_error;
+
+/// What you get back from `encrypt_credit_card_number()` - the values for the
+/// matching fields of `UpdatableCreditCardFields`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedCreditCardNumber {
+    pub cc_number_enc: String,
+    pub cc_number_last_4: String,
+    // One of the network names below, or an empty string if the network
+    // couldn't be determined.
+    pub cc_type: String,
+}
+
+// The shortest and longest numbers desktop accepts.
+const MIN_LENGTH: usize = 12;
+const MAX_LENGTH: usize = 19;
+
+// Issuer identification number ranges for each network. The prefixes are
+// inclusive ranges of the given number of leading digits, and the card must
+// have a length within `lengths`. When more than one range matches, the one
+// with the longest prefix wins, as it's the most specific.
+struct IinRange {
+    network: &'static str,
+    prefix: (u32, u32),
+    prefix_len: usize,
+    lengths: (usize, usize),
+}
+
+const fn iin(
+    network: &'static str,
+    prefix: (u32, u32),
+    prefix_len: usize,
+    lengths: (usize, usize),
+) -> IinRange {
+    IinRange {
+        network,
+        prefix,
+        prefix_len,
+        lengths,
+    }
+}
+
+const IIN_RANGES: &[IinRange] = &[
+    iin("amex", (34, 34), 2, (15, 15)),
+    iin("amex", (37, 37), 2, (15, 15)),
+    iin("cartebancaire", (4035, 4035), 4, (16, 16)),
+    iin("cartebancaire", (4360, 4360), 4, (16, 16)),
+    iin("diners", (300, 305), 3, (14, 19)),
+    iin("diners", (3095, 3095), 4, (14, 19)),
+    iin("diners", (36, 36), 2, (14, 19)),
+    iin("diners", (38, 39), 2, (14, 19)),
+    iin("discover", (6011, 6011), 4, (16, 19)),
+    iin("discover", (622126, 622925), 6, (16, 19)),
+    iin("discover", (624000, 626999), 6, (16, 16)),
+    iin("discover", (628200, 628899), 6, (16, 16)),
+    iin("discover", (64, 65), 2, (16, 19)),
+    iin("jcb", (3528, 3589), 4, (16, 19)),
+    iin("mastercard", (2221, 2720), 4, (16, 16)),
+    iin("mastercard", (51, 55), 2, (16, 16)),
+    iin("mir", (2200, 2204), 4, (16, 16)),
+    iin("unionpay", (62, 62), 2, (16, 19)),
+    iin("unionpay", (81, 81), 2, (16, 19)),
+    iin("visa", (4, 4), 1, (13, 19)),
+];
+
+// A public function we expose over the FFI (which is why it takes `String`
+// rather than `&str`). `number` may contain spaces or dashes, as typed.
+#[handle_error(Error)]
+pub fn encrypt_credit_card_number(
+    key: String,
+    number: String,
+) -> ApiResult<EncryptedCreditCardNumber> {
+    let number = normalize_number(&number)?;
+    Ok(EncryptedCreditCardNumber {
+        cc_number_enc: EncryptorDecryptor::new(&key)?.encrypt(&number, "credit card number")?,
+        cc_number_last_4: number[number.len() - 4..].to_string(),
+        cc_type: detect_network(&number).unwrap_or_default().to_string(),
+    })
+}
+
+/// Strip the separators from a credit-card number and check it's valid,
+/// returning just the digits. Error messages never include the number.
+pub(crate) fn normalize_number(number: &str) -> Result<String> {
+    let digits = number
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-'))
+        .collect::<String>();
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(Error::InvalidCreditCardNumber(
+            "must only contain digits, spaces and dashes".to_string(),
+        ));
+    }
+    if !(MIN_LENGTH..=MAX_LENGTH).contains(&digits.len()) {
+        return Err(Error::InvalidCreditCardNumber(format!(
+            "must have between {MIN_LENGTH} and {MAX_LENGTH} digits"
+        )));
+    }
+    if !passes_luhn(&digits) {
+        return Err(Error::InvalidCreditCardNumber(
+            "failed the checksum".to_string(),
+        ));
+    }
+    Ok(digits)
+}
+
+// https://en.wikipedia.org/wiki/Luhn_algorithm - `digits` must be ASCII digits.
+fn passes_luhn(digits: &str) -> bool {
+    let sum: u32 = digits
+        .bytes()
+        .rev()
+        .map(|b| u32::from(b - b'0'))
+        .enumerate()
+        .map(|(i, d)| match (i % 2, d * 2) {
+            (0, _) => d,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+/// The network of a credit-card number consisting only of digits, as the
+/// string desktop uses for `cc_type`.
+pub(crate) fn detect_network(digits: &str) -> Option<&'static str> {
+    IIN_RANGES
+        .iter()
+        .filter(|range| {
+            (range.lengths.0..=range.lengths.1).contains(&digits.len())
+                && digits
+                    .get(..range.prefix_len)
+                    .and_then(|prefix| prefix.parse::<u32>().ok())
+                    .map_or(false, |prefix| {
+                        (range.prefix.0..=range.prefix.1).contains(&prefix)
+                    })
+        })
+        .max_by_key(|range| range.prefix_len)
+        .map(|range| range.network)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::create_autofill_key;
+
+    #[test]
+    fn test_luhn() {
+        assert!(passes_luhn("4111111111111111"));
+        assert!(passes_luhn("378282246310005"));
+        assert!(!passes_luhn("4111111111111112"));
+        assert!(!passes_luhn("378282246310006"));
+    }
+
+    #[test]
+    fn test_detect_network() {
+        for (number, expected) in [
+            ("378282246310005", Some("amex")),
+            ("4035501000000008", Some("cartebancaire")),
+            ("30569309025904", Some("diners")),
+            ("6011111111111117", Some("discover")),
+            ("6221260000000000", Some("discover")),
+            ("3530111333300000", Some("jcb")),
+            ("2221000000000009", Some("mastercard")),
+            ("5555555555554444", Some("mastercard")),
+            ("2200000000000004", Some("mir")),
+            ("6200000000000005", Some("unionpay")),
+            ("4111111111111111", Some("visa")),
+            // Right prefix, wrong length.
+            ("37828224631000", None),
+            ("9999999999999995", None),
+        ] {
+            assert_eq!(detect_network(number), expected, "{number}");
+        }
+    }
+
+    #[test]
+    fn test_normalize_number() {
+        assert_eq!(
+            normalize_number("4111 1111-1111 1111").unwrap(),
+            "4111111111111111"
+        );
+        for invalid in ["4111 1111 1111 1112", "41111", "4111.1111.1111.1111", ""] {
+            assert!(
+                matches!(
+                    normalize_number(invalid),
+                    Err(Error::InvalidCreditCardNumber(_))
+                ),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_encrypt_credit_card_number() {
+        let key = create_autofill_key().unwrap();
+        let result =
+            encrypt_credit_card_number(key.clone(), "3782 822463 10005".to_string()).unwrap();
+        assert_eq!(result.cc_number_last_4, "0005");
+        assert_eq!(result.cc_type, "amex");
+        assert_eq!(
+            EncryptorDecryptor::new(&key)
+                .unwrap()
+                .decrypt(&result.cc_number_enc, "test")
+                .unwrap(),
+            "378282246310005"
+        );
+        assert!(matches!(
+            encrypt_credit_card_number(key, "1234".to_string()),
+            Err(AutofillApiError::InvalidRecord { .. })
+        ));
+    }
+}
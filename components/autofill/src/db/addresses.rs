@@ -3,12 +3,15 @@
 * file, You can obtain one at http://mozilla.org/MPL/2.0/.
 */
 
+use crate::address_normalization::{normalize_address, normalize_address_fields};
 use crate::db::{
     models::{
-        address::{InternalAddress, UpdatableAddressFields},
+        address::{AddressField, InternalAddress, UpdatableAddressFields},
         Metadata,
     },
+    prefix_match_sql,
     schema::{ADDRESS_COMMON_COLS, ADDRESS_COMMON_VALS},
+    FRECENCY_SQL,
 };
 use crate::error::*;
 
@@ -18,14 +21,21 @@ use types::Timestamp;
 
 pub(crate) fn add_address(
     conn: &Connection,
-    new: UpdatableAddressFields,
+    mut new: UpdatableAddressFields,
 ) -> Result<InternalAddress> {
+    normalize_address_fields(&mut new);
     let tx = conn.unchecked_transaction()?;
-    let now = Timestamp::now();
+    let address = new_internal_address(new);
+    add_internal_address(&tx, &address)?;
+    tx.commit()?;
+    Ok(address)
+}
 
-    // We return an InternalAddress, so set it up first, including the missing
-    // fields, before we insert it.
-    let address = InternalAddress {
+// We return an InternalAddress, so set it up first, including the missing
+// fields, before we insert it.
+fn new_internal_address(new: UpdatableAddressFields) -> InternalAddress {
+    let now = Timestamp::now();
+    InternalAddress {
         guid: Guid::random(),
         given_name: new.given_name,
         additional_name: new.additional_name,
@@ -44,10 +54,7 @@ pub(crate) fn add_address(
             time_last_modified: now,
             ..Default::default()
         },
-    };
-    add_internal_address(&tx, &address)?;
-    tx.commit()?;
-    Ok(address)
+    }
 }
 
 pub(crate) fn add_internal_address(tx: &Transaction<'_>, address: &InternalAddress) -> Result<()> {
@@ -119,6 +126,42 @@ pub(crate) fn get_all_addresses(conn: &Connection) -> Result<Vec<InternalAddress
     Ok(addresses)
 }
 
+/// Addresses where `field` is non-empty and starts with `prefix`, best first,
+/// ranked by how frequently and recently they've been used.
+pub(crate) fn suggest_addresses(
+    conn: &Connection,
+    field: AddressField,
+    prefix: &str,
+    limit: u32,
+    now: Timestamp,
+) -> Result<Vec<InternalAddress>> {
+    let sql = format!(
+        "SELECT
+            {common_cols},
+            sync_change_counter
+        FROM addresses_data
+        WHERE {column} != ''
+            AND {prefix_match}
+        ORDER BY {FRECENCY_SQL} DESC, time_last_used DESC
+        LIMIT :limit",
+        common_cols = ADDRESS_COMMON_COLS,
+        column = field.column(),
+        prefix_match = prefix_match_sql(field.column()),
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let addresses = stmt
+        .query_map(
+            rusqlite::named_params! {
+                ":prefix": prefix.trim(),
+                ":limit": limit,
+                ":now": now,
+            },
+            InternalAddress::from_row,
+        )?
+        .collect::<std::result::Result<Vec<InternalAddress>, _>>()?;
+    Ok(addresses)
+}
+
 /// Updates just the "updatable" columns - suitable for exposure as a public
 /// API.
 pub(crate) fn update_address(
@@ -126,6 +169,8 @@ pub(crate) fn update_address(
     guid: &Guid,
     address: &UpdatableAddressFields,
 ) -> Result<()> {
+    let mut address = address.clone();
+    normalize_address_fields(&mut address);
     let tx = conn.unchecked_transaction()?;
     tx.execute(
         "UPDATE addresses_data
@@ -235,6 +280,178 @@ pub(crate) fn delete_address(conn: &Connection, guid: &Guid) -> Result<bool> {
     Ok(exists)
 }
 
+// Duplicate detection. Addresses saved from form submissions tend to differ in
+// trivial ways - case, whitespace, punctuation, how a phone number is
+// formatted - so we compare normalized versions of the fields rather than the
+// raw values.
+
+#[derive(Clone, Copy)]
+enum FieldKind {
+    Text,
+    Street,
+    Tel,
+    PostalCode,
+}
+
+// The fields of an address, in a fixed order, along with how each should be
+// normalized. Works with anything which has the address fields, which is
+// `UpdatableAddressFields` and `InternalAddress`.
+macro_rules! address_fields {
+    ($a:expr) => {
+        [
+            (&$a.given_name, FieldKind::Text),
+            (&$a.additional_name, FieldKind::Text),
+            (&$a.family_name, FieldKind::Text),
+            (&$a.organization, FieldKind::Text),
+            (&$a.street_address, FieldKind::Street),
+            (&$a.address_level3, FieldKind::Text),
+            (&$a.address_level2, FieldKind::Text),
+            (&$a.address_level1, FieldKind::Text),
+            (&$a.postal_code, FieldKind::PostalCode),
+            (&$a.country, FieldKind::Text),
+            (&$a.tel, FieldKind::Tel),
+            (&$a.email, FieldKind::Text),
+        ]
+    };
+}
+
+fn normalize_field(value: &str, kind: FieldKind) -> String {
+    match kind {
+        FieldKind::Text => value
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase(),
+        // Street addresses are often multi-line, and people aren't consistent
+        // with punctuation (eg, "Apt. 3, 1 Main St." vs "Apt 3\n1 Main St").
+        FieldKind::Street => value
+            .split(|c: char| c.is_whitespace() || matches!(c, '.' | ',' | '#'))
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase(),
+        // Only the digits matter, plus whether it's in international format.
+        FieldKind::Tel => value
+            .trim()
+            .chars()
+            .enumerate()
+            .filter(|(i, c)| c.is_ascii_digit() || (*i == 0 && *c == '+'))
+            .map(|(_, c)| c)
+            .collect(),
+        FieldKind::PostalCode => value
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .collect::<String>()
+            .to_uppercase(),
+    }
+}
+
+/// Whether two addresses are the same once normalized. Synced addresses are
+/// stored as other devices wrote them, so this normalizes copies of both.
+pub(crate) fn addresses_match(a: &InternalAddress, b: &InternalAddress) -> bool {
+    let (mut a, mut b) = (a.clone(), b.clone());
+    normalize_address(&mut a);
+    normalize_address(&mut b);
+    address_fields!(a)
+        .into_iter()
+        .zip(address_fields!(b))
+        .all(|((a, kind), (b, _))| normalize_field(a, kind) == normalize_field(b, kind))
+}
+
+// Whether `new` can be merged into `existing` - that is, every field is the
+// same once normalized, or is empty in one of them. They must also share at
+// least one non-empty field, so two mostly-empty addresses aren't merged.
+// `new` must already have been normalized.
+fn is_mergeable(existing: &InternalAddress, new: &UpdatableAddressFields) -> bool {
+    // Synced addresses are stored as other devices wrote them.
+    let mut existing = existing.clone();
+    normalize_address(&mut existing);
+    let mut have_common_field = false;
+    for ((existing, kind), (new, _)) in address_fields!(existing)
+        .into_iter()
+        .zip(address_fields!(new))
+    {
+        let (existing, new) = (normalize_field(existing, kind), normalize_field(new, kind));
+        if existing.is_empty() || new.is_empty() {
+            continue;
+        }
+        if existing != new {
+            return false;
+        }
+        have_common_field = true;
+    }
+    have_common_field
+}
+
+/// Find an existing address which `address` is a duplicate of, as
+/// `add_or_merge_address()` would merge it into. If there are several, the
+/// most used is returned.
+pub(crate) fn find_duplicate_address(
+    conn: &Connection,
+    address: &UpdatableAddressFields,
+) -> Result<Option<InternalAddress>> {
+    let mut address = address.clone();
+    normalize_address_fields(&mut address);
+    Ok(get_all_addresses(conn)?
+        .into_iter()
+        .filter(|existing| is_mergeable(existing, &address))
+        .max_by_key(|existing| {
+            (
+                existing.metadata.times_used,
+                existing.metadata.time_last_used,
+            )
+        }))
+}
+
+/// Add an address, unless it's a duplicate of an existing one, in which case
+/// any fields missing from the existing address are filled in from it and the
+/// existing address is marked as used.
+pub(crate) fn add_or_merge_address(
+    conn: &Connection,
+    mut new: UpdatableAddressFields,
+) -> Result<InternalAddress> {
+    normalize_address_fields(&mut new);
+    // Look for the duplicate in the same transaction we write in, so another
+    // write can't change or delete it in between.
+    let tx = conn.unchecked_transaction()?;
+    let mut existing = match find_duplicate_address(&tx, &new)? {
+        Some(existing) => existing,
+        None => {
+            let address = new_internal_address(new);
+            add_internal_address(&tx, &address)?;
+            tx.commit()?;
+            return Ok(address);
+        }
+    };
+    let now = Timestamp::now();
+    for (field, value) in [
+        (&mut existing.given_name, new.given_name),
+        (&mut existing.additional_name, new.additional_name),
+        (&mut existing.family_name, new.family_name),
+        (&mut existing.organization, new.organization),
+        (&mut existing.street_address, new.street_address),
+        (&mut existing.address_level3, new.address_level3),
+        (&mut existing.address_level2, new.address_level2),
+        (&mut existing.address_level1, new.address_level1),
+        (&mut existing.postal_code, new.postal_code),
+        (&mut existing.country, new.country),
+        (&mut existing.tel, new.tel),
+        (&mut existing.email, new.email),
+    ] {
+        if field.trim().is_empty() && !value.trim().is_empty() {
+            *field = value;
+            existing.metadata.time_last_modified = now;
+        }
+    }
+    existing.metadata.time_last_used = now;
+    existing.metadata.times_used += 1;
+    update_internal_address(&tx, &existing, true)?;
+    tx.commit()?;
+    // Reflect the change counter increment made by the update.
+    existing.metadata.sync_change_counter += 1;
+    Ok(existing)
+}
+
 pub fn touch(conn: &Connection, guid: &Guid) -> Result<()> {
     let tx = conn.unchecked_transaction()?;
     let now_ms = Timestamp::now();
@@ -687,4 +904,203 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_normalize_field() {
+        assert_eq!(
+            normalize_field("  Jane \t Doe ", FieldKind::Text),
+            "jane doe"
+        );
+        assert_eq!(
+            normalize_field("Apt. 3,\n1 Main St.", FieldKind::Street),
+            "apt 3 1 main st"
+        );
+        assert_eq!(
+            normalize_field("+1 (555) 123-4567", FieldKind::Tel),
+            "+15551234567"
+        );
+        assert_eq!(
+            normalize_field("555.123.4567", FieldKind::Tel),
+            "5551234567"
+        );
+        assert_eq!(
+            normalize_field("sw1a 1aa", FieldKind::PostalCode),
+            "SW1A1AA"
+        );
+        assert_eq!(
+            normalize_field("12345-6789", FieldKind::PostalCode),
+            "123456789"
+        );
+    }
+
+    #[test]
+    fn test_add_or_merge_address() -> Result<()> {
+        let db = new_mem_db();
+        let original = add_address(
+            &db,
+            UpdatableAddressFields {
+                given_name: "Jane".to_string(),
+                family_name: "Doe".to_string(),
+                street_address: "123 Main St.\nApt 4".to_string(),
+                postal_code: "98101".to_string(),
+                country: "US".to_string(),
+                tel: "(555) 123-4567".to_string(),
+                ..UpdatableAddressFields::default()
+            },
+        )?;
+
+        // The same address with different formatting, plus an email.
+        let similar = UpdatableAddressFields {
+            given_name: "jane".to_string(),
+            family_name: "DOE".to_string(),
+            street_address: "123 Main St, Apt 4".to_string(),
+            postal_code: "98101".to_string(),
+            country: "US".to_string(),
+            tel: "555-123-4567".to_string(),
+            email: "jane@example.com".to_string(),
+            ..UpdatableAddressFields::default()
+        };
+        assert_eq!(
+            find_duplicate_address(&db, &similar)?.map(|a| a.guid),
+            Some(original.guid.clone())
+        );
+        let merged = add_or_merge_address(&db, similar)?;
+        assert_eq!(merged.guid, original.guid);
+        // Existing values are kept, and missing ones filled in.
+        assert_eq!(merged.given_name, "Jane");
+        assert_eq!(merged.email, "jane@example.com");
+        assert_eq!(merged.metadata.times_used, 1);
+        let stored = get_address(&db, &original.guid)?;
+        assert_eq!(stored.email, "jane@example.com");
+        assert_eq!(stored.metadata.times_used, 1);
+        assert_eq!(stored.metadata.sync_change_counter, 1);
+
+        // A different street means a different address.
+        let different = UpdatableAddressFields {
+            given_name: "Jane".to_string(),
+            street_address: "125 Main St".to_string(),
+            ..UpdatableAddressFields::default()
+        };
+        assert!(find_duplicate_address(&db, &different)?.is_none());
+        let added = add_or_merge_address(&db, different)?;
+        assert_ne!(added.guid, original.guid);
+        assert_eq!(get_all_addresses(&db)?.len(), 2);
+
+        // Nothing in common isn't a duplicate.
+        assert!(find_duplicate_address(&db, &UpdatableAddressFields::default())?.is_none());
+
+        // Synced addresses aren't stored normalized, but still match.
+        let synced = InternalAddress {
+            guid: Guid::random(),
+            given_name: "Sam".to_string(),
+            address_level1: "new york".to_string(),
+            postal_code: "100011234".to_string(),
+            country: "US".to_string(),
+            ..Default::default()
+        };
+        let tx = db.unchecked_transaction()?;
+        add_internal_address(&tx, &synced)?;
+        tx.commit()?;
+        let found = find_duplicate_address(
+            &db,
+            &UpdatableAddressFields {
+                given_name: "Sam".to_string(),
+                address_level1: "NY".to_string(),
+                postal_code: "10001-1234".to_string(),
+                country: "US".to_string(),
+                ..UpdatableAddressFields::default()
+            },
+        )?;
+        assert_eq!(
+            found.map(|a| a.address_level1),
+            Some("new york".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_suggest_addresses() -> Result<()> {
+        let db = new_mem_db();
+        let add = |given_name: &str, street_address: &str| {
+            add_address(
+                &db,
+                UpdatableAddressFields {
+                    given_name: given_name.to_string(),
+                    street_address: street_address.to_string(),
+                    country: "US".to_string(),
+                    ..Default::default()
+                },
+            )
+        };
+        let jane = add("Jane", "123 Main St")?;
+        let janet = add("Janet", "1 Elm St")?;
+        let john = add("John", "")?;
+        let set_usage = |guid: &Guid, times_used: i64, time_last_used: u64| {
+            db.execute(
+                "UPDATE addresses_data
+                SET times_used = :times_used, time_last_used = :time_last_used
+                WHERE guid = :guid",
+                rusqlite::named_params! {
+                    ":times_used": times_used,
+                    ":time_last_used": time_last_used,
+                    ":guid": guid,
+                },
+            )
+        };
+        let day = 24 * 60 * 60 * 1000;
+        let now = Timestamp(1000 * day);
+        // Jane was used more, but much longer ago, than Janet.
+        set_usage(&jane.guid, 10, 500 * day)?;
+        set_usage(&janet.guid, 3, 999 * day)?;
+        set_usage(&john.guid, 1, 999 * day)?;
+
+        let guids = |addresses: Vec<InternalAddress>| {
+            addresses.into_iter().map(|a| a.guid).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            guids(suggest_addresses(
+                &db,
+                AddressField::GivenName,
+                "ja",
+                10,
+                now
+            )?),
+            vec![janet.guid.clone(), jane.guid.clone()]
+        );
+        assert_eq!(
+            guids(suggest_addresses(
+                &db,
+                AddressField::GivenName,
+                " JANE",
+                1,
+                now
+            )?),
+            vec![janet.guid.clone()]
+        );
+        assert_eq!(
+            guids(suggest_addresses(
+                &db,
+                AddressField::GivenName,
+                "",
+                10,
+                now
+            )?),
+            vec![janet.guid.clone(), john.guid.clone(), jane.guid.clone()]
+        );
+        // Addresses with no value for the field aren't suggested.
+        assert_eq!(
+            guids(suggest_addresses(
+                &db,
+                AddressField::StreetAddress,
+                "",
+                10,
+                now
+            )?),
+            vec![janet.guid.clone(), jane.guid.clone()]
+        );
+        // `%` and `_` have no special meaning.
+        assert!(suggest_addresses(&db, AddressField::GivenName, "j%", 10, now)?.is_empty());
+        assert!(suggest_addresses(&db, AddressField::Email, "", 10, now)?.is_empty());
+        Ok(())
+    }
 }
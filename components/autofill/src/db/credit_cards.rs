@@ -5,10 +5,12 @@
 
 use crate::db::{
     models::{
-        credit_card::{InternalCreditCard, UpdatableCreditCardFields},
+        credit_card::{CreditCardReveal, InternalCreditCard, UpdatableCreditCardFields},
         Metadata,
     },
+    prefix_match_sql,
     schema::{CREDIT_CARD_COMMON_COLS, CREDIT_CARD_COMMON_VALS},
+    FRECENCY_SQL,
 };
 use crate::error::*;
 
@@ -129,7 +131,8 @@ pub fn update_credit_card(
             cc_exp_year                 = :cc_exp_year,
             cc_type                     = :cc_type,
             time_last_modified          = :time_last_modified,
-            sync_change_counter         = sync_change_counter + 1
+            sync_change_counter         = sync_change_counter + 1,
+            hidden                      = 0
         WHERE guid                      = :guid",
         rusqlite::named_params! {
             ":cc_name": credit_card.cc_name,
@@ -219,7 +222,8 @@ pub fn touch(conn: &Connection, guid: &Guid) -> Result<()> {
         "UPDATE credit_cards_data
         SET time_last_used              = :time_last_used,
             times_used                  = times_used + 1,
-            sync_change_counter         = sync_change_counter + 1
+            sync_change_counter         = sync_change_counter + 1,
+            hidden                      = 0
         WHERE guid                      = :guid",
         rusqlite::named_params! {
             ":time_last_used": now_ms,
@@ -231,6 +235,183 @@ pub fn touch(conn: &Connection, guid: &Guid) -> Result<()> {
     Ok(())
 }
 
+// SQL for the expiry of a card, and the month of the `:now` parameter, as a
+// count of months, so that December 2023 and January 2024 are one apart. Cards
+// without a valid expiry are never considered expiring or expired.
+const HAS_VALID_EXPIRY: &str = "(cc_exp_month BETWEEN 1 AND 12 AND cc_exp_year > 0)";
+const EXPIRY_MONTH: &str = "(cc_exp_year * 12 + cc_exp_month - 1)";
+const NOW_MONTH: &str = "(CAST(strftime('%Y', :now / 1000, 'unixepoch') AS INTEGER) * 12
+    + CAST(strftime('%m', :now / 1000, 'unixepoch') AS INTEGER) - 1)";
+// The first moment after the card expired, in milliseconds.
+const EXPIRED_AT: &str = "(CAST(strftime('%s', printf('%04d-%02d-01', cc_exp_year, cc_exp_month), '+1 month') AS INTEGER) * 1000)";
+
+/// Cards, other than hidden ones, which haven't expired but will have by the
+/// end of the month `within_months` months after `now` - so 0 means cards
+/// expiring this month.
+pub(crate) fn get_expiring_credit_cards(
+    conn: &Connection,
+    within_months: u32,
+    now: Timestamp,
+) -> Result<Vec<InternalCreditCard>> {
+    let sql = format!(
+        "SELECT
+            {common_cols},
+            sync_change_counter
+        FROM credit_cards_data
+        WHERE NOT hidden
+            AND {HAS_VALID_EXPIRY}
+            AND {EXPIRY_MONTH} BETWEEN {NOW_MONTH} AND {NOW_MONTH} + :within_months
+        ORDER BY cc_exp_year, cc_exp_month",
+        common_cols = CREDIT_CARD_COMMON_COLS,
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let credit_cards = stmt
+        .query_map(
+            rusqlite::named_params! {
+                ":now": now,
+                ":within_months": within_months,
+            },
+            InternalCreditCard::from_row,
+        )?
+        .collect::<std::result::Result<Vec<InternalCreditCard>, _>>()?;
+    Ok(credit_cards)
+}
+
+/// Cards whose name starts with `prefix`, best first, ranked by how frequently
+/// and recently they've been used. Hidden and expired cards are skipped.
+pub(crate) fn suggest_credit_cards(
+    conn: &Connection,
+    prefix: &str,
+    limit: u32,
+    now: Timestamp,
+) -> Result<Vec<InternalCreditCard>> {
+    let sql = format!(
+        "SELECT
+            {common_cols},
+            sync_change_counter
+        FROM credit_cards_data
+        WHERE NOT hidden
+            AND NOT ({HAS_VALID_EXPIRY} AND {EXPIRY_MONTH} < {NOW_MONTH})
+            AND {prefix_match}
+        ORDER BY {FRECENCY_SQL} DESC, time_last_used DESC
+        LIMIT :limit",
+        common_cols = CREDIT_CARD_COMMON_COLS,
+        prefix_match = prefix_match_sql("cc_name"),
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let credit_cards = stmt
+        .query_map(
+            rusqlite::named_params! {
+                ":prefix": prefix.trim(),
+                ":limit": limit,
+                ":now": now,
+            },
+            InternalCreditCard::from_row,
+        )?
+        .collect::<std::result::Result<Vec<InternalCreditCard>, _>>()?;
+    Ok(credit_cards)
+}
+
+/// Hides cards which expired more than `expired_months` months before `now`
+/// and haven't been used since they expired, returning how many were hidden.
+/// This is a local-only change, so doesn't bump the change counter. Using or
+/// updating a card un-hides it.
+pub(crate) fn hide_stale_credit_cards(
+    conn: &Connection,
+    expired_months: u32,
+    now: Timestamp,
+) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let count = tx.execute(
+        &format!(
+            "UPDATE credit_cards_data
+            SET hidden = 1
+            WHERE NOT hidden
+                AND {HAS_VALID_EXPIRY}
+                AND {NOW_MONTH} - {EXPIRY_MONTH} > :expired_months
+                AND IFNULL(time_last_used, 0) < {EXPIRED_AT}"
+        ),
+        rusqlite::named_params! {
+            ":now": now,
+            ":expired_months": expired_months,
+        },
+    )?;
+    tx.commit()?;
+    Ok(count)
+}
+
+pub(crate) fn get_hidden_credit_cards(conn: &Connection) -> Result<Vec<InternalCreditCard>> {
+    let sql = format!(
+        "SELECT
+            {common_cols},
+            sync_change_counter
+        FROM credit_cards_data
+        WHERE hidden",
+        common_cols = CREDIT_CARD_COMMON_COLS,
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let credit_cards = stmt
+        .query_map([], InternalCreditCard::from_row)?
+        .collect::<std::result::Result<Vec<InternalCreditCard>, _>>()?;
+    Ok(credit_cards)
+}
+
+/// Deletes all hidden cards, returning how many were deleted. As with
+/// `delete_credit_card()`, tombstones are created for cards which were synced.
+pub(crate) fn delete_hidden_credit_cards(conn: &Connection) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let count = tx.execute("DELETE FROM credit_cards_data WHERE hidden", [])?;
+    tx.commit()?;
+    Ok(count)
+}
+
+// We keep this many entries in the reveal log, dropping the oldest.
+pub(crate) const MAX_CREDIT_CARD_REVEALS: u32 = 500;
+
+/// Record in the audit log that the number of the credit-card was revealed.
+pub(crate) fn record_reveal(conn: &Connection, guid: &Guid) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO credit_cards_reveals (guid, time_revealed)
+        VALUES (:guid, :time_revealed)",
+        rusqlite::named_params! {
+            ":guid": guid,
+            ":time_revealed": Timestamp::now(),
+        },
+    )?;
+    tx.execute(
+        "DELETE FROM credit_cards_reveals
+        WHERE id NOT IN (
+            SELECT id FROM credit_cards_reveals
+            ORDER BY time_revealed DESC, id DESC
+            LIMIT :max_reveals
+        )",
+        rusqlite::named_params! {
+            ":max_reveals": MAX_CREDIT_CARD_REVEALS,
+        },
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// The reveal log, most recent first.
+pub(crate) fn get_reveals(conn: &Connection) -> Result<Vec<CreditCardReveal>> {
+    let mut stmt = conn.prepare(
+        "SELECT guid, time_revealed
+        FROM credit_cards_reveals
+        ORDER BY time_revealed DESC, id DESC",
+    )?;
+    let reveals = stmt
+        .query_map([], |row| {
+            Ok(CreditCardReveal {
+                guid: row.get("guid")?,
+                time_revealed: row.get("time_revealed")?,
+            })
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(reveals)
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
@@ -707,4 +888,188 @@ pub(crate) mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_credit_card_reveals() -> Result<()> {
+        let db = new_mem_db();
+        let guid_a = Guid::random();
+        let guid_b = Guid::random();
+        record_reveal(&db, &guid_a)?;
+        record_reveal(&db, &guid_b)?;
+        let reveals = get_reveals(&db)?;
+        assert_eq!(reveals.len(), 2);
+        assert_eq!(reveals[0].guid, guid_b.as_str());
+        assert_eq!(reveals[1].guid, guid_a.as_str());
+        assert_ne!(reveals[0].time_revealed, 0);
+
+        // The log is bounded.
+        for _ in 0..MAX_CREDIT_CARD_REVEALS {
+            record_reveal(&db, &guid_a)?;
+        }
+        let reveals = get_reveals(&db)?;
+        assert_eq!(reveals.len(), MAX_CREDIT_CARD_REVEALS as usize);
+        assert!(reveals.iter().all(|r| r.guid == guid_a.as_str()));
+        Ok(())
+    }
+
+    fn add_card_expiring(db: &Connection, month: i64, year: i64) -> Result<InternalCreditCard> {
+        add_credit_card(
+            db,
+            UpdatableCreditCardFields {
+                cc_name: "jane doe".to_string(),
+                cc_number_enc: "XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX".to_string(),
+                cc_number_last_4: "1234".to_string(),
+                cc_exp_month: month,
+                cc_exp_year: year,
+                cc_type: "visa".to_string(),
+            },
+        )
+    }
+
+    fn guids(cards: Vec<InternalCreditCard>) -> Vec<Guid> {
+        cards.into_iter().map(|card| card.guid).collect()
+    }
+
+    #[test]
+    fn test_get_expiring_credit_cards() -> Result<()> {
+        let db = new_mem_db();
+        // 2023-11-15T00:00:00Z
+        let now = Timestamp(1_700_006_400_000);
+        let expired = add_card_expiring(&db, 10, 2023)?;
+        let this_month = add_card_expiring(&db, 11, 2023)?;
+        let next_year = add_card_expiring(&db, 1, 2024)?;
+        let far_future = add_card_expiring(&db, 6, 2030)?;
+        add_card_expiring(&db, 0, 0)?;
+
+        assert_eq!(
+            guids(get_expiring_credit_cards(&db, 0, now)?),
+            vec![this_month.guid.clone()]
+        );
+        assert_eq!(
+            guids(get_expiring_credit_cards(&db, 2, now)?),
+            vec![this_month.guid.clone(), next_year.guid.clone()]
+        );
+        let all_expiring = guids(get_expiring_credit_cards(&db, 1000, now)?);
+        assert_eq!(all_expiring.len(), 3);
+        assert!(all_expiring.contains(&far_future.guid));
+        assert!(!all_expiring.contains(&expired.guid));
+        Ok(())
+    }
+
+    #[test]
+    fn test_hide_and_delete_stale_credit_cards() -> Result<()> {
+        let db = new_mem_db();
+        // 2023-11-15T00:00:00Z
+        let now = Timestamp(1_700_006_400_000);
+        let stale = add_card_expiring(&db, 3, 2022)?;
+        let recently_expired = add_card_expiring(&db, 9, 2023)?;
+        let used_since_expiry = add_card_expiring(&db, 1, 2022)?;
+        let valid = add_card_expiring(&db, 1, 2026)?;
+        // Used in 2023, after it expired.
+        db.execute(
+            "UPDATE credit_cards_data SET time_last_used = 1672531200000 WHERE guid = :guid",
+            rusqlite::named_params! { ":guid": used_since_expiry.guid },
+        )?;
+
+        assert_eq!(hide_stale_credit_cards(&db, 6, now)?, 1);
+        assert_eq!(
+            guids(get_hidden_credit_cards(&db)?),
+            vec![stale.guid.clone()]
+        );
+        // Hidden cards are still returned by `get_all_credit_cards()`.
+        assert_eq!(get_all_credit_cards(&db)?.len(), 4);
+        // A shorter cutoff catches the recently expired card too.
+        assert_eq!(hide_stale_credit_cards(&db, 1, now)?, 1);
+        assert_eq!(get_hidden_credit_cards(&db)?.len(), 2);
+
+        // Using a card un-hides it, without hiding it again.
+        touch(&db, &recently_expired.guid)?;
+        assert_eq!(hide_stale_credit_cards(&db, 1, now)?, 0);
+        assert_eq!(
+            guids(get_hidden_credit_cards(&db)?),
+            vec![stale.guid.clone()]
+        );
+
+        // Deleting goes through the normal tombstone path.
+        test_insert_mirror_record(
+            &db,
+            get_credit_card(&db, &stale.guid)?.into_test_incoming_bso(
+                &EncryptorDecryptor::new_with_random_key()?,
+                Default::default(),
+            ),
+        );
+        assert_eq!(delete_hidden_credit_cards(&db)?, 1);
+        assert!(get_hidden_credit_cards(&db)?.is_empty());
+        assert_eq!(guids(get_all_credit_cards(&db)?).len(), 3,);
+        assert!(get_credit_card(&db, &valid.guid).is_ok());
+        let tombstone_exists: bool = db.query_row(
+            "SELECT EXISTS (SELECT 1 FROM credit_cards_tombstones WHERE guid = :guid)",
+            [&stale.guid],
+            |row| row.get(0),
+        )?;
+        assert!(tombstone_exists);
+        Ok(())
+    }
+
+    #[test]
+    fn test_suggest_credit_cards() -> Result<()> {
+        let db = new_mem_db();
+        // 2023-11-15T00:00:00Z
+        let now = Timestamp(1_700_006_400_000);
+        let valid = add_card_expiring(&db, 1, 2026)?;
+        let rarely_used = add_card_expiring(&db, 11, 2023)?;
+        let no_expiry = add_card_expiring(&db, 0, 0)?;
+        let expired = add_card_expiring(&db, 10, 2023)?;
+        let other_name = add_credit_card(
+            &db,
+            UpdatableCreditCardFields {
+                cc_name: "john doe".to_string(),
+                cc_number_enc: "XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX".to_string(),
+                cc_number_last_4: "5678".to_string(),
+                cc_exp_month: 1,
+                cc_exp_year: 2026,
+                cc_type: "mastercard".to_string(),
+            },
+        )?;
+        for (card, times_used) in [(&valid, 5), (&no_expiry, 2), (&expired, 10)] {
+            db.execute(
+                "UPDATE credit_cards_data
+                SET times_used = :times_used, time_last_used = :now
+                WHERE guid = :guid",
+                rusqlite::named_params! {
+                    ":times_used": times_used,
+                    ":now": now,
+                    ":guid": card.guid,
+                },
+            )?;
+        }
+
+        assert_eq!(
+            guids(suggest_credit_cards(&db, "JANE", 10, now)?),
+            vec![
+                valid.guid.clone(),
+                no_expiry.guid.clone(),
+                rarely_used.guid.clone()
+            ]
+        );
+        assert_eq!(
+            guids(suggest_credit_cards(&db, "", 2, now)?),
+            vec![valid.guid.clone(), no_expiry.guid.clone()]
+        );
+        assert_eq!(
+            guids(suggest_credit_cards(&db, "john", 10, now)?),
+            vec![other_name.guid]
+        );
+
+        // Hidden cards aren't suggested.
+        db.execute(
+            "UPDATE credit_cards_data SET hidden = 1 WHERE guid = :guid",
+            [&valid.guid],
+        )?;
+        assert_eq!(
+            guids(suggest_credit_cards(&db, "jane", 10, now)?),
+            vec![no_expiry.guid, rarely_used.guid]
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,318 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/.
+*/
+
+use crate::db::{
+    models::{
+        iban::{InternalIban, UpdatableIbanFields},
+        Metadata,
+    },
+    schema::{IBAN_COMMON_COLS, IBAN_COMMON_VALS},
+};
+use crate::error::*;
+
+use rusqlite::{Connection, Transaction};
+use sync_guid::Guid;
+use types::Timestamp;
+
+pub(crate) fn add_iban(
+    conn: &Connection,
+    new_iban_fields: UpdatableIbanFields,
+) -> Result<InternalIban> {
+    let now = Timestamp::now();
+
+    // We return an InternalIban, so set it up first, including the missing
+    // fields, before we insert it.
+    let iban = InternalIban {
+        guid: Guid::random(),
+        iban_name: new_iban_fields.iban_name,
+        iban_enc: new_iban_fields.iban_enc,
+        iban_last_4: new_iban_fields.iban_last_4,
+        metadata: Metadata {
+            time_created: now,
+            time_last_modified: now,
+            ..Default::default()
+        },
+    };
+
+    let tx = conn.unchecked_transaction()?;
+    add_internal_iban(&tx, &iban)?;
+    tx.commit()?;
+    Ok(iban)
+}
+
+pub(crate) fn add_internal_iban(tx: &Transaction<'_>, iban: &InternalIban) -> Result<()> {
+    tx.execute(
+        &format!(
+            "INSERT INTO ibans_data (
+                {common_cols},
+                sync_change_counter
+            ) VALUES (
+                {common_vals},
+                :sync_change_counter
+            )",
+            common_cols = IBAN_COMMON_COLS,
+            common_vals = IBAN_COMMON_VALS,
+        ),
+        rusqlite::named_params! {
+            ":guid": iban.guid,
+            ":iban_name": iban.iban_name,
+            ":iban_enc": iban.iban_enc,
+            ":iban_last_4": iban.iban_last_4,
+            ":time_created": iban.metadata.time_created,
+            ":time_last_used": iban.metadata.time_last_used,
+            ":time_last_modified": iban.metadata.time_last_modified,
+            ":times_used": iban.metadata.times_used,
+            ":sync_change_counter": iban.metadata.sync_change_counter,
+        },
+    )?;
+    Ok(())
+}
+
+pub(crate) fn get_iban(conn: &Connection, guid: &Guid) -> Result<InternalIban> {
+    let sql = format!(
+        "SELECT
+            {common_cols},
+            sync_change_counter
+        FROM ibans_data
+        WHERE guid = :guid",
+        common_cols = IBAN_COMMON_COLS
+    );
+
+    conn.query_row(&sql, [guid], InternalIban::from_row)
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Error::NoSuchRecord(guid.to_string()),
+            e => e.into(),
+        })
+}
+
+pub(crate) fn get_all_ibans(conn: &Connection) -> Result<Vec<InternalIban>> {
+    let sql = format!(
+        "SELECT
+            {common_cols},
+            sync_change_counter
+        FROM ibans_data",
+        common_cols = IBAN_COMMON_COLS
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let ibans = stmt
+        .query_map([], InternalIban::from_row)?
+        .collect::<std::result::Result<Vec<InternalIban>, _>>()?;
+    Ok(ibans)
+}
+
+pub fn update_iban(conn: &Connection, guid: &Guid, iban: &UpdatableIbanFields) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "UPDATE ibans_data
+        SET iban_name                   = :iban_name,
+            iban_enc                    = :iban_enc,
+            iban_last_4                 = :iban_last_4,
+            time_last_modified          = :time_last_modified,
+            sync_change_counter         = sync_change_counter + 1
+        WHERE guid                      = :guid",
+        rusqlite::named_params! {
+            ":iban_name": iban.iban_name,
+            ":iban_enc": iban.iban_enc,
+            ":iban_last_4": iban.iban_last_4,
+            ":time_last_modified": Timestamp::now(),
+            ":guid": guid,
+        },
+    )?;
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Updates all fields including metadata - although the change counter gets
+/// slightly special treatment (eg, when called by Sync we don't want the
+/// change counter incremented).
+pub(crate) fn update_internal_iban(
+    tx: &Transaction<'_>,
+    iban: &InternalIban,
+    flag_as_changed: bool,
+) -> Result<()> {
+    let change_counter_increment = flag_as_changed as u32; // will be 1 or 0
+    tx.execute(
+        "UPDATE ibans_data
+        SET iban_name                   = :iban_name,
+            iban_enc                    = :iban_enc,
+            iban_last_4                 = :iban_last_4,
+            time_created                = :time_created,
+            time_last_used              = :time_last_used,
+            time_last_modified          = :time_last_modified,
+            times_used                  = :times_used,
+            sync_change_counter         = sync_change_counter + :change_incr
+        WHERE guid                      = :guid",
+        rusqlite::named_params! {
+            ":iban_name": iban.iban_name,
+            ":iban_enc": iban.iban_enc,
+            ":iban_last_4": iban.iban_last_4,
+            ":time_created": iban.metadata.time_created,
+            ":time_last_used": iban.metadata.time_last_used,
+            ":time_last_modified": iban.metadata.time_last_modified,
+            ":times_used": iban.metadata.times_used,
+            ":change_incr": change_counter_increment,
+            ":guid": iban.guid,
+        },
+    )?;
+    Ok(())
+}
+
+pub fn delete_iban(conn: &Connection, guid: &Guid) -> Result<bool> {
+    let tx = conn.unchecked_transaction()?;
+
+    // execute returns how many rows were affected.
+    let exists = tx.execute(
+        "DELETE FROM ibans_data
+        WHERE guid = :guid",
+        rusqlite::named_params! {
+            ":guid": guid.as_str(),
+        },
+    )? != 0;
+
+    tx.commit()?;
+    Ok(exists)
+}
+
+pub fn scrub_encrypted_iban_data(conn: &Connection) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("UPDATE ibans_data SET iban_enc = ''", [])?;
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn touch(conn: &Connection, guid: &Guid) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    let now_ms = Timestamp::now();
+
+    tx.execute(
+        "UPDATE ibans_data
+        SET time_last_used              = :time_last_used,
+            times_used                  = times_used + 1,
+            sync_change_counter         = sync_change_counter + 1
+        WHERE guid                      = :guid",
+        rusqlite::named_params! {
+            ":time_last_used": now_ms,
+            ":guid": guid.as_str(),
+        },
+    )?;
+
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::db::test::new_mem_db;
+    use crate::encryption::EncryptorDecryptor;
+    use sync15::bso::IncomingBso;
+
+    pub(crate) fn test_insert_mirror_record(conn: &Connection, bso: IncomingBso) {
+        // Like the credit-card version, this stores the raw payload with a
+        // cleartext IBAN rather than encrypting it.
+        conn.execute(
+            "INSERT INTO ibans_mirror (guid, payload)
+             VALUES (:guid, :payload)",
+            rusqlite::named_params! {
+                ":guid": &bso.envelope.id,
+                ":payload": &bso.payload,
+            },
+        )
+        .expect("should insert");
+    }
+
+    fn test_fields(encdec: &EncryptorDecryptor, name: &str) -> Result<UpdatableIbanFields> {
+        Ok(UpdatableIbanFields {
+            iban_name: name.to_string(),
+            iban_enc: encdec.encrypt("GB82WEST12345698765432", "iban")?,
+            iban_last_4: "5432".to_string(),
+        })
+    }
+
+    #[test]
+    fn test_iban_create_and_read() -> Result<()> {
+        let db = new_mem_db();
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+
+        let saved_iban = add_iban(&db, test_fields(&encdec, "joint account")?)?;
+
+        // check that the add function populated the guid field and times
+        assert_ne!(Guid::default(), saved_iban.guid);
+        assert_ne!(0, saved_iban.metadata.time_created.as_millis());
+        assert_ne!(0, saved_iban.metadata.time_last_modified.as_millis());
+        assert_eq!(0, saved_iban.metadata.sync_change_counter);
+
+        let retrieved_iban = get_iban(&db, &saved_iban.guid)?;
+        assert_eq!(saved_iban.guid, retrieved_iban.guid);
+        assert_eq!(retrieved_iban.iban_name, "joint account");
+        assert_eq!(saved_iban.iban_enc, retrieved_iban.iban_enc);
+        assert_eq!(retrieved_iban.iban_last_4, "5432");
+
+        add_iban(&db, test_fields(&encdec, "savings")?)?;
+        assert_eq!(get_all_ibans(&db)?.len(), 2);
+
+        assert!(delete_iban(&db, &saved_iban.guid)?);
+        assert!(!delete_iban(&db, &saved_iban.guid)?);
+        assert_eq!(
+            get_iban(&db, &saved_iban.guid).unwrap_err().to_string(),
+            Error::NoSuchRecord(saved_iban.guid.to_string()).to_string()
+        );
+        assert_eq!(get_all_ibans(&db)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_iban_update_and_touch() -> Result<()> {
+        let db = new_mem_db();
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+        let saved_iban = add_iban(&db, test_fields(&encdec, "joint account")?)?;
+
+        update_iban(&db, &saved_iban.guid, &test_fields(&encdec, "household")?)?;
+        let updated_iban = get_iban(&db, &saved_iban.guid)?;
+        assert_eq!(updated_iban.iban_name, "household");
+        assert_eq!(updated_iban.metadata.sync_change_counter, 1);
+
+        touch(&db, &saved_iban.guid)?;
+        let touched_iban = get_iban(&db, &saved_iban.guid)?;
+        assert_eq!(touched_iban.metadata.times_used, 1);
+        assert_eq!(touched_iban.metadata.sync_change_counter, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_iban_delete_creates_tombstone() -> Result<()> {
+        let db = new_mem_db();
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+        let saved_iban = add_iban(&db, test_fields(&encdec, "joint account")?)?;
+        let guid = saved_iban.guid.clone();
+        test_insert_mirror_record(
+            &db,
+            saved_iban.into_test_incoming_bso(&encdec, Default::default()),
+        );
+
+        assert!(delete_iban(&db, &guid)?);
+        let tombstone_exists: bool = db.query_row(
+            "SELECT EXISTS (SELECT 1 FROM ibans_tombstones WHERE guid = :guid)",
+            [&guid],
+            |row| row.get(0),
+        )?;
+        assert!(tombstone_exists);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scrub_encrypted_iban_data() -> Result<()> {
+        let db = new_mem_db();
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+        let saved_iban = add_iban(&db, test_fields(&encdec, "joint account")?)?;
+
+        scrub_encrypted_iban_data(&db)?;
+        let retrieved_iban = get_iban(&db, &saved_iban.guid)?;
+        assert!(retrieved_iban.has_scrubbed_data());
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@
 
 pub mod addresses;
 pub mod credit_cards;
+pub mod ibans;
 pub mod models;
 pub mod schema;
 pub mod store;
@@ -20,6 +21,20 @@ use std::{
 };
 use url::Url;
 
+// SQL for a "frecency"-like score used to rank suggestions, for tables with
+// the usual metadata columns. It's how often a record has been used, decayed by
+// how long ago it was last used (or created, if never used), so a record last
+// used 30 days ago scores half what it would if used today. Needs a `:now`
+// parameter.
+pub(crate) const FRECENCY_SQL: &str = "((times_used + 1)
+    / (1.0 + MAX(0, :now - IFNULL(NULLIF(time_last_used, 0), time_created)) / 2592000000.0))";
+
+// SQL for whether the column named `{column}` starts with the `:prefix`
+// parameter, ignoring ASCII case. Unlike `LIKE`, the prefix needs no escaping.
+pub(crate) fn prefix_match_sql(column: &str) -> String {
+    format!("substr(lower({column}), 1, length(:prefix)) = lower(:prefix)")
+}
+
 pub struct AutofillDb {
     pub writer: Connection,
     interrupt_handle: Arc<SqlInterruptHandle>,
@@ -29,6 +29,42 @@ pub struct UpdatableAddressFields {
     pub email: String,
 }
 
+/// The address fields which `Store::suggest_addresses()` can match on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressField {
+    GivenName,
+    AdditionalName,
+    FamilyName,
+    Organization,
+    StreetAddress,
+    AddressLevel3,
+    AddressLevel2,
+    AddressLevel1,
+    PostalCode,
+    Country,
+    Tel,
+    Email,
+}
+
+impl AddressField {
+    pub(crate) fn column(self) -> &'static str {
+        match self {
+            AddressField::GivenName => "given_name",
+            AddressField::AdditionalName => "additional_name",
+            AddressField::FamilyName => "family_name",
+            AddressField::Organization => "organization",
+            AddressField::StreetAddress => "street_address",
+            AddressField::AddressLevel3 => "address_level3",
+            AddressField::AddressLevel2 => "address_level2",
+            AddressField::AddressLevel1 => "address_level1",
+            AddressField::PostalCode => "postal_code",
+            AddressField::Country => "country",
+            AddressField::Tel => "tel",
+            AddressField::Email => "email",
+        }
+    }
+}
+
 // "Address" is what we return to consumers and has most of the metadata.
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Default)]
 pub struct Address {
@@ -104,3 +104,11 @@ impl InternalCreditCard {
         self.cc_number_enc.is_empty()
     }
 }
+
+/// A record of a credit-card number being revealed by
+/// `Store::reveal_credit_card_number()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreditCardReveal {
+    pub guid: String,
+    pub time_revealed: i64,
+}
@@ -0,0 +1,84 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/.
+*/
+
+use super::Metadata;
+use rusqlite::Row;
+use sync_guid::Guid;
+
+#[derive(Debug, Clone, Default)]
+pub struct UpdatableIbanFields {
+    pub iban_name: String,
+    pub iban_enc: String,
+    pub iban_last_4: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Iban {
+    pub guid: String,
+    pub iban_name: String,
+    pub iban_enc: String,
+    pub iban_last_4: String,
+
+    // The metadata
+    pub time_created: i64,
+    pub time_last_used: Option<i64>,
+    pub time_last_modified: i64,
+    pub times_used: i64,
+}
+
+// This is used to "externalize" an IBAN, suitable for handing back to
+// consumers.
+impl From<InternalIban> for Iban {
+    fn from(iban: InternalIban) -> Self {
+        Iban {
+            guid: iban.guid.to_string(),
+            iban_name: iban.iban_name,
+            iban_enc: iban.iban_enc,
+            iban_last_4: iban.iban_last_4,
+            // note we can't use u64 in uniffi
+            time_created: u64::from(iban.metadata.time_created) as i64,
+            time_last_used: if iban.metadata.time_last_used.0 == 0 {
+                None
+            } else {
+                Some(iban.metadata.time_last_used.0 as i64)
+            },
+            time_last_modified: u64::from(iban.metadata.time_last_modified) as i64,
+            times_used: iban.metadata.times_used,
+        }
+    }
+}
+
+// NOTE: No `PartialEq` here because the same IBAN will encrypt to a different
+// value each time it is encrypted, making it meaningless to compare.
+#[derive(Debug, Clone, Default)]
+pub struct InternalIban {
+    pub guid: Guid,
+    pub iban_name: String,
+    pub iban_enc: String,
+    pub iban_last_4: String,
+    pub metadata: Metadata,
+}
+
+impl InternalIban {
+    pub fn from_row(row: &Row<'_>) -> Result<InternalIban, rusqlite::Error> {
+        Ok(Self {
+            guid: Guid::from_string(row.get("guid")?),
+            iban_name: row.get("iban_name")?,
+            iban_enc: row.get("iban_enc")?,
+            iban_last_4: row.get("iban_last_4")?,
+            metadata: Metadata {
+                time_created: row.get("time_created")?,
+                time_last_used: row.get("time_last_used")?,
+                time_last_modified: row.get("time_last_modified")?,
+                times_used: row.get("times_used")?,
+                sync_change_counter: row.get("sync_change_counter")?,
+            },
+        })
+    }
+
+    pub fn has_scrubbed_data(&self) -> bool {
+        self.iban_enc.is_empty()
+    }
+}
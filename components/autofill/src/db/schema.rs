@@ -70,6 +70,26 @@ pub const CREDIT_CARD_COMMON_VALS: &str = "
     :time_last_modified,
     :times_used";
 
+pub const IBAN_COMMON_COLS: &str = "
+    guid,
+    iban_name,
+    iban_enc,
+    iban_last_4,
+    time_created,
+    time_last_used,
+    time_last_modified,
+    times_used";
+
+pub const IBAN_COMMON_VALS: &str = "
+    :guid,
+    :iban_name,
+    :iban_enc,
+    :iban_last_4,
+    :time_created,
+    :time_last_used,
+    :time_last_modified,
+    :times_used";
+
 const CREATE_SHARED_SCHEMA_SQL: &str = include_str!("../../sql/create_shared_schema.sql");
 const CREATE_SHARED_TRIGGERS_SQL: &str = include_str!("../../sql/create_shared_triggers.sql");
 const CREATE_SYNC_TEMP_TABLES_SQL: &str = include_str!("../../sql/create_sync_temp_tables.sql");
@@ -78,7 +98,7 @@ pub struct AutofillConnectionInitializer;
 
 impl ConnectionInitializer for AutofillConnectionInitializer {
     const NAME: &'static str = "autofill db";
-    const END_VERSION: u32 = 2;
+    const END_VERSION: u32 = 5;
 
     fn prepare(&self, conn: &Connection, _db_empty: bool) -> Result<()> {
         define_functions(conn)?;
@@ -107,6 +127,9 @@ impl ConnectionInitializer for AutofillConnectionInitializer {
             // upgrade_from_v0() for more details.
             0 => upgrade_from_v0(db),
             1 => upgrade_from_v1(db),
+            2 => upgrade_from_v2(db),
+            3 => upgrade_from_v3(db),
+            4 => upgrade_from_v4(db),
             _ => Err(Error::IncompatibleVersion(version)),
         }
     }
@@ -193,6 +216,57 @@ fn upgrade_from_v1(db: &Connection) -> Result<()> {
     Ok(())
 }
 
+fn upgrade_from_v2(db: &Connection) -> Result<()> {
+    // Add the tables for IBANs. Their triggers are TEMP triggers, so are
+    // created by `finish()`.
+    db.execute_batch(
+        "
+        CREATE TABLE ibans_data (
+            guid                TEXT NOT NULL PRIMARY KEY CHECK(length(guid) != 0),
+            iban_name           TEXT NOT NULL,
+            iban_enc            TEXT NOT NULL CHECK(length(iban_enc) > 34 OR iban_enc == ''),
+            iban_last_4         TEXT NOT NULL CHECK(length(iban_last_4) <= 4),
+            time_created        INTEGER NOT NULL,
+            time_last_used      INTEGER,
+            time_last_modified  INTEGER NOT NULL,
+            times_used          INTEGER NOT NULL,
+            sync_change_counter INTEGER NOT NULL
+        );
+        CREATE TABLE ibans_mirror (
+            guid                TEXT NOT NULL PRIMARY KEY CHECK(length(guid) != 0),
+            payload             TEXT NOT NULL CHECK(length(payload) != 0)
+        );
+        CREATE TABLE ibans_tombstones (
+            guid            TEXT PRIMARY KEY CHECK(length(guid) != 0),
+            time_deleted    INTEGER NOT NULL
+        ) WITHOUT ROWID;
+        ",
+    )?;
+    Ok(())
+}
+
+fn upgrade_from_v3(db: &Connection) -> Result<()> {
+    // Add the audit log of credit-card number reveals.
+    db.execute_batch(
+        "
+        CREATE TABLE credit_cards_reveals (
+            id              INTEGER PRIMARY KEY,
+            guid            TEXT NOT NULL,
+            time_revealed   INTEGER NOT NULL
+        );
+        ",
+    )?;
+    Ok(())
+}
+
+fn upgrade_from_v4(db: &Connection) -> Result<()> {
+    // Add the local-only flag for hidden credit cards.
+    db.execute_batch(
+        "ALTER TABLE credit_cards_data ADD COLUMN hidden INTEGER NOT NULL DEFAULT 0;",
+    )?;
+    Ok(())
+}
+
 pub fn create_empty_sync_temp_tables(db: &Connection) -> Result<()> {
     log::debug!("Initializing sync temp tables");
     db.execute_batch(CREATE_SYNC_TEMP_TABLES_SQL)?;
@@ -299,4 +373,68 @@ mod tests {
         db.execute("UPDATE credit_cards_data SET cc_number_enc='x'", [])
             .expect_err("cc_number_enc should be invalid");
     }
+
+    #[test]
+    fn test_upgrade_version_2() {
+        let db_file = MigratedDatabaseFile::new(AutofillConnectionInitializer, CREATE_V1_DB);
+        db_file.upgrade_to(2);
+        let select_ibans = "SELECT iban_enc FROM ibans_data";
+        db_file
+            .open()
+            .execute_batch(select_ibans)
+            .expect_err("select should fail as the table doesn't exist");
+
+        db_file.upgrade_to(3);
+        let db = db_file.open();
+        db.execute_batch(select_ibans)
+            .expect("select should now work");
+        // and the check constraints should exist.
+        db.execute(
+            "INSERT INTO ibans_data (guid, iban_name, iban_enc, iban_last_4, time_created,
+                time_last_modified, times_used, sync_change_counter)
+             VALUES ('A', 'name', 'DE89370400440532013000', '3000', 0, 0, 0, 0)",
+            [],
+        )
+        .expect_err("unencrypted iban should be invalid");
+    }
+
+    #[test]
+    fn test_upgrade_version_3() {
+        let db_file = MigratedDatabaseFile::new(AutofillConnectionInitializer, CREATE_V1_DB);
+        db_file.upgrade_to(3);
+        let select_reveals = "SELECT guid, time_revealed FROM credit_cards_reveals";
+        db_file
+            .open()
+            .execute_batch(select_reveals)
+            .expect_err("select should fail as the table doesn't exist");
+
+        db_file.upgrade_to(4);
+        db_file
+            .open()
+            .execute_batch(select_reveals)
+            .expect("select should now work");
+    }
+
+    #[test]
+    fn test_upgrade_version_4() {
+        let db_file = MigratedDatabaseFile::new(AutofillConnectionInitializer, CREATE_V1_DB);
+        db_file.upgrade_to(4);
+        let select_hidden = "SELECT hidden FROM credit_cards_data";
+        db_file
+            .open()
+            .execute_batch(select_hidden)
+            .expect_err("select should fail as the column doesn't exist");
+
+        db_file.upgrade_to(5);
+        let db = db_file.open();
+        let hidden: Vec<bool> = db
+            .prepare(select_hidden)
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert!(!hidden.is_empty());
+        assert!(hidden.iter().all(|hidden| !hidden));
+    }
 }
@@ -2,9 +2,11 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::db::models::address::{Address, UpdatableAddressFields};
-use crate::db::models::credit_card::{CreditCard, UpdatableCreditCardFields};
-use crate::db::{addresses, credit_cards, AutofillDb};
+use crate::db::models::address::{Address, AddressField, UpdatableAddressFields};
+use crate::db::models::credit_card::{CreditCard, CreditCardReveal, UpdatableCreditCardFields};
+use crate::db::models::iban::{Iban, UpdatableIbanFields};
+use crate::db::{addresses, credit_cards, ibans, AutofillDb};
+use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
 use error_support::handle_error;
 use rusqlite::{
@@ -14,8 +16,10 @@ use rusqlite::{
 use sql_support::{self, ConnExt};
 use std::path::Path;
 use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
 use sync15::engine::{SyncEngine, SyncEngineId};
 use sync_guid::Guid;
+use types::Timestamp;
 
 // Our "sync manager" will use whatever is stashed here.
 lazy_static::lazy_static! {
@@ -43,9 +47,17 @@ pub fn get_registered_sync_engine(engine_id: &SyncEngineId) -> Option<Box<dyn Sy
     }
 }
 
+// The meta key for how long after a re-authentication credit-card numbers can
+// be revealed, and the default if it's never been set.
+const CREDIT_CARD_REVEAL_WINDOW_KEY: &str = "credit_card_reveal_window_ms";
+const DEFAULT_CREDIT_CARD_REVEAL_WINDOW_MS: i64 = 5 * 60 * 1000;
+
 // This is the type that uniffi exposes.
 pub struct Store {
     pub(crate) db: Mutex<AutofillDb>,
+    // When the app last told us the user re-authenticated. This is only kept
+    // in memory, so the user must always re-authenticate after a restart.
+    last_reauthentication: Mutex<Option<Instant>>,
 }
 
 impl Store {
@@ -53,6 +65,7 @@ impl Store {
     pub fn new(db_path: impl AsRef<Path>) -> ApiResult<Self> {
         Ok(Self {
             db: Mutex::new(AutofillDb::new(db_path)?),
+            last_reauthentication: Mutex::new(None),
         })
     }
 
@@ -61,6 +74,7 @@ impl Store {
     pub fn new_memory() -> Self {
         Self {
             db: Mutex::new(crate::db::test::new_mem_db()),
+            last_reauthentication: Mutex::new(None),
         }
     }
 
@@ -69,6 +83,7 @@ impl Store {
     pub fn new_shared_memory(db_name: &str) -> ApiResult<Self> {
         Ok(Self {
             db: Mutex::new(AutofillDb::new_memory(db_name)?),
+            last_reauthentication: Mutex::new(None),
         })
     }
 
@@ -117,6 +132,152 @@ impl Store {
         credit_cards::touch(&self.db.lock().unwrap().writer, &Guid::new(&guid))
     }
 
+    /// Cards whose name starts with `prefix`, for suggesting in a form, best
+    /// first. Hidden and expired cards aren't included.
+    #[handle_error(Error)]
+    pub fn suggest_credit_cards(&self, prefix: String, limit: u32) -> ApiResult<Vec<CreditCard>> {
+        let credit_cards = credit_cards::suggest_credit_cards(
+            &self.db.lock().unwrap().writer,
+            &prefix,
+            limit,
+            Timestamp::now(),
+        )?
+        .into_iter()
+        .map(|x| x.into())
+        .collect();
+        Ok(credit_cards)
+    }
+
+    /// Cards which haven't expired but will have by the end of the month
+    /// `within_months` months from now - 0 means cards expiring this month.
+    /// Hidden cards aren't included.
+    #[handle_error(Error)]
+    pub fn get_expiring_credit_cards(&self, within_months: u32) -> ApiResult<Vec<CreditCard>> {
+        let credit_cards = credit_cards::get_expiring_credit_cards(
+            &self.db.lock().unwrap().writer,
+            within_months,
+            Timestamp::now(),
+        )?
+        .into_iter()
+        .map(|x| x.into())
+        .collect();
+        Ok(credit_cards)
+    }
+
+    /// Hides cards which expired more than `expired_months` months ago and
+    /// haven't been used since, so they aren't suggested. This is local-only
+    /// and is undone by using or updating the card. Returns how many cards
+    /// were hidden.
+    #[handle_error(Error)]
+    pub fn hide_stale_credit_cards(&self, expired_months: u32) -> ApiResult<u32> {
+        let count = credit_cards::hide_stale_credit_cards(
+            &self.db.lock().unwrap().writer,
+            expired_months,
+            Timestamp::now(),
+        )?;
+        Ok(count as u32)
+    }
+
+    #[handle_error(Error)]
+    pub fn get_hidden_credit_cards(&self) -> ApiResult<Vec<CreditCard>> {
+        let credit_cards = credit_cards::get_hidden_credit_cards(&self.db.lock().unwrap().writer)?
+            .into_iter()
+            .map(|x| x.into())
+            .collect();
+        Ok(credit_cards)
+    }
+
+    /// Permanently deletes the hidden cards, returning how many were deleted.
+    #[handle_error(Error)]
+    pub fn delete_hidden_credit_cards(&self) -> ApiResult<u32> {
+        let count = credit_cards::delete_hidden_credit_cards(&self.db.lock().unwrap().writer)?;
+        Ok(count as u32)
+    }
+
+    /// Called by the app when the user has successfully re-authenticated
+    /// with the OS, allowing `reveal_credit_card_number()` for the reveal
+    /// window.
+    pub fn record_reauthentication(&self) {
+        *self.last_reauthentication.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Sets how long after a re-authentication credit-card numbers can be
+    /// revealed. Zero or negative values require a re-authentication
+    /// immediately before every reveal.
+    #[handle_error(Error)]
+    pub fn set_credit_card_reveal_window(&self, window_ms: i64) -> ApiResult<()> {
+        put_meta(
+            &self.db.lock().unwrap().writer,
+            CREDIT_CARD_REVEAL_WINDOW_KEY,
+            &window_ms.max(0),
+        )
+    }
+
+    /// Decrypts the number of a credit-card for display to the user, and
+    /// records that it was revealed. Fails with `ReauthenticationRequired` if
+    /// `record_reauthentication()` wasn't called within the reveal window.
+    #[handle_error(Error)]
+    pub fn reveal_credit_card_number(&self, guid: String, key: String) -> ApiResult<String> {
+        let db = self.db.lock().unwrap();
+        let window_ms = get_meta::<i64>(&db.writer, CREDIT_CARD_REVEAL_WINDOW_KEY)?
+            .unwrap_or(DEFAULT_CREDIT_CARD_REVEAL_WINDOW_MS);
+        let window = Duration::from_millis(window_ms.max(0) as u64);
+        let reauthenticated = self
+            .last_reauthentication
+            .lock()
+            .unwrap()
+            .map_or(false, |when| when.elapsed() < window);
+        if !reauthenticated {
+            return Err(Error::ReauthenticationRequired);
+        }
+        let guid = Guid::new(&guid);
+        let credit_card = credit_cards::get_credit_card(&db.writer, &guid)?;
+        let number =
+            EncryptorDecryptor::new(&key)?.decrypt(&credit_card.cc_number_enc, "cc_number")?;
+        credit_cards::record_reveal(&db.writer, &guid)?;
+        Ok(number)
+    }
+
+    /// The log of revealed credit-card numbers, most recent first.
+    #[handle_error(Error)]
+    pub fn get_credit_card_reveals(&self) -> ApiResult<Vec<CreditCardReveal>> {
+        credit_cards::get_reveals(&self.db.lock().unwrap().writer)
+    }
+
+    #[handle_error(Error)]
+    pub fn add_iban(&self, fields: UpdatableIbanFields) -> ApiResult<Iban> {
+        Ok(ibans::add_iban(&self.db.lock().unwrap().writer, fields)?.into())
+    }
+
+    #[handle_error(Error)]
+    pub fn get_iban(&self, guid: String) -> ApiResult<Iban> {
+        Ok(ibans::get_iban(&self.db.lock().unwrap().writer, &Guid::new(&guid))?.into())
+    }
+
+    #[handle_error(Error)]
+    pub fn get_all_ibans(&self) -> ApiResult<Vec<Iban>> {
+        let ibans = ibans::get_all_ibans(&self.db.lock().unwrap().writer)?
+            .into_iter()
+            .map(|x| x.into())
+            .collect();
+        Ok(ibans)
+    }
+
+    #[handle_error(Error)]
+    pub fn update_iban(&self, guid: String, iban: UpdatableIbanFields) -> ApiResult<()> {
+        ibans::update_iban(&self.db.lock().unwrap().writer, &Guid::new(&guid), &iban)
+    }
+
+    #[handle_error(Error)]
+    pub fn delete_iban(&self, guid: String) -> ApiResult<bool> {
+        ibans::delete_iban(&self.db.lock().unwrap().writer, &Guid::new(&guid))
+    }
+
+    #[handle_error(Error)]
+    pub fn touch_iban(&self, guid: String) -> ApiResult<()> {
+        ibans::touch(&self.db.lock().unwrap().writer, &Guid::new(&guid))
+    }
+
     #[handle_error(Error)]
     pub fn add_address(&self, new_address: UpdatableAddressFields) -> ApiResult<Address> {
         Ok(addresses::add_address(&self.db.lock().unwrap().writer, new_address)?.into())
@@ -151,14 +312,57 @@ impl Store {
         addresses::touch(&self.db.lock().unwrap().writer, &Guid::new(&guid))
     }
 
+    /// Addresses where `field` starts with `prefix`, for suggesting in a
+    /// form, best first.
+    #[handle_error(Error)]
+    pub fn suggest_addresses(
+        &self,
+        field: AddressField,
+        prefix: String,
+        limit: u32,
+    ) -> ApiResult<Vec<Address>> {
+        let addresses = addresses::suggest_addresses(
+            &self.db.lock().unwrap().writer,
+            field,
+            &prefix,
+            limit,
+            Timestamp::now(),
+        )?
+        .into_iter()
+        .map(|x| x.into())
+        .collect();
+        Ok(addresses)
+    }
+
+    #[handle_error(Error)]
+    pub fn find_duplicate_address(
+        &self,
+        address: UpdatableAddressFields,
+    ) -> ApiResult<Option<Address>> {
+        Ok(
+            addresses::find_duplicate_address(&self.db.lock().unwrap().writer, &address)?
+                .map(|x| x.into()),
+        )
+    }
+
+    #[handle_error(Error)]
+    pub fn add_or_merge_address(&self, address: UpdatableAddressFields) -> ApiResult<Address> {
+        Ok(addresses::add_or_merge_address(&self.db.lock().unwrap().writer, address)?.into())
+    }
+
     #[handle_error(Error)]
     pub fn scrub_encrypted_data(self: Arc<Self>) -> ApiResult<()> {
         // scrub the data on disk
-        // Currently only credit cards have encrypted data
-        credit_cards::scrub_encrypted_credit_card_data(&self.db.lock().unwrap().writer)?;
-        // Force the sync engine to refetch data (only need to do this for the credit cards, since the
-        // addresses engine doesn't store encrypted data).
-        crate::sync::credit_card::create_engine(self).reset_local_sync_data()?;
+        // Currently only credit cards and IBANs have encrypted data
+        {
+            let db = self.db.lock().unwrap();
+            credit_cards::scrub_encrypted_credit_card_data(&db.writer)?;
+            ibans::scrub_encrypted_iban_data(&db.writer)?;
+        }
+        // Force the sync engines to refetch data (only need to do this for the credit cards and
+        // IBANs, since the addresses engine doesn't store encrypted data).
+        crate::sync::credit_card::create_engine(self.clone()).reset_local_sync_data()?;
+        crate::sync::iban::create_engine(self).reset_local_sync_data()?;
         Ok(())
     }
 
@@ -172,7 +376,7 @@ impl Store {
         *state = Arc::downgrade(&self);
     }
 
-    // These 2 are a little odd - they aren't exposed by uniffi - currently the
+    // These 3 are a little odd - they aren't exposed by uniffi - currently the
     // only consumer of this is our "example" (and hence why they
     // are `pub` and not `pub(crate)`).
     // We could probably make the example work with the sync manager - but then
@@ -185,6 +389,10 @@ impl Store {
     pub fn create_addresses_sync_engine(self: Arc<Self>) -> Box<dyn SyncEngine> {
         Box::new(crate::sync::address::create_engine(self))
     }
+
+    pub fn create_ibans_sync_engine(self: Arc<Self>) -> Box<dyn SyncEngine> {
+        Box::new(crate::sync::iban::create_engine(self))
+    }
 }
 
 pub(crate) fn put_meta(conn: &Connection, key: &str, value: &dyn ToSql) -> Result<()> {
@@ -249,6 +457,62 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_reveal_credit_card_number() {
+        let store = Store::new_memory();
+        let key = crate::encryption::create_autofill_key().unwrap();
+        let credit_card = store
+            .add_credit_card(UpdatableCreditCardFields {
+                cc_name: "jane doe".to_string(),
+                cc_number_enc: crate::encryption::encrypt_string(
+                    key.clone(),
+                    "4111111111111111".to_string(),
+                )
+                .unwrap(),
+                cc_number_last_4: "1111".to_string(),
+                cc_exp_month: 3,
+                cc_exp_year: 2032,
+                cc_type: "visa".to_string(),
+            })
+            .unwrap();
+
+        // Without a re-authentication, the number can't be revealed.
+        assert!(matches!(
+            store.reveal_credit_card_number(credit_card.guid.clone(), key.clone()),
+            Err(AutofillApiError::ReauthenticationRequired)
+        ));
+        assert!(store.get_credit_card_reveals().unwrap().is_empty());
+
+        store.record_reauthentication();
+        assert_eq!(
+            store
+                .reveal_credit_card_number(credit_card.guid.clone(), key.clone())
+                .unwrap(),
+            "4111111111111111"
+        );
+        let reveals = store.get_credit_card_reveals().unwrap();
+        assert_eq!(reveals.len(), 1);
+        assert_eq!(reveals[0].guid, credit_card.guid);
+
+        // A re-authentication from before the window started isn't enough.
+        store.set_credit_card_reveal_window(1000).unwrap();
+        *store.last_reauthentication.lock().unwrap() =
+            Instant::now().checked_sub(Duration::from_secs(2));
+        assert!(matches!(
+            store.reveal_credit_card_number(credit_card.guid.clone(), key.clone()),
+            Err(AutofillApiError::ReauthenticationRequired)
+        ));
+
+        // A zero window needs a re-authentication for every reveal.
+        store.set_credit_card_reveal_window(0).unwrap();
+        store.record_reauthentication();
+        assert!(matches!(
+            store.reveal_credit_card_number(credit_card.guid, key),
+            Err(AutofillApiError::ReauthenticationRequired)
+        ));
+        assert_eq!(store.get_credit_card_reveals().unwrap().len(), 1);
+    }
+
     #[test]
     fn test_sync_manager_registration() {
         let store = Arc::new(Store::new_shared_memory("sync-mgr-test").unwrap());
@@ -27,6 +27,12 @@ pub enum AutofillApiError {
     #[error("No record with guid exists: {guid}")]
     NoSuchRecord { guid: String },
 
+    #[error("Invalid record: {reason}")]
+    InvalidRecord { reason: String },
+
+    #[error("The user must re-authenticate first")]
+    ReauthenticationRequired,
+
     #[error("Unexpected Error: {reason}")]
     UnexpectedAutofillApiError { reason: String },
 }
@@ -65,6 +71,18 @@ pub enum Error {
 
     #[error("No record with guid exists: {0}")]
     NoSuchRecord(String),
+
+    #[error("Invalid credit card number: {0}")]
+    InvalidCreditCardNumber(String),
+
+    #[error("Invalid IBAN: {0}")]
+    InvalidIban(String),
+
+    #[error("Invalid address field: {0}")]
+    InvalidAddressField(String),
+
+    #[error("The user hasn't re-authenticated recently enough")]
+    ReauthenticationRequired,
 }
 
 // Define how our internal errors are handled and converted to external errors
@@ -126,6 +144,29 @@ impl GetErrorHandling for Error {
                 ErrorHandling::convert(AutofillApiError::NoSuchRecord { guid: guid.clone() })
                     .log_warning()
             }
+
+            Self::InvalidCreditCardNumber(reason) => {
+                ErrorHandling::convert(AutofillApiError::InvalidRecord {
+                    reason: format!("Invalid credit card number: {reason}"),
+                })
+                .log_warning()
+            }
+
+            Self::InvalidIban(reason) => ErrorHandling::convert(AutofillApiError::InvalidRecord {
+                reason: format!("Invalid IBAN: {reason}"),
+            })
+            .log_warning(),
+
+            Self::InvalidAddressField(reason) => {
+                ErrorHandling::convert(AutofillApiError::InvalidRecord {
+                    reason: reason.clone(),
+                })
+                .log_warning()
+            }
+
+            Self::ReauthenticationRequired => {
+                ErrorHandling::convert(AutofillApiError::ReauthenticationRequired).log_info()
+            }
         }
     }
 }
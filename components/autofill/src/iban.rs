@@ -0,0 +1,143 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+// Validation of IBANs as typed by the user. IBANs are stored encrypted, so we
+// can't validate what's passed to `add_iban()` - apps use
+// `encrypt_iban()` to get the values to store instead.
+//
+// The rules are from ISO 13616:
+// https://en.wikipedia.org/wiki/International_Bank_Account_Number#Validating_the_IBAN
+
+use crate::encryption::EncryptorDecryptor;
+use crate::error::*;
+use error_support::handle_error;
+
+/// What you get back from `encrypt_iban()` - the values for the matching
+/// fields of `UpdatableIbanFields`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedIban {
+    pub iban_enc: String,
+    pub iban_last_4: String,
+}
+
+// The shortest and longest IBANs in use.
+const MIN_LENGTH: usize = 15;
+const MAX_LENGTH: usize = 34;
+
+// A public function we expose over the FFI (which is why it takes `String`
+// rather than `&str`). `iban` may contain spaces and be in any case, as typed.
+#[handle_error(Error)]
+pub fn encrypt_iban(key: String, iban: String) -> ApiResult<EncryptedIban> {
+    let iban = normalize_iban(&iban)?;
+    Ok(EncryptedIban {
+        iban_enc: EncryptorDecryptor::new(&key)?.encrypt(&iban, "iban")?,
+        iban_last_4: iban[iban.len() - 4..].to_string(),
+    })
+}
+
+/// Strip the spaces from an IBAN, uppercase it, and check it's valid,
+/// returning the "electronic format" IBAN. Error messages never include the
+/// IBAN.
+pub(crate) fn normalize_iban(iban: &str) -> Result<String> {
+    let iban = iban
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_uppercase();
+    if !iban.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(Error::InvalidIban(
+            "must only contain letters, digits and spaces".to_string(),
+        ));
+    }
+    if !(MIN_LENGTH..=MAX_LENGTH).contains(&iban.len()) {
+        return Err(Error::InvalidIban(format!(
+            "must have between {MIN_LENGTH} and {MAX_LENGTH} characters"
+        )));
+    }
+    let (country, check_digits) = (&iban[..2], &iban[2..4]);
+    if !country.chars().all(|c| c.is_ascii_alphabetic())
+        || !check_digits.chars().all(|c| c.is_ascii_digit())
+    {
+        return Err(Error::InvalidIban(
+            "must start with a country code and check digits".to_string(),
+        ));
+    }
+    if !passes_mod_97(&iban) {
+        return Err(Error::InvalidIban("failed the checksum".to_string()));
+    }
+    Ok(iban)
+}
+
+// Move the first 4 chars to the end, replace letters with numbers (A=10, ...,
+// Z=35), and the result mod 97 must be 1. `iban` must be ASCII alphanumeric.
+fn passes_mod_97(iban: &str) -> bool {
+    // The number is far too big for any integer type, so we compute the
+    // remainder as we go.
+    let remainder = iban[4..]
+        .chars()
+        .chain(iban[..4].chars())
+        .filter_map(|c| c.to_digit(36))
+        .fold(0, |remainder, value| {
+            let scale = if value < 10 { 10 } else { 100 };
+            (remainder * scale + value) % 97
+        });
+    remainder == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::create_autofill_key;
+
+    #[test]
+    fn test_mod_97() {
+        assert!(passes_mod_97("GB82WEST12345698765432"));
+        assert!(passes_mod_97("DE89370400440532013000"));
+        assert!(!passes_mod_97("GB82WEST12345698765433"));
+        assert!(!passes_mod_97("DE88370400440532013000"));
+    }
+
+    #[test]
+    fn test_normalize_iban() {
+        assert_eq!(
+            normalize_iban("gb82 west 1234 5698 7654 32").unwrap(),
+            "GB82WEST12345698765432"
+        );
+        assert_eq!(
+            normalize_iban("FR14 2004 1010 0505 0001 3M02 606").unwrap(),
+            "FR1420041010050500013M02606"
+        );
+        for invalid in [
+            "GB82 WEST 1234 5698 7654 33",
+            "GB82-WEST-1234-5698-7654-32",
+            "8282WEST12345698765432",
+            "GB82",
+            "",
+        ] {
+            assert!(
+                matches!(normalize_iban(invalid), Err(Error::InvalidIban(_))),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_encrypt_iban() {
+        let key = create_autofill_key().unwrap();
+        let result = encrypt_iban(key.clone(), "DE89 3704 0044 0532 0130 00".to_string()).unwrap();
+        assert_eq!(result.iban_last_4, "3000");
+        assert_eq!(
+            EncryptorDecryptor::new(&key)
+                .unwrap()
+                .decrypt(&result.iban_enc, "test")
+                .unwrap(),
+            "DE89370400440532013000"
+        );
+        assert!(matches!(
+            encrypt_iban(key, "DE00".to_string()),
+            Err(AutofillApiError::InvalidRecord { .. })
+        ));
+    }
+}
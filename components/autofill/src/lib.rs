@@ -6,19 +6,26 @@
 #![allow(unknown_lints)]
 #![warn(rust_2018_idioms)]
 
+pub mod address_normalization;
+pub mod credit_card_number;
 pub mod db;
 pub mod encryption;
 pub mod error;
+pub mod iban;
 pub mod sync;
 
 // Re-export stuff the sync manager needs.
 pub use crate::db::store::get_registered_sync_engine;
 
 // Expose stuff needed by the uniffi generated code.
+use crate::address_normalization::{format_address, normalize_postal_code, normalize_tel};
+use crate::credit_card_number::*;
 use crate::db::models::address::*;
 use crate::db::models::credit_card::*;
+use crate::db::models::iban::*;
 use crate::db::store::Store;
 use crate::encryption::{create_autofill_key, decrypt_string, encrypt_string};
+use crate::iban::*;
 pub use error::{ApiResult, AutofillApiError, Error, Result};
 
 uniffi::include_scaffolding!("autofill");
@@ -4,7 +4,7 @@
 */
 
 use super::AddressPayload;
-use crate::db::addresses::{add_internal_address, update_internal_address};
+use crate::db::addresses::{add_internal_address, addresses_match, update_internal_address};
 use crate::db::models::address::InternalAddress;
 use crate::db::schema::ADDRESS_COMMON_COLS;
 use crate::error::*;
@@ -155,7 +155,8 @@ impl ProcessIncomingRecordImpl for IncomingAddressesImpl {
 
     /// Returns a local record that has the same values as the given incoming record (with the exception
     /// of the `guid` values which should differ) that will be used as a local duplicate record for
-    /// syncing.
+    /// syncing. Values are compared after normalization, so differences in formatting (eg, of the
+    /// phone number) don't prevent a match.
     fn get_local_dupe(
         &self,
         tx: &Transaction<'_>,
@@ -173,48 +174,16 @@ impl ProcessIncomingRecordImpl for IncomingAddressesImpl {
                 AND guid NOT IN (
                     SELECT guid
                     FROM addresses_mirror
-                )
-                -- and sql can check the field values.
-                AND given_name == :given_name
-                AND additional_name == :additional_name
-                AND family_name == :family_name
-                AND organization == :organization
-                AND street_address == :street_address
-                AND address_level3 == :address_level3
-                AND address_level2 == :address_level2
-                AND address_level1 == :address_level1
-                AND postal_code == :postal_code
-                AND country == :country
-                AND tel == :tel
-                AND email == :email", common_cols = ADDRESS_COMMON_COLS);
-
-        let params = named_params! {
-            ":guid": incoming.guid,
-            ":given_name": incoming.given_name,
-            ":additional_name": incoming.additional_name,
-            ":family_name": incoming.family_name,
-            ":organization": incoming.organization,
-            ":street_address": incoming.street_address,
-            ":address_level3": incoming.address_level3,
-            ":address_level2": incoming.address_level2,
-            ":address_level1": incoming.address_level1,
-            ":postal_code": incoming.postal_code,
-            ":country": incoming.country,
-            ":tel": incoming.tel,
-            ":email": incoming.email,
-        };
-
-        let result = tx.query_row(&sql, params, |row| {
-            Ok(Self::Record::from_row(row).expect("wtf? '?' doesn't work :("))
-        });
+                )", common_cols = ADDRESS_COMMON_COLS);
 
-        match result {
-            Ok(r) => Ok(Some(r)),
-            Err(e) => match e {
-                rusqlite::Error::QueryReturnedNoRows => Ok(None),
-                _ => Err(Error::SqlError(e)),
-            },
-        }
+        let candidates = tx.query_rows_and_then(
+            &sql,
+            named_params! { ":guid": incoming.guid },
+            |row| -> Result<Self::Record> { Ok(Self::Record::from_row(row)?) },
+        )?;
+        Ok(candidates
+            .into_iter()
+            .find(|candidate| addresses_match(candidate, incoming)))
     }
 
     fn update_local_record(
@@ -259,6 +228,7 @@ mod tests {
     use super::*;
     use crate::db::addresses::get_address;
     use crate::sync::common::tests::*;
+    use crate::sync::MergeResult;
 
     use interrupt_support::NeverInterrupts;
     use serde_json::{json, Map, Value};
@@ -492,4 +462,92 @@ mod tests {
         let bso = record.clone().into_test_incoming_bso();
         do_test_staged_to_mirror(&ai, &tx, record, bso, "addresses_mirror");
     }
+
+    #[test]
+    fn test_get_local_dupe_normalized() -> Result<()> {
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction()?;
+        let ri = IncomingAddressesImpl {};
+        let mut local = test_record('C');
+        local.guid = SyncGuid::new(&expand_test_guid('B'));
+        local.street_address = "3050 south la brea ave.".to_string();
+        local.tel = "+1 (555) 123-4567".to_string();
+        ri.insert_local_record(&tx, local)?;
+
+        let mut incoming = test_record('C');
+        incoming.tel = "+15551234567".to_string();
+        let dupe = ri
+            .get_local_dupe(&tx, &incoming)?
+            .expect("should find a dupe");
+        assert_eq!(dupe.guid, expand_test_guid('B'));
+
+        incoming.street_address = "3051 South La Brea Ave".to_string();
+        assert!(ri.get_local_dupe(&tx, &incoming)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_normalized() {
+        let mut mirror = test_record('C');
+        mirror.address_level1 = "NY".to_string();
+        mirror.tel = "+12125550100".to_string();
+        mirror.country = "US".to_string();
+        // The other device only changed the formatting of some fields, and the
+        // name.
+        let mut incoming = mirror.clone();
+        incoming.address_level1 = "new york".to_string();
+        incoming.tel = "(212) 555-0100".to_string();
+        incoming.given_name = "Janet".to_string();
+        // We only changed the email.
+        let mut local = mirror.clone();
+        local.email = "jane@example.com".to_string();
+
+        match InternalAddress::merge(&incoming, &local, &Some(mirror)) {
+            MergeResult::Merged { merged } => {
+                assert_eq!(merged.given_name, "Janet");
+                assert_eq!(merged.email, "jane@example.com");
+                // Unchanged fields keep our formatting.
+                assert_eq!(merged.address_level1, "NY");
+                assert_eq!(merged.tel, "+12125550100");
+            }
+            _ => panic!("should have merged"),
+        }
+
+        // Without a mirror, differently formatted values still aren't a conflict.
+        let mut local = incoming.clone();
+        local.address_level1 = "NY".to_string();
+        local.tel = "+1 212 555 0100".to_string();
+        assert!(matches!(
+            InternalAddress::merge(&incoming, &local, &None),
+            MergeResult::Merged { .. }
+        ));
+    }
+
+    #[test]
+    fn test_incoming_not_normalized() {
+        let payload = serde_json::from_value(json! {{
+            "id": expand_test_guid('E'),
+            "entry": {
+                "given-name": "jane",
+                "address-level1": "new york",
+                "postal-code": "100011234",
+                "country": "US",
+                "tel": "(212) 555-0100",
+                "version": 1,
+            }
+        }})
+        .unwrap();
+        // Other devices' data is stored as they wrote it...
+        let address = InternalAddress::from_payload(payload).expect("should be valid");
+        assert_eq!(address.address_level1, "new york");
+        assert_eq!(address.postal_code, "100011234");
+        assert_eq!(address.tel, "(212) 555-0100");
+
+        // ...but still matches the same address saved locally.
+        let mut local = address.clone();
+        local.address_level1 = "NY".to_string();
+        local.postal_code = "10001-1234".to_string();
+        local.tel = "+12125550100".to_string();
+        assert!(addresses_match(&local, &address));
+    }
 }
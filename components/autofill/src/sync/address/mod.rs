@@ -11,6 +11,7 @@ use super::{
     MergeResult, Metadata, ProcessIncomingRecordImpl, ProcessOutgoingRecordImpl, SyncRecord,
     UnknownFields,
 };
+use crate::address_normalization::normalize_address;
 use crate::db::models::address::InternalAddress;
 use crate::error::*;
 use crate::sync_merge_field_check;
@@ -200,6 +201,20 @@ impl SyncRecord for InternalAddress {
 
         merged_record.guid = incoming.guid.clone();
 
+        // Incoming records are stored as other devices wrote them, so a field
+        // which only differs in formatting (eg, "new york" and "NY") hasn't
+        // changed. Compare normalized copies.
+        let normalized = |address: &InternalAddress| {
+            let mut address = address.clone();
+            normalize_address(&mut address);
+            address
+        };
+        let (n_incoming, n_local, n_mirror) = (
+            normalized(incoming),
+            normalized(local),
+            mirror.as_ref().map(normalized),
+        );
+
         sync_merge_field_check!(given_name, incoming, local, mirror, merged_record);
         sync_merge_field_check!(additional_name, incoming, local, mirror, merged_record);
         sync_merge_field_check!(family_name, incoming, local, mirror, merged_record);
@@ -207,10 +222,31 @@ impl SyncRecord for InternalAddress {
         sync_merge_field_check!(street_address, incoming, local, mirror, merged_record);
         sync_merge_field_check!(address_level3, incoming, local, mirror, merged_record);
         sync_merge_field_check!(address_level2, incoming, local, mirror, merged_record);
-        sync_merge_field_check!(address_level1, incoming, local, mirror, merged_record);
-        sync_merge_field_check!(postal_code, incoming, local, mirror, merged_record);
+        sync_merge_field_check!(
+            address_level1,
+            incoming,
+            local,
+            mirror,
+            merged_record,
+            compared as (n_incoming, n_local, n_mirror)
+        );
+        sync_merge_field_check!(
+            postal_code,
+            incoming,
+            local,
+            mirror,
+            merged_record,
+            compared as (n_incoming, n_local, n_mirror)
+        );
         sync_merge_field_check!(country, incoming, local, mirror, merged_record);
-        sync_merge_field_check!(tel, incoming, local, mirror, merged_record);
+        sync_merge_field_check!(
+            tel,
+            incoming,
+            local,
+            mirror,
+            merged_record,
+            compared as (n_incoming, n_local, n_mirror)
+        );
         sync_merge_field_check!(email, incoming, local, mirror, merged_record);
 
         merged_record.metadata = incoming.metadata;
@@ -140,6 +140,9 @@ pub(super) fn common_mirror_staged_records(
 // InsertableItem type.
 // Macros don't have fine-grained visibility and is visible to the entire
 // crate, so we give it a very specific name.
+// The values can be compared using other versions of the records (eg,
+// normalized copies) by passing them with `compared as`; the merged value is
+// always taken from `$incoming` or `$local`.
 #[macro_export]
 macro_rules! sync_merge_field_check {
     ($field_name:ident,
@@ -148,12 +151,28 @@ macro_rules! sync_merge_field_check {
     $mirror:ident,
     $merged_record:ident
     ) => {
-        let incoming_field = &$incoming.$field_name;
-        let local_field = &$local.$field_name;
+        $crate::sync_merge_field_check!(
+            $field_name,
+            $incoming,
+            $local,
+            $mirror,
+            $merged_record,
+            compared as ($incoming, $local, $mirror)
+        );
+    };
+    ($field_name:ident,
+    $incoming:ident,
+    $local:ident,
+    $mirror:ident,
+    $merged_record:ident,
+    compared as ($compared_incoming:ident, $compared_local:ident, $compared_mirror:ident)
+    ) => {
+        let incoming_field = &$compared_incoming.$field_name;
+        let local_field = &$compared_local.$field_name;
         let is_local_same;
         let is_incoming_same;
 
-        match &$mirror {
+        match &$compared_mirror {
             Some(m) => {
                 let mirror_field = &m.$field_name;
                 is_local_same = mirror_field == local_field;
@@ -168,9 +187,9 @@ macro_rules! sync_merge_field_check {
         let should_use_local = is_incoming_same || local_field == incoming_field;
 
         if is_local_same && !is_incoming_same {
-            $merged_record.$field_name = incoming_field.clone();
+            $merged_record.$field_name = $incoming.$field_name.clone();
         } else if should_use_local {
-            $merged_record.$field_name = local_field.clone();
+            $merged_record.$field_name = $local.$field_name.clone();
         } else {
             // There are conflicting differences, so we "fork" the record - we
             // will end up giving the local one a new guid and save the remote
@@ -0,0 +1,420 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/.
+*/
+
+use super::IbanPayload;
+use crate::db::ibans::{add_internal_iban, update_internal_iban};
+use crate::db::models::iban::InternalIban;
+use crate::db::schema::IBAN_COMMON_COLS;
+use crate::encryption::EncryptorDecryptor;
+use crate::error::*;
+use crate::sync::common::*;
+use crate::sync::{
+    IncomingBso, IncomingContent, IncomingEnvelope, IncomingKind, IncomingState, LocalRecordInfo,
+    ProcessIncomingRecordImpl, ServerTimestamp, SyncRecord,
+};
+use interrupt_support::Interruptee;
+use rusqlite::{named_params, Transaction};
+use sql_support::ConnExt;
+use sync_guid::Guid as SyncGuid;
+
+// Takes a raw payload, as stored in our database, and returns an InternalIban
+// or a tombstone. Like credit-cards, IBANs store the payload as an encrypted
+// string, so we decrypt before conversion.
+fn raw_payload_to_incoming(
+    id: SyncGuid,
+    raw: String,
+    encdec: &EncryptorDecryptor,
+) -> Result<IncomingContent<InternalIban>> {
+    let payload = encdec.decrypt(&raw, "raw payload")?;
+    // Turn it into a BSO
+    let bso = IncomingBso {
+        envelope: IncomingEnvelope {
+            id,
+            modified: ServerTimestamp::default(),
+            sortindex: None,
+            ttl: None,
+        },
+        payload,
+    };
+    let payload_content = bso.into_content::<IbanPayload>();
+    Ok(match payload_content.kind {
+        IncomingKind::Content(content) => IncomingContent {
+            envelope: payload_content.envelope,
+            kind: IncomingKind::Content(InternalIban::from_payload(content, encdec)?),
+        },
+        IncomingKind::Tombstone => IncomingContent {
+            envelope: payload_content.envelope,
+            kind: IncomingKind::Tombstone,
+        },
+        IncomingKind::Malformed => IncomingContent {
+            envelope: payload_content.envelope,
+            kind: IncomingKind::Malformed,
+        },
+    })
+}
+
+pub(super) struct IncomingIbansImpl {
+    pub(super) encdec: EncryptorDecryptor,
+}
+
+impl ProcessIncomingRecordImpl for IncomingIbansImpl {
+    type Record = InternalIban;
+
+    /// The first step in the "apply incoming" process - stage the records
+    fn stage_incoming(
+        &self,
+        tx: &Transaction<'_>,
+        incoming: Vec<IncomingBso>,
+        signal: &dyn Interruptee,
+    ) -> Result<()> {
+        // Convert the sync15::Payloads to encrypted strings.
+        let to_stage = incoming
+            .into_iter()
+            .map(|bso| {
+                // consider turning this into malformed?
+                let encrypted = self.encdec.encrypt(&bso.payload, "bso payload")?;
+                Ok((bso.envelope.id, encrypted, bso.envelope.modified))
+            })
+            .collect::<Result<_>>()?;
+        common_stage_incoming_records(tx, "ibans_sync_staging", to_stage, signal)
+    }
+
+    fn finish_incoming(&self, tx: &Transaction<'_>) -> Result<()> {
+        common_mirror_staged_records(tx, "ibans_sync_staging", "ibans_mirror")
+    }
+
+    /// The second step in the "apply incoming" process for syncing autofill IBAN records.
+    /// Incoming items are retrieved from the temp tables, deserialized, and
+    /// assigned `IncomingState` values.
+    fn fetch_incoming_states(
+        &self,
+        tx: &Transaction<'_>,
+    ) -> Result<Vec<IncomingState<Self::Record>>> {
+        let sql = "
+        SELECT
+            s.guid as guid,
+            l.guid as l_guid,
+            t.guid as t_guid,
+            s.payload as s_payload,
+            m.payload as m_payload,
+            l.iban_name,
+            l.iban_enc,
+            l.iban_last_4,
+            l.time_created,
+            l.time_last_used,
+            l.time_last_modified,
+            l.times_used,
+            l.sync_change_counter
+        FROM temp.ibans_sync_staging s
+        LEFT JOIN ibans_mirror m ON s.guid = m.guid
+        LEFT JOIN ibans_data l ON s.guid = l.guid
+        LEFT JOIN ibans_tombstones t ON s.guid = t.guid";
+
+        tx.query_rows_and_then(sql, [], |row| -> Result<IncomingState<Self::Record>> {
+            // the 'guid' and 's_payload' rows must be non-null.
+            let guid: SyncGuid = row.get("guid")?;
+            let incoming =
+                raw_payload_to_incoming(guid.clone(), row.get("s_payload")?, &self.encdec)?;
+            Ok(IncomingState {
+                incoming,
+                local: match row.get_unwrap::<_, Option<String>>("l_guid") {
+                    Some(l_guid) => {
+                        assert_eq!(l_guid, guid);
+                        // local record exists, check the state.
+                        let record = InternalIban::from_row(row)?;
+                        if record.has_scrubbed_data() {
+                            LocalRecordInfo::Scrubbed { record }
+                        } else {
+                            let has_changes = record.metadata().sync_change_counter != 0;
+                            if has_changes {
+                                LocalRecordInfo::Modified { record }
+                            } else {
+                                LocalRecordInfo::Unmodified { record }
+                            }
+                        }
+                    }
+                    None => {
+                        // no local record - maybe a tombstone?
+                        match row.get::<_, Option<String>>("t_guid")? {
+                            Some(t_guid) => {
+                                assert_eq!(guid, t_guid);
+                                LocalRecordInfo::Tombstone { guid: guid.clone() }
+                            }
+                            None => LocalRecordInfo::Missing,
+                        }
+                    }
+                },
+                mirror: {
+                    match row.get::<_, Option<String>>("m_payload")? {
+                        Some(m_payload) => {
+                            // a tombstone in the mirror can be treated as though it's missing.
+                            raw_payload_to_incoming(guid, m_payload, &self.encdec)?.content()
+                        }
+                        None => None,
+                    }
+                },
+            })
+        })
+    }
+
+    /// Returns a local record that has the same values as the given incoming record (with the exception
+    /// of the `guid` values which should differ) that will be used as a local duplicate record for
+    /// syncing.
+    fn get_local_dupe(
+        &self,
+        tx: &Transaction<'_>,
+        incoming: &Self::Record,
+    ) -> Result<Option<Self::Record>> {
+        let sql = format!("
+            SELECT
+                {common_cols},
+                sync_change_counter
+            FROM ibans_data
+            WHERE
+                -- `guid <> :guid` is a pre-condition for this being called, but...
+                guid <> :guid
+                -- only non-synced records are candidates, which means can't already be in the mirror.
+                AND guid NOT IN (
+                    SELECT guid
+                    FROM ibans_mirror
+                )
+                -- and sql can check the field values (but note we can not meaningfully
+                -- check the encrypted value, as it's different each time it is encrypted)
+                AND iban_name == :iban_name
+                AND iban_last_4 == :iban_last_4", common_cols = IBAN_COMMON_COLS);
+
+        let params = named_params! {
+            ":guid": incoming.guid,
+            ":iban_name": incoming.iban_name,
+            ":iban_last_4": incoming.iban_last_4,
+        };
+
+        // Because we can't check the IBAN in the sql, we fetch all matching
+        // rows and decrypt the IBANs here.
+        let records = tx.query_rows_and_then(&sql, params, |row| -> Result<Self::Record> {
+            Ok(Self::Record::from_row(row)?)
+        })?;
+
+        let incoming_iban = self.encdec.decrypt(&incoming.iban_enc, "iban")?;
+        for record in records {
+            if self.encdec.decrypt(&record.iban_enc, "iban")? == incoming_iban {
+                return Ok(Some(record));
+            }
+        }
+        Ok(None)
+    }
+
+    fn update_local_record(
+        &self,
+        tx: &Transaction<'_>,
+        new_record: Self::Record,
+        flag_as_changed: bool,
+    ) -> Result<()> {
+        update_internal_iban(tx, &new_record, flag_as_changed)?;
+        Ok(())
+    }
+
+    fn insert_local_record(&self, tx: &Transaction<'_>, new_record: Self::Record) -> Result<()> {
+        add_internal_iban(tx, &new_record)?;
+        Ok(())
+    }
+
+    /// Changes the guid of the local record for the given `old_guid` to the given `new_guid` used
+    /// for the `HasLocalDupe` incoming state, and mark the item as dirty.
+    /// We also update the mirror record if it exists in forking scenarios
+    fn change_record_guid(
+        &self,
+        tx: &Transaction<'_>,
+        old_guid: &SyncGuid,
+        new_guid: &SyncGuid,
+    ) -> Result<()> {
+        common_change_guid(tx, "ibans_data", "ibans_mirror", old_guid, new_guid)
+    }
+
+    fn remove_record(&self, tx: &Transaction<'_>, guid: &SyncGuid) -> Result<()> {
+        common_remove_record(tx, "ibans_data", guid)
+    }
+
+    fn remove_tombstone(&self, tx: &Transaction<'_>, guid: &SyncGuid) -> Result<()> {
+        common_remove_record(tx, "ibans_tombstones", guid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::test::new_syncable_mem_db;
+    use super::*;
+    use crate::db::ibans::get_iban;
+    use crate::sync::common::tests::*;
+
+    use interrupt_support::NeverInterrupts;
+    use serde_json::{json, Map, Value};
+
+    lazy_static::lazy_static! {
+        static ref TEST_JSON_RECORDS: Map<String, Value> = {
+            let val = json! {{
+                "A" : {
+                    "id": expand_test_guid('A'),
+                    "entry": {
+                        "iban-name": "Joint account",
+                        "iban": "GB82WEST12345698765432",
+                        "version": 1,
+                    }
+                },
+                "C" : {
+                    "id": expand_test_guid('C'),
+                    "entry": {
+                        "iban-name": "Savings",
+                        "iban": "DE89370400440532013000",
+                        "timeCreated": 0,
+                        "timeLastUsed": 0,
+                        "timeLastModified": 0,
+                        "timesUsed": 0,
+                        "version": 1,
+                        "foo": "bar",
+                    }
+                }
+            }};
+            val.as_object().expect("literal is an object").clone()
+        };
+    }
+
+    fn test_json_record(guid_prefix: char) -> Value {
+        TEST_JSON_RECORDS
+            .get(&guid_prefix.to_string())
+            .expect("should exist")
+            .clone()
+    }
+
+    fn test_record(guid_prefix: char, encdec: &EncryptorDecryptor) -> InternalIban {
+        let json = test_json_record(guid_prefix);
+        let payload = serde_json::from_value(json).unwrap();
+        InternalIban::from_payload(payload, encdec).expect("should be valid")
+    }
+
+    #[test]
+    fn test_stage_incoming() -> Result<()> {
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction()?;
+        let ii = IncomingIbansImpl {
+            encdec: EncryptorDecryptor::new_with_random_key().unwrap(),
+        };
+        ii.stage_incoming(
+            &tx,
+            array_to_incoming(vec![
+                test_json_record('A'),
+                test_json_record('C'),
+                test_json_tombstone('B'),
+            ]),
+            &NeverInterrupts,
+        )?;
+        let states = ii.fetch_incoming_states(&tx)?;
+        assert_eq!(states.len(), 3);
+        let tombstones = states
+            .iter()
+            .filter(|s| matches!(s.incoming.kind, IncomingKind::Tombstone))
+            .count();
+        assert_eq!(tombstones, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_incoming() {
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction().expect("should get tx");
+        let ii = IncomingIbansImpl {
+            encdec: EncryptorDecryptor::new_with_random_key().unwrap(),
+        };
+        let record = test_record('C', &ii.encdec);
+        let bso = record
+            .clone()
+            .into_test_incoming_bso(&ii.encdec, Default::default());
+        do_test_incoming_same(&ii, &tx, record, bso);
+    }
+
+    #[test]
+    fn test_incoming_tombstone() {
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction().expect("should get tx");
+        let ii = IncomingIbansImpl {
+            encdec: EncryptorDecryptor::new_with_random_key().unwrap(),
+        };
+        do_test_incoming_tombstone(&ii, &tx, test_record('C', &ii.encdec));
+    }
+
+    #[test]
+    fn test_local_data_scrubbed() {
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction().expect("should get tx");
+        let ii = IncomingIbansImpl {
+            encdec: EncryptorDecryptor::new_with_random_key().unwrap(),
+        };
+        let mut scrubbed_record = test_record('A', &ii.encdec);
+        let bso = scrubbed_record
+            .clone()
+            .into_test_incoming_bso(&ii.encdec, Default::default());
+        scrubbed_record.iban_enc = "".to_string();
+        do_test_scrubbed_local_data(&ii, &tx, scrubbed_record, bso);
+    }
+
+    #[test]
+    fn test_staged_to_mirror() {
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction().expect("should get tx");
+        let ii = IncomingIbansImpl {
+            encdec: EncryptorDecryptor::new_with_random_key().unwrap(),
+        };
+        let record = test_record('C', &ii.encdec);
+        let bso = record
+            .clone()
+            .into_test_incoming_bso(&ii.encdec, Default::default());
+        do_test_staged_to_mirror(&ii, &tx, record, bso, "ibans_mirror");
+    }
+
+    #[test]
+    fn test_find_dupe() {
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction().expect("should get tx");
+        let ii = IncomingIbansImpl {
+            encdec: EncryptorDecryptor::new_with_random_key().unwrap(),
+        };
+        let local_record = test_record('C', &ii.encdec);
+        let local_guid = local_record.guid.clone();
+        ii.insert_local_record(&tx, local_record.clone()).unwrap();
+
+        // The same IBAN incoming with a different guid should find the local
+        // one, even though the encrypted values differ.
+        let mut incoming_record = test_record('C', &ii.encdec);
+        assert_ne!(local_record.iban_enc, incoming_record.iban_enc);
+        incoming_record.guid = SyncGuid::random();
+        let dupe = ii.get_local_dupe(&tx, &incoming_record).unwrap().unwrap();
+        assert_eq!(dupe.guid, local_guid);
+
+        // But a different IBAN with the same name and last 4 isn't a dupe.
+        incoming_record.iban_enc = ii.encdec.encrypt("XX00370400440532013000", "iban").unwrap();
+        assert!(ii.get_local_dupe(&tx, &incoming_record).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_change_record_guid() -> Result<()> {
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction()?;
+        let ii = IncomingIbansImpl {
+            encdec: EncryptorDecryptor::new_with_random_key().unwrap(),
+        };
+
+        ii.insert_local_record(&tx, test_record('C', &ii.encdec))?;
+
+        ii.change_record_guid(
+            &tx,
+            &SyncGuid::new(&expand_test_guid('C')),
+            &SyncGuid::new(&expand_test_guid('B')),
+        )?;
+        tx.commit()?;
+        assert!(get_iban(&db.writer, &expand_test_guid('C').into()).is_err());
+        assert!(get_iban(&db.writer, &expand_test_guid('B').into()).is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/.
+*/
+
+pub mod incoming;
+pub mod outgoing;
+
+use super::engine::{ConfigSyncEngine, EngineConfig, SyncEngineStorageImpl};
+use super::{
+    MergeResult, Metadata, ProcessIncomingRecordImpl, ProcessOutgoingRecordImpl, SyncRecord,
+    UnknownFields,
+};
+use crate::db::models::iban::InternalIban;
+use crate::encryption::EncryptorDecryptor;
+use crate::error::*;
+use crate::sync_merge_field_check;
+use incoming::IncomingIbansImpl;
+use outgoing::OutgoingIbansImpl;
+use rusqlite::Transaction;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use sync_guid::Guid;
+use types::Timestamp;
+
+// The engine. Note that there's no `SyncEngineId` for IBANs yet, so this
+// isn't offered to the sync manager - it will be once the collection exists.
+pub(crate) fn create_engine(store: Arc<crate::Store>) -> ConfigSyncEngine<InternalIban> {
+    ConfigSyncEngine::new(
+        EngineConfig {
+            namespace: "ibans".to_string(),
+            collection: "ibans".into(),
+        },
+        store,
+        Box::new(IbansEngineStorageImpl {}),
+    )
+}
+
+pub(super) struct IbansEngineStorageImpl {}
+
+impl SyncEngineStorageImpl<InternalIban> for IbansEngineStorageImpl {
+    fn get_incoming_impl(
+        &self,
+        enc_key: &Option<String>,
+    ) -> Result<Box<dyn ProcessIncomingRecordImpl<Record = InternalIban>>> {
+        let enc_key = match enc_key {
+            None => return Err(Error::MissingEncryptionKey),
+            Some(enc_key) => enc_key,
+        };
+        let encdec = EncryptorDecryptor::new(enc_key)?;
+        Ok(Box::new(IncomingIbansImpl { encdec }))
+    }
+
+    fn reset_storage(&self, tx: &Transaction<'_>) -> Result<()> {
+        tx.execute_batch(
+            "DELETE FROM ibans_mirror;
+            DELETE FROM ibans_tombstones;",
+        )?;
+        Ok(())
+    }
+
+    fn get_outgoing_impl(
+        &self,
+        enc_key: &Option<String>,
+    ) -> Result<Box<dyn ProcessOutgoingRecordImpl<Record = InternalIban>>> {
+        let enc_key = match enc_key {
+            None => return Err(Error::MissingEncryptionKey),
+            Some(enc_key) => enc_key,
+        };
+        let encdec = EncryptorDecryptor::new(enc_key)?;
+        Ok(Box::new(OutgoingIbansImpl { encdec }))
+    }
+}
+
+// These structs are a representation of what's stored on the sync server for non-tombstone records.
+// (The actual server doesn't have `id` in the payload but instead in the envelope)
+// We use the same shape as the other autofill collections, including the
+// `entry` object.
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub(crate) struct IbanPayload {
+    id: Guid,
+
+    pub(super) entry: PayloadEntry,
+}
+
+// As for credit-cards, the sync payload contains the "unencrypted" IBAN,
+// while our internal structs have the iban_enc/iban_last_4 pair.
+#[derive(Default, Debug, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub(super) struct PayloadEntry {
+    pub iban_name: String,
+    pub iban: String,
+    // metadata (which isn't kebab-case, for consistency with the other collections)
+    #[serde(rename = "timeCreated")]
+    pub time_created: Timestamp,
+    #[serde(rename = "timeLastUsed")]
+    pub time_last_used: Timestamp,
+    #[serde(rename = "timeLastModified")]
+    pub time_last_modified: Timestamp,
+    #[serde(rename = "timesUsed")]
+    pub times_used: i64,
+    pub version: u32, // always 1 for IBANs
+    // Fields that the current schema did not expect, we store them only internally
+    // to round-trip them back to sync without processing them in any way
+    #[serde(flatten)]
+    pub unknown_fields: UnknownFields,
+}
+
+impl InternalIban {
+    fn from_payload(p: IbanPayload, encdec: &EncryptorDecryptor) -> Result<Self> {
+        if p.entry.version != 1 {
+            // when new versions are introduced we will start accepting and
+            // converting old ones - but 1 is the lowest we support.
+            return Err(Error::InvalidSyncPayload(format!(
+                "invalid version - {}",
+                p.entry.version
+            )));
+        }
+        // need to encrypt the cleartext in the sync record.
+        let iban_enc = encdec.encrypt(&p.entry.iban, "iban")?;
+        let iban_last_4 = get_last_4(&p.entry.iban);
+
+        Ok(InternalIban {
+            guid: p.id,
+            iban_name: p.entry.iban_name,
+            iban_enc,
+            iban_last_4,
+            metadata: Metadata {
+                time_created: p.entry.time_created,
+                time_last_used: p.entry.time_last_used,
+                time_last_modified: p.entry.time_last_modified,
+                times_used: p.entry.times_used,
+                sync_change_counter: 0,
+            },
+        })
+    }
+
+    pub(crate) fn into_payload(self, encdec: &EncryptorDecryptor) -> Result<IbanPayload> {
+        let iban = encdec.decrypt(&self.iban_enc, "iban")?;
+        Ok(IbanPayload {
+            id: self.guid,
+            entry: PayloadEntry {
+                iban_name: self.iban_name,
+                iban,
+                time_created: self.metadata.time_created,
+                time_last_used: self.metadata.time_last_used,
+                time_last_modified: self.metadata.time_last_modified,
+                times_used: self.metadata.times_used,
+                version: 1,
+                unknown_fields: Default::default(),
+            },
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn into_test_incoming_bso(
+        self,
+        encdec: &EncryptorDecryptor,
+        unknown_fields: UnknownFields,
+    ) -> super::IncomingBso {
+        let mut payload = self.into_payload(encdec).expect("is json");
+        payload.entry.unknown_fields = unknown_fields;
+        super::IncomingBso::from_test_content(payload)
+    }
+}
+
+impl SyncRecord for InternalIban {
+    fn record_name() -> &'static str {
+        "Iban"
+    }
+
+    fn id(&self) -> &Guid {
+        &self.guid
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
+    /// Performs a three-way merge between an incoming, local, and mirror record.
+    /// If a merge cannot be successfully completed (ie, if we find the same
+    /// field has changed both locally and remotely since the last sync), the
+    /// local record data is returned with a new guid and updated sync metadata.
+    /// Note that mirror being None is an edge-case and typically means first
+    /// sync since a "reset" (eg, disconnecting and reconnecting.
+    #[allow(clippy::cognitive_complexity)] // Looks like clippy considers this after macro-expansion...
+    fn merge(incoming: &Self, local: &Self, mirror: &Option<Self>) -> MergeResult<Self> {
+        let mut merged_record: Self = Default::default();
+        // guids must be identical
+        assert_eq!(incoming.guid, local.guid);
+
+        if let Some(m) = mirror {
+            assert_eq!(incoming.guid, m.guid)
+        };
+
+        merged_record.guid = incoming.guid.clone();
+
+        sync_merge_field_check!(iban_name, incoming, local, mirror, merged_record);
+        // XXX - as for credit-cards, this would allow merging a locally
+        // changed iban_enc with a remotely changed iban_last_4.
+        sync_merge_field_check!(iban_enc, incoming, local, mirror, merged_record);
+        sync_merge_field_check!(iban_last_4, incoming, local, mirror, merged_record);
+
+        merged_record.metadata = incoming.metadata;
+        merged_record
+            .metadata
+            .merge(&local.metadata, mirror.as_ref().map(|m| m.metadata()));
+
+        MergeResult::Merged {
+            merged: merged_record,
+        }
+    }
+}
+
+/// Returns a with the given local record's data but with a new guid and
+/// fresh sync metadata.
+fn get_forked_record(local_record: InternalIban) -> InternalIban {
+    let mut local_record_data = local_record;
+    local_record_data.guid = Guid::random();
+    local_record_data.metadata.time_created = Timestamp::now();
+    local_record_data.metadata.time_last_used = Timestamp::now();
+    local_record_data.metadata.time_last_modified = Timestamp::now();
+    local_record_data.metadata.times_used = 0;
+    local_record_data.metadata.sync_change_counter = 1;
+
+    local_record_data
+}
+
+fn get_last_4(v: &str) -> String {
+    v.chars()
+        .rev()
+        .take(4)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect::<String>()
+}
+
+#[test]
+fn test_to_from_payload() {
+    let key = crate::encryption::create_autofill_key().unwrap();
+    let iban = "GB82WEST12345698765432";
+    let iban_enc = crate::encryption::encrypt_string(key.clone(), iban.to_string()).unwrap();
+    let internal = InternalIban {
+        iban_name: "Joint account".to_string(),
+        iban_enc,
+        iban_last_4: "5432".to_string(),
+        ..Default::default()
+    };
+    let encdec = EncryptorDecryptor::new(&key).unwrap();
+    let payload: IbanPayload = internal.clone().into_payload(&encdec).unwrap();
+
+    assert_eq!(payload.id, internal.guid);
+    assert_eq!(payload.entry.iban_name, "Joint account".to_string());
+    assert_eq!(payload.entry.iban, iban.to_string());
+
+    // and back.
+    let internal2 = InternalIban::from_payload(payload, &encdec).unwrap();
+    assert_eq!(internal2.guid, internal.guid);
+    assert_eq!(internal2.iban_name, "Joint account".to_string());
+    assert_eq!(internal2.iban_last_4, internal.iban_last_4);
+    // The decrypted IBAN should be the same, but the encrypted value should not.
+    assert_eq!(
+        crate::encryption::decrypt_string(key, internal2.iban_enc.clone()).unwrap(),
+        iban
+    );
+    assert_ne!(internal2.iban_enc, internal.iban_enc);
+}
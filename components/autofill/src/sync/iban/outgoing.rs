@@ -0,0 +1,206 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/.
+*/
+
+use crate::db::models::iban::InternalIban;
+use crate::db::schema::IBAN_COMMON_COLS;
+use crate::encryption::EncryptorDecryptor;
+use crate::error::*;
+use crate::sync::common::*;
+use crate::sync::{iban::IbanPayload, OutgoingBso, ProcessOutgoingRecordImpl};
+use rusqlite::{Row, Transaction};
+use sync_guid::Guid as SyncGuid;
+
+const DATA_TABLE_NAME: &str = "ibans_data";
+const MIRROR_TABLE_NAME: &str = "ibans_mirror";
+const STAGING_TABLE_NAME: &str = "ibans_sync_outgoing_staging";
+
+pub(super) struct OutgoingIbansImpl {
+    pub(super) encdec: EncryptorDecryptor,
+}
+
+impl ProcessOutgoingRecordImpl for OutgoingIbansImpl {
+    type Record = InternalIban;
+
+    /// Gets the local records that have unsynced changes or don't have corresponding mirror
+    /// records and upserts them to the mirror table
+    fn fetch_outgoing_records(&self, tx: &Transaction<'_>) -> anyhow::Result<Vec<OutgoingBso>> {
+        let data_sql = format!(
+            "SELECT
+                l.{common_cols},
+                m.payload,
+                l.sync_change_counter
+            FROM ibans_data l
+            LEFT JOIN ibans_mirror m
+            ON l.guid = m.guid
+            WHERE sync_change_counter > 0
+                OR l.guid NOT IN (
+                    SELECT m.guid
+                    FROM ibans_mirror m
+                )",
+            common_cols = IBAN_COMMON_COLS,
+        );
+        let record_from_data_row: &dyn Fn(&Row<'_>) -> Result<(OutgoingBso, i64)> = &|row| {
+            let mut record = InternalIban::from_row(row)?.into_payload(&self.encdec)?;
+            // If the server had unknown fields we fetch it and add it to the record
+            if let Some(enc_s) = row.get::<_, Option<String>>("payload")? {
+                // The full payload in the IBANs mirror is encrypted
+                let mirror_payload: IbanPayload =
+                    serde_json::from_str(&self.encdec.decrypt(&enc_s, "iban payload")?)?;
+                record.entry.unknown_fields = mirror_payload.entry.unknown_fields;
+            };
+
+            Ok((
+                OutgoingBso::from_content_with_id(record)?,
+                row.get::<_, i64>("sync_change_counter")?,
+            ))
+        };
+
+        let tombstones_sql = "SELECT guid FROM ibans_tombstones";
+
+        // save outgoing records to the mirror table
+        let staging_records = common_get_outgoing_staging_records(
+            tx,
+            &data_sql,
+            tombstones_sql,
+            record_from_data_row,
+        )?
+        .into_iter()
+        .map(|(bso, change_counter)| {
+            // Turn the record into an encrypted repr to save in the mirror.
+            let encrypted = self.encdec.encrypt(&bso.payload, "bso payload")?;
+            Ok((bso.envelope.id, encrypted, change_counter))
+        })
+        .collect::<Result<_>>()?;
+        common_save_outgoing_records(tx, STAGING_TABLE_NAME, staging_records)?;
+
+        // return outgoing changes
+        Ok(
+            common_get_outgoing_records(tx, &data_sql, tombstones_sql, record_from_data_row)?
+                .into_iter()
+                .map(|(bso, _change_counter)| bso)
+                .collect::<Vec<OutgoingBso>>(),
+        )
+    }
+
+    fn finish_synced_items(
+        &self,
+        tx: &Transaction<'_>,
+        records_synced: Vec<SyncGuid>,
+    ) -> anyhow::Result<()> {
+        common_finish_synced_items(
+            tx,
+            DATA_TABLE_NAME,
+            MIRROR_TABLE_NAME,
+            STAGING_TABLE_NAME,
+            records_synced,
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::ibans::{add_internal_iban, tests::test_insert_mirror_record};
+    use crate::sync::{common::tests::*, test::new_syncable_mem_db, UnknownFields};
+    use serde_json::{json, Map, Value};
+    use types::Timestamp;
+
+    fn test_record(encdec: &EncryptorDecryptor) -> InternalIban {
+        InternalIban {
+            guid: SyncGuid::new(&expand_test_guid('C')),
+            iban_name: "Savings".to_string(),
+            iban_enc: encdec.encrypt("DE89370400440532013000", "iban").unwrap(),
+            iban_last_4: "3000".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_outgoing_never_synced() {
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction().expect("should get tx");
+        let io = OutgoingIbansImpl {
+            encdec: EncryptorDecryptor::new_with_random_key().unwrap(),
+        };
+        let test_record = test_record(&io.encdec);
+
+        assert!(add_internal_iban(&tx, &test_record).is_ok());
+        do_test_outgoing_never_synced(
+            &tx,
+            &io,
+            &test_record.guid,
+            DATA_TABLE_NAME,
+            MIRROR_TABLE_NAME,
+            STAGING_TABLE_NAME,
+        );
+    }
+
+    #[test]
+    fn test_outgoing_tombstone() {
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction().expect("should get tx");
+        let io = OutgoingIbansImpl {
+            encdec: EncryptorDecryptor::new_with_random_key().unwrap(),
+        };
+        let test_record = test_record(&io.encdec);
+
+        tx.execute(
+            "INSERT INTO ibans_tombstones (guid, time_deleted)
+             VALUES (:guid, :time_deleted)",
+            rusqlite::named_params! {
+                ":guid": test_record.guid,
+                ":time_deleted": Timestamp::now(),
+            },
+        )
+        .expect("should insert tombstone");
+        do_test_outgoing_tombstone(
+            &tx,
+            &io,
+            &test_record.guid,
+            DATA_TABLE_NAME,
+            MIRROR_TABLE_NAME,
+            STAGING_TABLE_NAME,
+        );
+    }
+
+    #[test]
+    fn test_outgoing_roundtrip_unknown() {
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction().expect("should get tx");
+        let io = OutgoingIbansImpl {
+            encdec: EncryptorDecryptor::new_with_random_key().unwrap(),
+        };
+
+        // create synced record with non-zero sync_change_counter
+        let mut test_record = test_record(&io.encdec);
+        test_record.metadata.sync_change_counter = 2;
+        assert!(add_internal_iban(&tx, &test_record).is_ok());
+
+        let unknown_fields: UnknownFields =
+            serde_json::from_value(json! {{ "foo": "bar" }}).unwrap();
+        // The mirror payload is encrypted in reality, so we encrypt it here.
+        let mut bso = test_record
+            .clone()
+            .into_test_incoming_bso(&io.encdec, unknown_fields);
+        bso.payload = io.encdec.encrypt(&bso.payload, "bso payload").unwrap();
+        test_insert_mirror_record(&tx, bso);
+
+        let outgoing = &io.fetch_outgoing_records(&tx).unwrap();
+        let bso_payload: Map<String, Value> = serde_json::from_str(&outgoing[0].payload).unwrap();
+        let entry = bso_payload.get("entry").unwrap();
+        assert_eq!(entry.get("foo").unwrap(), "bar");
+        assert_eq!(entry.get("iban").unwrap(), "DE89370400440532013000");
+        do_test_outgoing_synced_with_local_change(
+            &tx,
+            &io,
+            &test_record.guid,
+            DATA_TABLE_NAME,
+            MIRROR_TABLE_NAME,
+            STAGING_TABLE_NAME,
+        );
+    }
+}
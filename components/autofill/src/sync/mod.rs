@@ -7,6 +7,7 @@ pub mod address;
 mod common;
 pub mod credit_card;
 pub mod engine;
+pub mod iban;
 
 pub(crate) use crate::db::models::Metadata;
 use crate::error::Result;
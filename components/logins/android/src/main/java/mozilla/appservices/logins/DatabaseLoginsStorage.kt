@@ -43,6 +43,13 @@ class DatabaseLoginsStorage(dbPath: String) : AutoCloseable {
         this.store.wipeLocal()
     }
 
+    @Throws(LoginsApiException::class)
+    fun rekey(oldEncryptionKey: String, newEncryptionKey: String, canary: String, canaryText: String): UInt {
+        return writeQueryCounters.measure {
+            store.rekey(oldEncryptionKey, newEncryptionKey, canary, canaryText)
+        }
+    }
+
     @Throws(LoginsApiException::class)
     fun delete(id: String): Boolean {
         return writeQueryCounters.measure {
@@ -50,6 +57,27 @@ class DatabaseLoginsStorage(dbPath: String) : AutoCloseable {
         }
     }
 
+    @Throws(LoginsApiException::class)
+    fun setTrashRetention(retentionMs: Long) {
+        writeQueryCounters.measure {
+            store.setTrashRetention(retentionMs)
+        }
+    }
+
+    @Throws(LoginsApiException::class)
+    fun listDeleted(): List<DeletedLogin> {
+        return readQueryCounters.measure {
+            store.listDeleted()
+        }
+    }
+
+    @Throws(LoginsApiException::class)
+    fun undelete(id: String, encryptionKey: String): EncryptedLogin {
+        return writeQueryCounters.measure {
+            store.undelete(id, encryptionKey)
+        }
+    }
+
     @Throws(LoginsApiException::class)
     fun get(id: String): EncryptedLogin? {
         return readQueryCounters.measure {
@@ -78,6 +106,48 @@ class DatabaseLoginsStorage(dbPath: String) : AutoCloseable {
         }
     }
 
+    @Throws(LoginsApiException::class)
+    fun updateBreaches(breachesJson: String) {
+        writeQueryCounters.measure {
+            store.updateBreaches(breachesJson)
+        }
+    }
+
+    @Throws(LoginsApiException::class)
+    fun getBreachedLogins(): List<EncryptedLogin> {
+        return readQueryCounters.measure {
+            store.getBreachedLogins()
+        }
+    }
+
+    @Throws(LoginsApiException::class)
+    fun dismissBreachAlert(id: String) {
+        writeQueryCounters.measure {
+            store.dismissBreachAlert(id)
+        }
+    }
+
+    @Throws(LoginsApiException::class)
+    fun query(query: LoginQuery, encryptionKey: String? = null): List<EncryptedLogin> {
+        return readQueryCounters.measure {
+            store.query(query, encryptionKey)
+        }
+    }
+
+    @Throws(LoginsApiException::class)
+    fun getSyncConflicts(): List<SyncConflict> {
+        return readQueryCounters.measure {
+            store.getSyncConflicts()
+        }
+    }
+
+    @Throws(LoginsApiException::class)
+    fun audit(encryptionKey: String): LoginAudit {
+        return readQueryCounters.measure {
+            store.audit(encryptionKey)
+        }
+    }
+
     @Throws(LoginsApiException::class)
     fun findLoginToUpdate(look: LoginEntry, encryptionKey: String): Login? {
         return readQueryCounters.measure {
@@ -99,6 +169,27 @@ class DatabaseLoginsStorage(dbPath: String) : AutoCloseable {
         }
     }
 
+    @Throws(LoginsApiException::class)
+    fun setPasswordHistoryLimit(limit: UInt) {
+        writeQueryCounters.measure {
+            store.setPasswordHistoryLimit(limit)
+        }
+    }
+
+    @Throws(LoginsApiException::class)
+    fun getPasswordHistory(id: String, encryptionKey: String): List<PasswordHistoryEntry> {
+        return readQueryCounters.measure {
+            store.getPasswordHistory(id, encryptionKey)
+        }
+    }
+
+    @Throws(LoginsApiException::class)
+    fun restorePassword(id: String, historyId: Long, encryptionKey: String): EncryptedLogin {
+        return writeQueryCounters.measure {
+            store.restorePassword(id, historyId, encryptionKey)
+        }
+    }
+
     @Throws(LoginsApiException::class)
     fun addOrUpdate(entry: LoginEntry, encryptionKey: String): EncryptedLogin {
         return writeQueryCounters.measure {
@@ -106,6 +197,20 @@ class DatabaseLoginsStorage(dbPath: String) : AutoCloseable {
         }
     }
 
+    @Throws(LoginsApiException::class)
+    fun importCsv(csv: String, encryptionKey: String): List<CsvImportRowResult> {
+        return writeQueryCounters.measure {
+            store.importCsv(csv, encryptionKey)
+        }
+    }
+
+    @Throws(LoginsApiException::class)
+    fun exportCsv(encryptionKey: String): String {
+        return readQueryCounters.measure {
+            store.exportCsv(encryptionKey)
+        }
+    }
+
     fun registerWithSyncManager() {
         return store.registerWithSyncManager()
     }
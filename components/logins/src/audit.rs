@@ -0,0 +1,326 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! # Password audits
+//!
+//! Password manager UIs want to point out logins with passwords which are reused across sites, or
+//! which are obviously weak. Doing that needs every password decrypted, so we do it here rather
+//! than having the app decrypt every login itself. Only `EncryptedLogin`s are returned, so no
+//! plaintext crosses the FFI.
+
+use crate::encryption::EncryptorDecryptor;
+use crate::error::*;
+use crate::login::EncryptedLogin;
+use std::collections::{HashMap, HashSet};
+use url::{Host, Url};
+
+/// Passwords shorter than this are considered weak.
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// The public suffixes we know about, from the Public Suffix List. We don't ship the whole list,
+/// so this only has suffixes whose subdomains we're confident about - for example `uk` is here,
+/// but so is every second-level suffix under it, like `co.uk`, and there's no `jp` or `io`, which
+/// have too many. Hosts which aren't under one of these are compared in full.
+const PUBLIC_SUFFIXES: &[&str] = &[
+    // Generic TLDs, and the services under them which give each user a subdomain.
+    "com",
+    "appspot.com",
+    "blogspot.com",
+    "cloudfront.net",
+    "firebaseapp.com",
+    "herokuapp.com",
+    "azurewebsites.net",
+    "net",
+    "org",
+    "edu",
+    "gov",
+    "mil",
+    "int",
+    "github.io",
+    "gitlab.io",
+    "netlify.app",
+    "vercel.app",
+    "web.app",
+    "pages.dev",
+    // Country code TLDs.
+    "uk",
+    "ac.uk",
+    "co.uk",
+    "gov.uk",
+    "ltd.uk",
+    "me.uk",
+    "net.uk",
+    "nhs.uk",
+    "org.uk",
+    "plc.uk",
+    "police.uk",
+    "sch.uk",
+    "com.au",
+    "edu.au",
+    "gov.au",
+    "net.au",
+    "org.au",
+    "ac.nz",
+    "co.nz",
+    "govt.nz",
+    "net.nz",
+    "org.nz",
+    "co.jp",
+    "ac.jp",
+    "be",
+    "ch",
+    "de",
+    "dk",
+    "eu",
+    "fi",
+    "nl",
+];
+
+/// Why a password was considered weak.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WeakPasswordReason {
+    /// The password is shorter than 8 characters.
+    TooShort,
+    /// The password is a single character repeated, or a run like `12345678` or `abcdefgh`.
+    Sequence,
+    /// The password only uses one kind of character, such as only digits or only lowercase
+    /// letters.
+    SingleCharacterClass,
+}
+
+/// A login with a weak password.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeakPasswordLogin {
+    pub login: EncryptedLogin,
+    pub reason: WeakPasswordReason,
+}
+
+/// Logins for different sites which all use the same password.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReusedPasswordGroup {
+    pub logins: Vec<EncryptedLogin>,
+}
+
+/// The result of `LoginStore::audit()`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LoginAudit {
+    pub reused_passwords: Vec<ReusedPasswordGroup>,
+    pub weak_passwords: Vec<WeakPasswordLogin>,
+}
+
+/// Audit the passwords of `logins`, decrypting each one once.
+pub(crate) fn audit_logins(
+    logins: Vec<EncryptedLogin>,
+    encdec: &EncryptorDecryptor,
+) -> Result<LoginAudit> {
+    let mut weak_passwords = Vec::new();
+    let mut by_password: HashMap<String, Vec<EncryptedLogin>> = HashMap::new();
+    for login in logins {
+        let password = login.decrypt_fields(encdec)?.password;
+        if let Some(reason) = weak_password_reason(&password) {
+            weak_passwords.push(WeakPasswordLogin {
+                login: login.clone(),
+                reason,
+            });
+        }
+        by_password.entry(password).or_default().push(login);
+    }
+    let mut reused_passwords = by_password
+        .into_values()
+        .filter(|logins| spans_multiple_sites(logins))
+        .map(|mut logins| {
+            logins.sort_by(|a, b| a.fields.origin.cmp(&b.fields.origin));
+            ReusedPasswordGroup { logins }
+        })
+        .collect::<Vec<_>>();
+    // The HashMap gives us an arbitrary order, so make it something stable.
+    reused_passwords.sort_by(|a, b| a.logins[0].fields.origin.cmp(&b.logins[0].fields.origin));
+    weak_passwords.sort_by(|a, b| a.login.fields.origin.cmp(&b.login.fields.origin));
+    Ok(LoginAudit {
+        reused_passwords,
+        weak_passwords,
+    })
+}
+
+// Whether any two of these logins are for different sites - that is, have different registrable
+// domains. So `https://www.example.com` and `https://login.example.com` sharing a password is fine.
+fn spans_multiple_sites(logins: &[EncryptedLogin]) -> bool {
+    logins
+        .iter()
+        .filter_map(|login| site(&Url::parse(&login.fields.origin).ok()?))
+        .collect::<HashSet<_>>()
+        .len()
+        > 1
+}
+
+// The site of the URL's host: its registrable domain (or "eTLD+1"), such as `example.co.uk` for
+// `https://www.example.co.uk`, if it's under one of `PUBLIC_SUFFIXES`. Otherwise, rather than
+// guess, it's the whole host - so sibling subdomains of those are treated as different sites. IP
+// addresses are returned as they are.
+fn site(url: &Url) -> Option<String> {
+    let domain = match url.host()? {
+        Host::Domain(domain) => domain.trim_end_matches('.').to_ascii_lowercase(),
+        host => return Some(host.to_string()),
+    };
+    let labels = domain.split('.').collect::<Vec<_>>();
+    // The longest known suffix, which must leave at least one label for the registrable domain.
+    let suffix_len = (1..labels.len())
+        .rev()
+        .find(|&len| PUBLIC_SUFFIXES.contains(&labels[labels.len() - len..].join(".").as_str()));
+    Some(match suffix_len {
+        Some(len) => labels[labels.len() - len - 1..].join("."),
+        None => domain,
+    })
+}
+
+fn weak_password_reason(password: &str) -> Option<WeakPasswordReason> {
+    let chars = password.chars().collect::<Vec<_>>();
+    if chars.len() < MIN_PASSWORD_LENGTH {
+        return Some(WeakPasswordReason::TooShort);
+    }
+    // Every character is the same, or one more or less than the previous one.
+    let step = chars[1] as i64 - chars[0] as i64;
+    if step.abs() <= 1
+        && chars
+            .windows(2)
+            .all(|pair| pair[1] as i64 - pair[0] as i64 == step)
+    {
+        return Some(WeakPasswordReason::Sequence);
+    }
+    let classes: [fn(&char) -> bool; 3] = [
+        char::is_ascii_digit,
+        char::is_ascii_lowercase,
+        char::is_ascii_uppercase,
+    ];
+    if classes.iter().any(|class| chars.iter().all(class)) {
+        return Some(WeakPasswordReason::SingleCharacterClass);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::test_utils::{encrypt_struct, TEST_ENCRYPTOR};
+    use crate::{LoginFields, RecordFields, SecureLoginFields};
+
+    fn login(id: &str, origin: &str, password: &str) -> EncryptedLogin {
+        EncryptedLogin {
+            record: RecordFields {
+                id: id.into(),
+                ..Default::default()
+            },
+            fields: LoginFields {
+                origin: origin.into(),
+                form_action_origin: Some(origin.into()),
+                ..Default::default()
+            },
+            sec_fields: encrypt_struct(&SecureLoginFields {
+                username: "user".into(),
+                password: password.into(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_weak_password_reason() {
+        for (password, expected) in [
+            ("", Some(WeakPasswordReason::TooShort)),
+            ("aB3$xY9", Some(WeakPasswordReason::TooShort)),
+            ("aaaaaaaaaa", Some(WeakPasswordReason::Sequence)),
+            ("12345678", Some(WeakPasswordReason::Sequence)),
+            ("hgfedcba", Some(WeakPasswordReason::Sequence)),
+            (
+                "31415926535",
+                Some(WeakPasswordReason::SingleCharacterClass),
+            ),
+            (
+                "correcthorse",
+                Some(WeakPasswordReason::SingleCharacterClass),
+            ),
+            (
+                "CORRECTHORSE",
+                Some(WeakPasswordReason::SingleCharacterClass),
+            ),
+            ("correct horse", None),
+            ("Tr0ub4dor&3", None),
+            ("ééééééé1", None),
+        ] {
+            assert_eq!(weak_password_reason(password), expected, "{password}");
+        }
+    }
+
+    #[test]
+    fn test_site() {
+        for (origin, expected) in [
+            ("https://example.com", "example.com"),
+            ("https://www.example.com", "example.com"),
+            ("https://a.b.Example.COM.", "example.com"),
+            ("https://www.example.co.uk", "example.co.uk"),
+            ("https://example.co.uk", "example.co.uk"),
+            ("https://www.example.uk", "example.uk"),
+            ("https://example.com.au", "example.com.au"),
+            ("https://www.example.de", "example.de"),
+            // Each user has their own site.
+            ("https://user1.github.io", "user1.github.io"),
+            ("https://www.user1.github.io", "user1.github.io"),
+            ("https://someone.blogspot.com", "someone.blogspot.com"),
+            ("https://github.io", "github.io"),
+            // We don't know enough about these to guess.
+            ("https://www.example.ne.jp", "www.example.ne.jp"),
+            ("https://login.example.io", "login.example.io"),
+            ("https://co.uk", "co.uk"),
+            ("http://localhost:8080", "localhost"),
+            ("http://192.168.0.1", "192.168.0.1"),
+            ("http://[::1]", "[::1]"),
+        ] {
+            assert_eq!(
+                site(&Url::parse(origin).unwrap()).as_deref(),
+                Some(expected),
+                "{origin}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_audit() {
+        let logins = vec![
+            login("a", "https://example.com", "Sh4red-Pass"),
+            login("b", "https://login.example.com", "Sh4red-Pass"),
+            login("c", "https://example.org", "Sh4red-Pass"),
+            // Reused, but only within one site.
+            login("d", "https://example.net", "N0t-Reused"),
+            login("e", "https://www.example.net", "N0t-Reused"),
+            login("f", "https://weak.example.com", "password"),
+            login("g", "https://unique.example.com", "Un1que!Pass"),
+            // Sibling subdomains are the same site too.
+            login("h", "https://www.example.edu", "S1bling-Pass"),
+            login("i", "https://login.example.edu", "S1bling-Pass"),
+            // But different sites under the same public suffix aren't.
+            login("j", "https://one.co.uk", "Sh4red-Suffix"),
+            login("k", "https://two.co.uk", "Sh4red-Suffix"),
+            login("l", "https://user1.github.io", "Sh4red-Pages"),
+            login("m", "https://user2.github.io", "Sh4red-Pages"),
+            // IP addresses are compared exactly.
+            login("n", "http://192.168.0.1", "Sh4red-Router"),
+            login("o", "http://192.168.0.1:8080", "Sh4red-Router"),
+            login("p", "http://192.168.0.2", "Sh4red-Router"),
+        ];
+        let audit = audit_logins(logins, &TEST_ENCRYPTOR).unwrap();
+        fn ids(logins: &[EncryptedLogin]) -> Vec<&str> {
+            logins.iter().map(|l| l.record.id.as_str()).collect()
+        }
+        assert_eq!(audit.reused_passwords.len(), 4);
+        assert_eq!(ids(&audit.reused_passwords[0].logins), vec!["n", "o", "p"]);
+        assert_eq!(ids(&audit.reused_passwords[1].logins), vec!["a", "c", "b"]);
+        assert_eq!(ids(&audit.reused_passwords[2].logins), vec!["j", "k"]);
+        assert_eq!(ids(&audit.reused_passwords[3].logins), vec!["l", "m"]);
+        assert_eq!(audit.weak_passwords.len(), 1);
+        assert_eq!(audit.weak_passwords[0].login.record.id, "f");
+        assert_eq!(
+            audit.weak_passwords[0].reason,
+            WeakPasswordReason::SingleCharacterClass
+        );
+    }
+}
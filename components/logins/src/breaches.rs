@@ -0,0 +1,145 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! # Breach alerts
+//!
+//! Desktop Firefox warns users about logins for sites which have had a data breach since the
+//! password was last changed. The list of breaches comes from the `fxmonitor-breaches` Remote
+//! Settings collection - the app fetches that and hands us the records as JSON, which look like:
+//!
+//! ```json
+//! [{
+//!     "Name": "Adobe",
+//!     "Domain": "adobe.com",
+//!     "BreachDate": "2013-10-04",
+//!     "DataClasses": ["Email addresses", "Passwords"],
+//!     ...
+//! }]
+//! ```
+//!
+//! Like desktop, we only care about breaches which include passwords and have a domain. The
+//! matching of logins against breaches is done by `LoginDb::get_breached_logins()`.
+
+use crate::error::*;
+use serde_derive::*;
+
+/// A breach record as it appears in the Remote Settings collection. There are many more fields,
+/// but these are the only ones we use.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BreachRecord {
+    name: String,
+    #[serde(default)]
+    domain: String,
+    breach_date: String,
+    #[serde(default)]
+    data_classes: Vec<String>,
+}
+
+/// A breach, as we store it in the `loginsBreaches` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Breach {
+    pub name: String,
+    pub domain: String,
+    /// Milliseconds since the epoch, at midnight UTC on the day of the breach.
+    pub breach_date: i64,
+}
+
+/// Parse the JSON records from the Remote Settings collection. Records which aren't relevant or
+/// which we can't understand are skipped.
+pub(crate) fn parse_breaches(json: &str) -> Result<Vec<Breach>> {
+    let records: Vec<BreachRecord> = serde_json::from_str(json)?;
+    Ok(records
+        .into_iter()
+        .filter(|r| !r.domain.is_empty() && r.data_classes.iter().any(|c| c == "Passwords"))
+        .filter_map(|r| match parse_date_ms(&r.breach_date) {
+            Some(breach_date) => Some(Breach {
+                name: r.name,
+                domain: r.domain,
+                breach_date,
+            }),
+            None => {
+                log::warn!("Ignoring breach with an invalid date");
+                None
+            }
+        })
+        .collect())
+}
+
+/// Parse a `YYYY-MM-DD` date (optionally followed by a time, which is ignored) into milliseconds
+/// since the epoch at midnight UTC.
+fn parse_date_ms(date: &str) -> Option<i64> {
+    let mut parts = date.get(..10)?.split('-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // `days_from_civil()` from http://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    Some(days * 24 * 60 * 60 * 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date_ms("1970-01-01"), Some(0));
+        assert_eq!(parse_date_ms("2013-10-04"), Some(1_380_844_800_000));
+        assert_eq!(parse_date_ms("2000-02-29T12:34:56Z"), Some(951_782_400_000));
+        assert_eq!(parse_date_ms("2013-13-04"), None);
+        assert_eq!(parse_date_ms("2013-10"), None);
+        assert_eq!(parse_date_ms("not a date"), None);
+    }
+
+    #[test]
+    fn test_parse_breaches() {
+        let json = r#"[
+            {
+                "Name": "Adobe",
+                "Domain": "adobe.com",
+                "BreachDate": "2013-10-04",
+                "AddedDate": "2013-12-04T00:00Z",
+                "DataClasses": ["Email addresses", "Passwords"],
+                "id": "abc",
+                "last_modified": 1
+            },
+            {
+                "Name": "NoPasswords",
+                "Domain": "example.com",
+                "BreachDate": "2013-10-04",
+                "DataClasses": ["Email addresses"]
+            },
+            {
+                "Name": "NoDomain",
+                "Domain": "",
+                "BreachDate": "2013-10-04",
+                "DataClasses": ["Passwords"]
+            },
+            {
+                "Name": "BadDate",
+                "Domain": "example.com",
+                "BreachDate": "yesterday",
+                "DataClasses": ["Passwords"]
+            }
+        ]"#;
+        assert_eq!(
+            parse_breaches(json).unwrap(),
+            vec![Breach {
+                name: "Adobe".into(),
+                domain: "adobe.com".into(),
+                breach_date: 1_380_844_800_000,
+            }]
+        );
+        assert!(parse_breaches("{}").is_err());
+    }
+}
@@ -0,0 +1,363 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! # CSV import and export
+//!
+//! Desktop Firefox can export and import logins as a CSV file with the columns:
+//!
+//! `url,username,password,httpRealm,formActionOrigin,guid,timeCreated,timeLastUsed,timePasswordChanged`
+//!
+//! Other browsers (eg, Chrome) write a subset of these columns, possibly alongside columns we
+//! don't care about, so on import we only require `url` and `password` and find the columns
+//! by name. On export we write exactly what desktop writes, so the file can be imported there.
+//!
+//! This module only deals with the CSV format itself - the logic for merging imported
+//! logins with existing ones lives with the rest of the DB code in `LoginDb::import_csv_logins()`.
+
+use crate::error::*;
+use crate::login::{Login, LoginEntry, LoginFields, SecureLoginFields};
+
+/// The columns desktop exports, in the order it exports them.
+const EXPORT_COLUMNS: [&str; 9] = [
+    "url",
+    "username",
+    "password",
+    "httpRealm",
+    "formActionOrigin",
+    "guid",
+    "timeCreated",
+    "timeLastUsed",
+    "timePasswordChanged",
+];
+
+/// What happened to a single row when importing a CSV file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsvImportOutcome {
+    /// The row was added as a new login with this id.
+    Added { id: String },
+    /// The row matched an existing login, which had its password updated.
+    Updated { id: String },
+    /// The row matched an existing login, which was left alone.
+    SkippedDupe { id: String },
+    /// The row could not be imported.
+    Invalid { reason: String },
+}
+
+/// The result of importing one row of a CSV file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvImportRowResult {
+    /// The line of the file this row started on. The header is line 1.
+    pub line: u32,
+    pub outcome: CsvImportOutcome,
+}
+
+/// A CSV row which has been parsed, but not yet validated or checked for dupes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CsvLogin {
+    pub entry: LoginEntry,
+    pub guid: Option<String>,
+    pub time_created: Option<i64>,
+    pub time_last_used: Option<i64>,
+    pub time_password_changed: Option<i64>,
+}
+
+/// The rows of a CSV file along with the line they started on. Rows which we couldn't make sense
+/// of have the reason as the error.
+pub(crate) type CsvRows = Vec<(u32, std::result::Result<CsvLogin, String>)>;
+
+/// Parse the text of a CSV file into logins.
+///
+/// An error is returned if the file as a whole is unusable. Problems with individual rows are
+/// reported in the result for that row.
+pub(crate) fn parse_logins(text: &str) -> Result<CsvRows> {
+    let mut records = read_records(text)?.into_iter();
+    let header = match records.next() {
+        Some((_, header)) => header,
+        None => return Ok(vec![]),
+    };
+    let column = |name: &str| header.iter().position(|h| h.trim() == name);
+    let (url_col, password_col) = match (column("url"), column("password")) {
+        (Some(url_col), Some(password_col)) => (url_col, password_col),
+        _ => {
+            return Err(Error::InvalidCsv(
+                "the header must have `url` and `password` columns".into(),
+            ))
+        }
+    };
+    let username_col = column("username");
+    let http_realm_col = column("httpRealm");
+    let form_action_origin_col = column("formActionOrigin");
+    let guid_col = column("guid");
+    let time_created_col = column("timeCreated");
+    let time_last_used_col = column("timeLastUsed");
+    let time_password_changed_col = column("timePasswordChanged");
+
+    Ok(records
+        .map(|(line, record)| {
+            if record.len() != header.len() {
+                return (
+                    line,
+                    Err(format!(
+                        "expected {} fields but found {}",
+                        header.len(),
+                        record.len()
+                    )),
+                );
+            }
+            let get = |col: Option<usize>| col.map(|i| record[i].clone()).filter(|s| !s.is_empty());
+            let timestamp = |col: Option<usize>| {
+                get(col)
+                    .and_then(|s| s.trim().parse::<i64>().ok())
+                    .filter(|t| *t > 0)
+            };
+            let http_realm = get(http_realm_col);
+            // Desktop uses an empty `formActionOrigin` for form logins where the action is
+            // unknown, which is also what we get when neither column is set.
+            let form_action_origin =
+                get(form_action_origin_col).or_else(|| http_realm.is_none().then(String::new));
+            let login = CsvLogin {
+                entry: LoginEntry {
+                    fields: LoginFields {
+                        origin: get(Some(url_col)).unwrap_or_default(),
+                        http_realm,
+                        form_action_origin,
+                        ..Default::default()
+                    },
+                    sec_fields: SecureLoginFields {
+                        username: get(username_col).unwrap_or_default(),
+                        password: get(Some(password_col)).unwrap_or_default(),
+                    },
+                },
+                guid: get(guid_col),
+                time_created: timestamp(time_created_col),
+                time_last_used: timestamp(time_last_used_col),
+                time_password_changed: timestamp(time_password_changed_col),
+            };
+            (line, Ok(login))
+        })
+        .collect())
+}
+
+/// Write logins as CSV text in the format desktop exports.
+pub(crate) fn logins_to_csv(logins: &[Login]) -> String {
+    let mut out = String::new();
+    write_record(&mut out, EXPORT_COLUMNS);
+    for login in logins {
+        let time_created = login.record.time_created.to_string();
+        let time_last_used = login.record.time_last_used.to_string();
+        let time_password_changed = login.record.time_password_changed.to_string();
+        write_record(
+            &mut out,
+            [
+                login.fields.origin.as_str(),
+                &login.sec_fields.username,
+                &login.sec_fields.password,
+                login.fields.http_realm.as_deref().unwrap_or_default(),
+                login
+                    .fields
+                    .form_action_origin
+                    .as_deref()
+                    .unwrap_or_default(),
+                &login.record.id,
+                &time_created,
+                &time_last_used,
+                &time_password_changed,
+            ],
+        );
+    }
+    out
+}
+
+// Like desktop, we quote every field rather than trying to work out which ones need it.
+fn write_record<'a>(out: &mut String, fields: impl IntoIterator<Item = &'a str>) {
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push('"');
+        out.push_str(&field.replace('"', "\"\""));
+        out.push('"');
+    }
+    out.push_str("\r\n");
+}
+
+/// Split CSV text into records as described by RFC 4180, returning each one with the line it
+/// started on. Blank lines are skipped.
+fn read_records(text: &str) -> Result<Vec<(u32, Vec<String>)>> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    field.push(c);
+                }
+            }
+            continue;
+        }
+        match c {
+            '"' => in_quotes = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            // The `\n` which follows will end the record.
+            '\r' if chars.peek() == Some(&'\n') => (),
+            '\r' | '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push((record_line, std::mem::take(&mut record)));
+                line += 1;
+                record_line = line;
+            }
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(Error::InvalidCsv(format!(
+            "unterminated quoted field on line {record_line}"
+        )));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push((record_line, record));
+    }
+    records.retain(|(_, record)| !(record.len() == 1 && record[0].is_empty()));
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordFields;
+
+    #[test]
+    fn test_read_records() {
+        let text = "\u{feff}a,b,c\r\n1,\"2, two\",\"3 \"\"three\"\"\"\n\n\"multi\nline\",,x\ny,z";
+        let expected: Vec<(u32, Vec<String>)> = vec![
+            (1, vec!["a".into(), "b".into(), "c".into()]),
+            (2, vec!["1".into(), "2, two".into(), "3 \"three\"".into()]),
+            (4, vec!["multi\nline".into(), "".into(), "x".into()]),
+            (6, vec!["y".into(), "z".into()]),
+        ];
+        assert_eq!(read_records(text).unwrap(), expected);
+        assert!(matches!(
+            read_records("a,\"b\nc"),
+            Err(Error::InvalidCsv(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_desktop() {
+        let text = "\"url\",\"username\",\"password\",\"httpRealm\",\"formActionOrigin\",\"guid\",\"timeCreated\",\"timeLastUsed\",\"timePasswordChanged\"
+\"https://example.com\",\"user\",\"pass\",\"\",\"https://example.com\",\"{guid-1}\",\"1000\",\"2000\",\"3000\"
+\"https://example.com\",\"\",\"pass\",\"realm\",\"\",\"\",\"\",\"\",\"\"
+\"https://example.com\",\"user\"
+";
+        let rows = parse_logins(text).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows[0],
+            (
+                2,
+                Ok(CsvLogin {
+                    entry: LoginEntry {
+                        fields: LoginFields {
+                            origin: "https://example.com".into(),
+                            form_action_origin: Some("https://example.com".into()),
+                            ..Default::default()
+                        },
+                        sec_fields: SecureLoginFields {
+                            username: "user".into(),
+                            password: "pass".into(),
+                        },
+                    },
+                    guid: Some("{guid-1}".into()),
+                    time_created: Some(1000),
+                    time_last_used: Some(2000),
+                    time_password_changed: Some(3000),
+                })
+            )
+        );
+        let realm_login = rows[1].1.as_ref().unwrap();
+        assert_eq!(realm_login.entry.fields.http_realm, Some("realm".into()));
+        assert_eq!(realm_login.entry.fields.form_action_origin, None);
+        assert_eq!(realm_login.guid, None);
+        assert_eq!(realm_login.time_created, None);
+        assert_eq!(rows[2].0, 4);
+        assert!(rows[2].1.is_err());
+    }
+
+    #[test]
+    fn test_parse_chrome() {
+        let text =
+            "name,url,username,password,note\nexample.com,https://example.com/login,user,pass,\n";
+        let rows = parse_logins(text).unwrap();
+        assert_eq!(rows.len(), 1);
+        let login = rows[0].1.as_ref().unwrap();
+        assert_eq!(login.entry.fields.origin, "https://example.com/login");
+        // No target at all means a form login with an unknown action.
+        assert_eq!(login.entry.fields.form_action_origin, Some("".into()));
+        assert_eq!(login.entry.sec_fields.username, "user");
+        assert_eq!(login.entry.sec_fields.password, "pass");
+    }
+
+    #[test]
+    fn test_parse_bad_header() {
+        assert!(matches!(
+            parse_logins("origin,username,password\n"),
+            Err(Error::InvalidCsv(_))
+        ));
+        assert!(parse_logins("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let login = Login {
+            record: RecordFields {
+                id: "guid".into(),
+                time_created: 1,
+                time_last_used: 2,
+                time_password_changed: 3,
+                times_used: 4,
+            },
+            fields: LoginFields {
+                origin: "https://example.com".into(),
+                http_realm: Some("a \"quoted\", realm".into()),
+                ..Default::default()
+            },
+            sec_fields: SecureLoginFields {
+                username: "user".into(),
+                password: "multi\nline".into(),
+            },
+        };
+        let text = logins_to_csv(&[login.clone()]);
+        assert!(text.starts_with("\"url\",\"username\",\"password\",\"httpRealm\","));
+        let rows = parse_logins(&text).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(
+            rows[0],
+            (
+                2,
+                Ok(CsvLogin {
+                    entry: login.entry(),
+                    guid: Some("guid".into()),
+                    time_created: Some(1),
+                    time_last_used: Some(2),
+                    time_password_changed: Some(3),
+                })
+            )
+        );
+    }
+}
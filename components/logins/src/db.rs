@@ -22,11 +22,13 @@
 ///     server.
 ///   - After we sync, we move all records from loginsL to loginsM, overwriting any previous data.
 ///     loginsL will be an empty table after this.  See mark_as_synchronized() for the details.
+use crate::breaches::Breach;
+use crate::csv::{CsvImportOutcome, CsvImportRowResult, CsvLogin, CsvRows};
 use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
 use crate::login::*;
 use crate::schema;
-use crate::sync::SyncStatus;
+use crate::sync::{SyncConflict, SyncConflictWinner, SyncStatus};
 use crate::util;
 use interrupt_support::{SqlInterruptHandle, SqlInterruptScope};
 use lazy_static::lazy_static;
@@ -36,6 +38,7 @@ use rusqlite::{
     Connection,
 };
 use sql_support::ConnExt;
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::path::Path;
 use std::sync::Arc;
@@ -158,37 +161,93 @@ impl LoginDb {
         let rows = stmt
             .query_and_then([], EncryptedLogin::from_row)?
             .filter(|r| {
-                let login = r
-                    .as_ref()
-                    .ok()
-                    .and_then(|login| Url::parse(&login.fields.origin).ok());
-                let this_host = login.as_ref().and_then(|url| url.host());
-                match (&base_host, this_host) {
-                    (Host::Domain(base), Some(Host::Domain(look))) => {
-                        // a fairly long-winded way of saying
-                        // `login.fields.origin == base_domain ||
-                        //  login.fields.origin.ends_with('.' + base_domain);`
-                        let mut rev_input = base.chars().rev();
-                        let mut rev_host = look.chars().rev();
-                        loop {
-                            match (rev_input.next(), rev_host.next()) {
-                                (Some(ref a), Some(ref b)) if a == b => continue,
-                                (None, None) => return true, // exactly equal
-                                (None, Some(ref h)) => return *h == '.',
-                                _ => return false,
-                            }
-                        }
-                    }
-                    // ip addresses must match exactly.
-                    (Host::Ipv4(base), Some(Host::Ipv4(look))) => *base == look,
-                    (Host::Ipv6(base), Some(Host::Ipv6(look))) => *base == look,
-                    // all "mismatches" in domain types are false.
-                    _ => false,
-                }
+                r.as_ref().map_or(false, |login| {
+                    origin_in_base_domain(&login.fields.origin, &base_host)
+                })
             });
         rows.collect::<Result<_>>()
     }
 
+    /// Find logins matching `query`. The username can only be matched if `encdec` is supplied.
+    ///
+    /// Matching the username is expensive: logins whose origin, http realm or form action
+    /// origin match are found in SQL, but every other login before the end of the requested
+    /// page has to be decrypted, which for a query without a limit means most of the table.
+    pub fn query(
+        &self,
+        query: &LoginQuery,
+        encdec: Option<&EncryptorDecryptor>,
+    ) -> Result<Vec<EncryptedLogin>> {
+        let order_by = match query.sort {
+            LoginSortOrder::TimeLastUsed => "timeLastUsed DESC, guid",
+            LoginSortOrder::TimesUsed => "timesUsed DESC, guid",
+            LoginSortOrder::Origin => "origin, guid",
+        };
+        let text = query.text.as_deref().filter(|t| !t.is_empty());
+        let limit = query.limit.map_or(-1, i64::from);
+        let offset = i64::from(query.offset);
+        match (text, encdec) {
+            (Some(text), Some(encdec)) => {
+                // We can't match the username in SQL, so filter and page here, only decrypting
+                // the logins which don't match otherwise, and stopping once the page is full.
+                let end = query
+                    .limit
+                    .map_or(usize::MAX, |l| offset as usize + l as usize);
+                let lower_text = text.to_lowercase();
+                let mut stmt = self.db.prepare_cached(&format!(
+                    "SELECT *,
+                            (instr(lower(origin), lower(:text)) > 0
+                             OR instr(lower(ifnull(httpRealm, '')), lower(:text)) > 0
+                             OR instr(lower(ifnull(formActionOrigin, '')), lower(:text)) > 0)
+                                AS matchesText
+                     FROM ({get_all})
+                     ORDER BY {order_by}",
+                    get_all = &*GET_ALL_SQL,
+                ))?;
+                let mut rows = stmt.query(named_params! { ":text": text })?;
+                let mut results = Vec::new();
+                while results.len() < end {
+                    let row = match rows.next()? {
+                        Some(row) => row,
+                        None => break,
+                    };
+                    let login = EncryptedLogin::from_row(row)?;
+                    if row.get::<_, bool>("matchesText")?
+                        || login
+                            .decrypt_fields(encdec)?
+                            .username
+                            .to_lowercase()
+                            .contains(&lower_text)
+                    {
+                        results.push(login);
+                    }
+                }
+                Ok(results.into_iter().skip(offset as usize).collect())
+            }
+            (text, _) => {
+                let mut stmt = self.db.prepare_cached(&format!(
+                    "SELECT * FROM ({get_all})
+                     WHERE :text IS NULL
+                        OR instr(lower(origin), lower(:text)) > 0
+                        OR instr(lower(ifnull(httpRealm, '')), lower(:text)) > 0
+                        OR instr(lower(ifnull(formActionOrigin, '')), lower(:text)) > 0
+                     ORDER BY {order_by}
+                     LIMIT :limit OFFSET :offset",
+                    get_all = &*GET_ALL_SQL,
+                ))?;
+                let rows = stmt.query_and_then(
+                    named_params! {
+                        ":text": text,
+                        ":limit": limit,
+                        ":offset": offset,
+                    },
+                    EncryptedLogin::from_row,
+                )?;
+                rows.collect::<Result<_>>()
+            }
+        }
+    }
+
     pub fn get_by_id(&self, id: &str) -> Result<Option<EncryptedLogin>> {
         self.try_query_row(
             &GET_BY_GUID_SQL,
@@ -382,10 +441,22 @@ impl LoginDb {
         sguid: &str,
         entry: LoginEntry,
         encdec: &EncryptorDecryptor,
+    ) -> Result<EncryptedLogin> {
+        let tx = self.unchecked_transaction()?;
+        let result = self.update_in_tx(sguid, entry, encdec)?;
+        tx.commit()?;
+        Ok(result)
+    }
+
+    // The guts of `update()`. Must be called in a transaction.
+    fn update_in_tx(
+        &self,
+        sguid: &str,
+        entry: LoginEntry,
+        encdec: &EncryptorDecryptor,
     ) -> Result<EncryptedLogin> {
         let guid = Guid::new(sguid);
         let now_ms = util::system_time_ms_i64(SystemTime::now());
-        let tx = self.unchecked_transaction()?;
 
         let entry = entry.fixup()?;
 
@@ -414,12 +485,13 @@ impl LoginDb {
             Some(e) => e,
             None => return Err(Error::NoSuchRecord(sguid.to_owned())),
         };
-        let time_password_changed =
-            if existing.decrypt_fields(encdec)?.password == entry.sec_fields.password {
-                existing.record.time_password_changed
-            } else {
-                now_ms
-            };
+        let existing_password = existing.decrypt_fields(encdec)?.password;
+        let time_password_changed = if existing_password == entry.sec_fields.password {
+            existing.record.time_password_changed
+        } else {
+            self.add_to_password_history(sguid, &existing_password, now_ms, encdec)?;
+            now_ms
+        };
 
         // Make the final object here - every column will be updated.
         let result = EncryptedLogin {
@@ -435,7 +507,6 @@ impl LoginDb {
         };
 
         self.update_existing_login(&result)?;
-        tx.commit()?;
         Ok(result)
     }
 
@@ -587,10 +658,26 @@ impl LoginDb {
 
     /// Delete the record with the provided id. Returns true if the record
     /// existed already.
+    ///
+    /// If a trash retention period is set, the login is kept in the trash so it can be restored
+    /// with `undelete()`, and the tombstone isn't synced until the period ends.
     pub fn delete(&self, id: &str) -> Result<bool> {
         let tx = self.unchecked_transaction_imm()?;
+        self.purge_expired_trash()?;
         let exists = self.exists(id)?;
         let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let use_trash = exists && self.get_trash_retention()? > 0;
+        if use_trash {
+            self.execute_cached(
+                &format!(
+                    "INSERT OR REPLACE INTO loginsTrash ({common_cols}, timeDeleted)
+                     SELECT {common_cols}, :now_ms FROM ({get_by_guid})",
+                    common_cols = schema::COMMON_COLS,
+                    get_by_guid = &*GET_BY_GUID_SQL,
+                ),
+                named_params! { ":now_ms": now_ms, ":guid": id },
+            )?;
+        }
 
         // For IDs that have, mark is_deleted and clear sensitive fields
         self.execute(
@@ -625,6 +712,18 @@ impl LoginDb {
             WHERE guid = :guid",
             changed = SyncStatus::Changed as u8),
             named_params! { ":now_ms": now_ms, ":guid": id })?;
+
+        // The password history and any breach alert dismissal go when the login leaves the trash.
+        if !use_trash {
+            self.execute(
+                "DELETE FROM loginsPasswordHistory WHERE guid = :guid",
+                named_params! { ":guid": id },
+            )?;
+            self.execute(
+                "DELETE FROM loginsBreachDismissals WHERE guid = :guid",
+                named_params! { ":guid": id },
+            )?;
+        }
         tx.commit()?;
         Ok(exists)
     }
@@ -699,6 +798,13 @@ impl LoginDb {
                 changed = SyncStatus::Changed as u8),
             named_params! { ":now_ms": now_ms })?;
         scope.err_if_interrupted()?;
+
+        self.execute_all(&[
+            "DELETE FROM loginsPasswordHistory",
+            "DELETE FROM loginsBreachDismissals",
+            "DELETE FROM loginsTrash",
+            "DELETE FROM loginsSyncConflicts",
+        ])?;
         tx.commit()?;
         Ok(())
     }
@@ -710,10 +816,592 @@ impl LoginDb {
             "DELETE FROM loginsL",
             "DELETE FROM loginsM",
             "DELETE FROM loginsSyncMeta",
+            "DELETE FROM loginsBreachDismissals",
+            "DELETE FROM loginsPasswordHistory",
+            "DELETE FROM loginsTrash",
+            "DELETE FROM loginsSyncConflicts",
         ])?;
         tx.commit()?;
         Ok(())
     }
+
+    /// Re-encrypt every encrypted value in the database, decrypting with `old_encdec` and
+    /// encrypting with `new_encdec`.  This covers `secFields` in `loginsL`, `loginsM` and the
+    /// trash, plus `loginsM.enc_unknown_fields` and the password history.
+    ///
+    /// Everything happens in a single transaction, so if any value fails to decrypt, or we are
+    /// interrupted, nothing is changed.  Returns the number of records which were rotated.
+    pub(crate) fn rekey(
+        &self,
+        old_encdec: &EncryptorDecryptor,
+        new_encdec: &EncryptorDecryptor,
+        scope: &SqlInterruptScope,
+    ) -> Result<u32> {
+        let tx = self.unchecked_transaction_imm()?;
+        log::info!("Re-encrypting logins with a new key");
+        let mut count = 0;
+        for table in ["loginsL", "loginsM", "loginsTrash"] {
+            // Tombstones have an empty string for secFields, so there's nothing to rotate there.
+            let rows: Vec<(i64, String)> = self
+                .prepare(&format!(
+                    "SELECT id, secFields FROM {table}
+                     WHERE secFields IS NOT NULL AND secFields <> ''"
+                ))?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<_>>()?;
+            let mut stmt = self.prepare(&format!(
+                "UPDATE {table} SET secFields = :sec_fields WHERE id = :id"
+            ))?;
+            for (id, ciphertext) in rows {
+                scope.err_if_interrupted()?;
+                let sec_fields = SecureLoginFields::decrypt(&ciphertext, old_encdec)?;
+                stmt.execute(named_params! {
+                    ":sec_fields": sec_fields.encrypt(new_encdec)?,
+                    ":id": id,
+                })?;
+                count += 1;
+            }
+        }
+
+        // The unknown fields are an opaque JSON blob - we don't need to understand them to
+        // re-encrypt them.
+        let rows: Vec<(i64, String)> = self
+            .prepare(
+                "SELECT id, enc_unknown_fields FROM loginsM
+                 WHERE enc_unknown_fields IS NOT NULL",
+            )?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        let mut stmt =
+            self.prepare("UPDATE loginsM SET enc_unknown_fields = :unknown WHERE id = :id")?;
+        for (id, ciphertext) in rows {
+            scope.err_if_interrupted()?;
+            let unknown = old_encdec.decrypt(&ciphertext, "rekey decrypt unknown fields")?;
+            stmt.execute(named_params! {
+                ":unknown": new_encdec.encrypt(&unknown, "rekey encrypt unknown fields")?,
+                ":id": id,
+            })?;
+        }
+
+        let rows: Vec<(i64, String)> = self
+            .prepare("SELECT id, encPassword FROM loginsPasswordHistory")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        let mut stmt = self
+            .prepare("UPDATE loginsPasswordHistory SET encPassword = :password WHERE id = :id")?;
+        for (id, ciphertext) in rows {
+            scope.err_if_interrupted()?;
+            let password = old_encdec.decrypt(&ciphertext, "rekey decrypt password history")?;
+            stmt.execute(named_params! {
+                ":password": new_encdec.encrypt(&password, "rekey encrypt password history")?,
+                ":id": id,
+            })?;
+        }
+        scope.err_if_interrupted()?;
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Import the logins from a parsed CSV file.
+    ///
+    /// Rows which are dupes of an existing login (as determined by `find_dupe()`) are merged into
+    /// that login rather than added. The whole import happens in a single transaction.
+    pub(crate) fn import_csv_logins(
+        &self,
+        rows: CsvRows,
+        encdec: &EncryptorDecryptor,
+        scope: &SqlInterruptScope,
+    ) -> Result<Vec<CsvImportRowResult>> {
+        let tx = self.unchecked_transaction_imm()?;
+        let mut results = Vec::with_capacity(rows.len());
+        for (line, row) in rows {
+            scope.err_if_interrupted()?;
+            let outcome = match row {
+                Ok(login) => self.import_csv_login(login, encdec)?,
+                Err(reason) => CsvImportOutcome::Invalid { reason },
+            };
+            results.push(CsvImportRowResult { line, outcome });
+        }
+        tx.commit()?;
+        Ok(results)
+    }
+
+    fn import_csv_login(
+        &self,
+        login: CsvLogin,
+        encdec: &EncryptorDecryptor,
+    ) -> Result<CsvImportOutcome> {
+        let entry = match login.entry.fixup() {
+            Ok(entry) => entry,
+            Err(Error::InvalidLogin(why)) => {
+                return Ok(CsvImportOutcome::Invalid {
+                    reason: why.to_string(),
+                })
+            }
+            Err(e) => return Err(e),
+        };
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        // Keep the guid from the file if we can, so re-importing a file we exported doesn't
+        // change the identity of the logins.
+        let guid = match login.guid {
+            Some(guid)
+                if Guid::new(&guid).is_valid_for_sync_server() && !self.guid_in_use(&guid)? =>
+            {
+                Guid::from_string(guid)
+            }
+            _ => Guid::random(),
+        };
+
+        if let Some(dupe) = self.find_dupe(&guid, &entry, encdec)? {
+            let existing = self
+                .get_by_id(&dupe)?
+                .ok_or_else(|| Error::NoSuchRecord(dupe.to_string()))?;
+            let existing_sec_fields = existing.decrypt_fields(encdec)?;
+            // Only take the imported password if it's newer than the one we have.
+            let is_newer = login
+                .time_password_changed
+                .map_or(true, |t| t > existing.record.time_password_changed);
+            if existing_sec_fields.password == entry.sec_fields.password || !is_newer {
+                return Ok(CsvImportOutcome::SkippedDupe {
+                    id: existing.record.id,
+                });
+            }
+            self.ensure_local_overlay_exists(&existing.record.id)?;
+            self.mark_mirror_overridden(&existing.record.id)?;
+            let updated = EncryptedLogin {
+                record: RecordFields {
+                    time_password_changed: login.time_password_changed.unwrap_or(now_ms),
+                    time_last_used: now_ms,
+                    ..existing.record
+                },
+                fields: existing.fields,
+                sec_fields: SecureLoginFields {
+                    password: entry.sec_fields.password,
+                    ..existing_sec_fields
+                }
+                .encrypt(encdec)?,
+            };
+            self.update_existing_login(&updated)?;
+            return Ok(CsvImportOutcome::Updated {
+                id: updated.record.id,
+            });
+        }
+
+        let time_created = login.time_created.unwrap_or(now_ms);
+        let new_login = EncryptedLogin::from_fixed(
+            RecordFields {
+                id: guid.to_string(),
+                time_created,
+                time_password_changed: login.time_password_changed.unwrap_or(time_created),
+                time_last_used: login.time_last_used.unwrap_or(time_created),
+                times_used: 1,
+            },
+            entry,
+            encdec,
+        )?;
+        self.insert_new_login(&new_login)?;
+        Ok(CsvImportOutcome::Added {
+            id: new_login.record.id,
+        })
+    }
+
+    /// Replace all known breaches with `breaches`.
+    pub(crate) fn replace_breaches(&self, breaches: &[Breach]) -> Result<()> {
+        let tx = self.unchecked_transaction()?;
+        self.execute("DELETE FROM loginsBreaches", [])?;
+        let mut stmt = self.prepare(
+            "INSERT OR REPLACE INTO loginsBreaches (name, domain, breachDate)
+             VALUES (:name, :domain, :breach_date)",
+        )?;
+        for breach in breaches {
+            stmt.execute(named_params! {
+                ":name": breach.name,
+                ":domain": breach.domain,
+                ":breach_date": breach.breach_date,
+            })?;
+        }
+        drop(stmt);
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get the logins which are affected by a known breach - that is, the breach was for the
+    /// login's domain (as `get_by_base_domain()` would match it) and happened after the password
+    /// was last changed. Logins where the user has dismissed the alert since the breach are
+    /// excluded.
+    pub fn get_breached_logins(&self) -> Result<Vec<EncryptedLogin>> {
+        let breaches = self
+            .prepare_cached("SELECT domain, breachDate FROM loginsBreaches")?
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?
+            .into_iter()
+            .filter_map(|(domain, breach_date)| Some((Host::parse(&domain).ok()?, breach_date)))
+            .collect::<Vec<_>>();
+        if breaches.is_empty() {
+            return Ok(vec![]);
+        }
+        let dismissals = self
+            .prepare_cached("SELECT guid, timeDismissed FROM loginsBreachDismissals")?
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .collect::<rusqlite::Result<HashMap<_, _>>>()?;
+        Ok(self
+            .get_all()?
+            .into_iter()
+            .filter(|login| {
+                let dismissed = dismissals.get(&login.record.id);
+                breaches.iter().any(|(base_host, breach_date)| {
+                    login.record.time_password_changed < *breach_date
+                        && dismissed.map_or(true, |dismissed| dismissed < breach_date)
+                        && origin_in_base_domain(&login.fields.origin, base_host)
+                })
+            })
+            .collect())
+    }
+
+    /// Dismiss the breach alert for a login. Breaches which happened before now will no longer
+    /// be reported for it.
+    pub fn dismiss_breach_alert(&self, id: &str) -> Result<()> {
+        if !self.exists(id)? {
+            return Err(Error::NoSuchRecord(id.to_owned()));
+        }
+        self.execute_cached(
+            "REPLACE INTO loginsBreachDismissals (guid, timeDismissed) VALUES (:guid, :now_ms)",
+            named_params! {
+                ":guid": id,
+                ":now_ms": util::system_time_ms_i64(SystemTime::now()),
+            },
+        )?;
+        Ok(())
+    }
+
+    /// The number of previous passwords kept for each login. 0 means password history is
+    /// disabled, which is the default.
+    pub fn get_password_history_limit(&self) -> Result<u32> {
+        Ok(self
+            .get_meta::<u32>(schema::PASSWORD_HISTORY_LIMIT_META_KEY)?
+            .unwrap_or_default())
+    }
+
+    /// Set the number of previous passwords kept for each login, discarding any older ones
+    /// beyond the new limit. Setting it to 0 disables password history and clears it.
+    pub fn set_password_history_limit(&self, limit: u32) -> Result<()> {
+        let tx = self.unchecked_transaction()?;
+        if limit == 0 {
+            self.delete_meta(schema::PASSWORD_HISTORY_LIMIT_META_KEY)?;
+            self.execute("DELETE FROM loginsPasswordHistory", [])?;
+        } else {
+            self.put_meta(schema::PASSWORD_HISTORY_LIMIT_META_KEY, &limit)?;
+            self.execute(
+                "DELETE FROM loginsPasswordHistory
+                 WHERE id IN (
+                     SELECT id FROM (
+                         SELECT id, row_number() OVER (
+                             PARTITION BY guid ORDER BY timeReplaced DESC, id DESC
+                         ) AS n
+                         FROM loginsPasswordHistory
+                     )
+                     WHERE n > :limit
+                 )",
+                named_params! { ":limit": limit },
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Remember `password` as a previous password of the login, if password history is enabled.
+    // Must be called in a transaction.
+    fn add_to_password_history(
+        &self,
+        guid: &str,
+        password: &str,
+        now_ms: i64,
+        encdec: &EncryptorDecryptor,
+    ) -> Result<()> {
+        let limit = self.get_password_history_limit()?;
+        if limit == 0 {
+            return Ok(());
+        }
+        self.execute_cached(
+            "INSERT INTO loginsPasswordHistory (guid, encPassword, timeReplaced)
+             VALUES (:guid, :password, :now_ms)",
+            named_params! {
+                ":guid": guid,
+                ":password": encdec.encrypt(password, "encrypt password history")?,
+                ":now_ms": now_ms,
+            },
+        )?;
+        self.execute_cached(
+            "DELETE FROM loginsPasswordHistory
+             WHERE guid = :guid AND id NOT IN (
+                 SELECT id FROM loginsPasswordHistory
+                 WHERE guid = :guid
+                 ORDER BY timeReplaced DESC, id DESC
+                 LIMIT :limit
+             )",
+            named_params! { ":guid": guid, ":limit": limit },
+        )?;
+        Ok(())
+    }
+
+    /// Get the previous passwords of a login, most recently replaced first.
+    pub fn get_password_history(
+        &self,
+        id: &str,
+        encdec: &EncryptorDecryptor,
+    ) -> Result<Vec<PasswordHistoryEntry>> {
+        let mut stmt = self.prepare_cached(
+            "SELECT id, encPassword, timeReplaced FROM loginsPasswordHistory
+             WHERE guid = :guid
+             ORDER BY timeReplaced DESC, id DESC",
+        )?;
+        let rows = stmt.query_and_then(named_params! { ":guid": id }, |row| {
+            Ok(PasswordHistoryEntry {
+                id: row.get("id")?,
+                password: encdec.decrypt(
+                    &row.get::<_, String>("encPassword")?,
+                    "decrypt password history",
+                )?,
+                time_replaced: row.get("timeReplaced")?,
+            })
+        })?;
+        rows.collect::<Result<_>>()
+    }
+
+    /// Make a previous password the current password of the login again. The current password
+    /// is added to the history in its place, like any other update.
+    pub fn restore_password(
+        &self,
+        id: &str,
+        history_id: i64,
+        encdec: &EncryptorDecryptor,
+    ) -> Result<EncryptedLogin> {
+        let tx = self.unchecked_transaction()?;
+        let password = self
+            .get_password_history(id, encdec)?
+            .into_iter()
+            .find(|entry| entry.id == history_id)
+            .ok_or_else(|| Error::NoSuchRecord(format!("{id} (password history {history_id})")))?
+            .password;
+        let login = match self.get_by_id(id)? {
+            Some(login) => login.decrypt(encdec)?,
+            None => return Err(Error::NoSuchRecord(id.to_owned())),
+        };
+        let mut entry = login.entry();
+        entry.sec_fields.password = password;
+        let result = self.update_in_tx(id, entry, encdec)?;
+        self.execute_cached(
+            "DELETE FROM loginsPasswordHistory WHERE id = :id",
+            named_params! { ":id": history_id },
+        )?;
+        tx.commit()?;
+        Ok(result)
+    }
+
+    /// How long, in milliseconds, deleted logins are kept in the trash. 0 means they aren't,
+    /// which is the default.
+    pub fn get_trash_retention(&self) -> Result<i64> {
+        Ok(self
+            .get_meta::<i64>(schema::TRASH_RETENTION_META_KEY)?
+            .unwrap_or_default())
+    }
+
+    /// Set how long deleted logins are kept in the trash. Setting it to 0 disables the trash and
+    /// empties it, so any tombstones it was holding back will be synced.
+    pub fn set_trash_retention(&self, retention_ms: i64) -> Result<()> {
+        let tx = self.unchecked_transaction()?;
+        if retention_ms <= 0 {
+            self.delete_meta(schema::TRASH_RETENTION_META_KEY)?;
+        } else {
+            self.put_meta(schema::TRASH_RETENTION_META_KEY, &retention_ms)?;
+        }
+        self.purge_expired_trash()?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    // The time before which trashed logins have expired.
+    pub(crate) fn trash_cutoff(&self) -> Result<i64> {
+        Ok(util::system_time_ms_i64(SystemTime::now()) - self.get_trash_retention()?)
+    }
+
+    // Forget trashed logins whose retention period has ended, along with their password
+    // history and breach alert dismissals. Must be called in a transaction.
+    pub(crate) fn purge_expired_trash(&self) -> Result<()> {
+        let cutoff = self.trash_cutoff()?;
+        self.execute_cached(
+            "DELETE FROM loginsPasswordHistory
+             WHERE guid IN (SELECT guid FROM loginsTrash WHERE timeDeleted <= :cutoff)",
+            named_params! { ":cutoff": cutoff },
+        )?;
+        self.execute_cached(
+            "DELETE FROM loginsBreachDismissals
+             WHERE guid IN (SELECT guid FROM loginsTrash WHERE timeDeleted <= :cutoff)",
+            named_params! { ":cutoff": cutoff },
+        )?;
+        self.execute_cached(
+            "DELETE FROM loginsTrash WHERE timeDeleted <= :cutoff",
+            named_params! { ":cutoff": cutoff },
+        )?;
+        Ok(())
+    }
+
+    /// Get the logins in the trash, most recently deleted first.
+    pub fn list_deleted(&self) -> Result<Vec<DeletedLogin>> {
+        let tx = self.unchecked_transaction()?;
+        self.purge_expired_trash()?;
+        let mut stmt = self.prepare_cached(&format!(
+            "SELECT {common_cols}, timeDeleted FROM loginsTrash ORDER BY timeDeleted DESC, id DESC",
+            common_cols = schema::COMMON_COLS,
+        ))?;
+        let deleted = stmt
+            .query_and_then([], |row| {
+                Ok(DeletedLogin {
+                    login: EncryptedLogin::from_row(row)?,
+                    time_deleted: row.get("timeDeleted")?,
+                })
+            })?
+            .collect::<Result<_>>()?;
+        drop(stmt);
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Restore a login from the trash. It's treated as a local change, so it will be uploaded
+    /// at the next sync, replacing anything which happened to it elsewhere in the meantime.
+    ///
+    /// Fails with a duplicate login error if a login for the same site and username has been
+    /// saved since this one was deleted.
+    pub fn undelete(&self, id: &str, encdec: &EncryptorDecryptor) -> Result<EncryptedLogin> {
+        let tx = self.unchecked_transaction_imm()?;
+        self.purge_expired_trash()?;
+        let trashed = self
+            .try_query_row(
+                &format!(
+                    "SELECT {common_cols} FROM loginsTrash WHERE guid = :guid",
+                    common_cols = schema::COMMON_COLS,
+                ),
+                named_params! { ":guid": id },
+                EncryptedLogin::from_row,
+                true,
+            )?
+            .ok_or_else(|| Error::NoSuchRecord(id.to_owned()))?;
+        self.check_for_dupes(&Guid::new(id), &trashed.decrypt(encdec)?.entry(), encdec)?;
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let in_mirror: bool = self.db.query_row(
+            "SELECT EXISTS(SELECT 1 FROM loginsM WHERE guid = :guid)",
+            named_params! { ":guid": id },
+            |row| row.get(0),
+        )?;
+        self.execute_cached(
+            "DELETE FROM loginsL WHERE guid = :guid",
+            named_params! { ":guid": id },
+        )?;
+        let restored = self.execute_cached(
+            &format!(
+                "INSERT INTO loginsL ({common_cols}, local_modified, is_deleted, sync_status)
+                 SELECT {common_cols}, :now_ms, 0, :sync_status
+                 FROM loginsTrash
+                 WHERE guid = :guid",
+                common_cols = schema::COMMON_COLS,
+            ),
+            named_params! {
+                ":now_ms": now_ms,
+                ":sync_status": (if in_mirror { SyncStatus::Changed } else { SyncStatus::New }) as u8,
+                ":guid": id,
+            },
+        )?;
+        if restored == 0 {
+            return Err(Error::NoSuchRecord(id.to_owned()));
+        }
+        self.mark_mirror_overridden(id)?;
+        self.execute_cached(
+            "DELETE FROM loginsTrash WHERE guid = :guid",
+            named_params! { ":guid": id },
+        )?;
+        let login = self
+            .get_by_id(id)?
+            .ok_or_else(|| Error::NoSuchRecord(id.to_owned()))?;
+        tx.commit()?;
+        Ok(login)
+    }
+
+    /// Get the three-way merges recently done by sync, most recent first.
+    pub fn get_sync_conflicts(&self) -> Result<Vec<SyncConflict>> {
+        let mut stmt = self.prepare_cached(
+            "SELECT guid, conflictingFields, remoteWon, localModified, remoteModified, timeMerged
+             FROM loginsSyncConflicts
+             ORDER BY id DESC",
+        )?;
+        let rows = stmt.query_and_then([], |row| {
+            let fields: String = row.get("conflictingFields")?;
+            Ok(SyncConflict {
+                id: row.get("guid")?,
+                conflicting_fields: fields
+                    .split(',')
+                    .filter(|f| !f.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                winner: if row.get::<_, bool>("remoteWon")? {
+                    SyncConflictWinner::Remote
+                } else {
+                    SyncConflictWinner::Local
+                },
+                local_modified: row.get("localModified")?,
+                remote_modified: row.get("remoteModified")?,
+                time_merged: row.get("timeMerged")?,
+            })
+        })?;
+        rows.collect::<Result<_>>()
+    }
+
+    // Whether any row, including tombstones, uses this guid.
+    fn guid_in_use(&self, guid: &str) -> Result<bool> {
+        Ok(self.db.query_row(
+            "SELECT EXISTS(
+                 SELECT 1 FROM loginsL WHERE guid = :guid
+                 UNION ALL
+                 SELECT 1 FROM loginsM WHERE guid = :guid
+             )",
+            named_params! { ":guid": guid },
+            |row| row.get(0),
+        )?)
+    }
+}
+
+// Returns true if the host of `origin` is `base_host` or one of its subdomains. IP addresses must
+// match exactly.
+fn origin_in_base_domain(origin: &str, base_host: &Host) -> bool {
+    let url = match Url::parse(origin) {
+        Ok(url) => url,
+        Err(_) => return false,
+    };
+    match (base_host, url.host()) {
+        (Host::Domain(base), Some(Host::Domain(look))) => {
+            // a fairly long-winded way of saying
+            // `login.fields.origin == base_domain ||
+            //  login.fields.origin.ends_with('.' + base_domain);`
+            let mut rev_input = base.chars().rev();
+            let mut rev_host = look.chars().rev();
+            loop {
+                match (rev_input.next(), rev_host.next()) {
+                    (Some(ref a), Some(ref b)) if a == b => continue,
+                    (None, None) => return true, // exactly equal
+                    (None, Some(ref h)) => return *h == '.',
+                    _ => return false,
+                }
+            }
+        }
+        // ip addresses must match exactly.
+        (Host::Ipv4(base), Some(Host::Ipv4(look))) => *base == look,
+        (Host::Ipv6(base), Some(Host::Ipv6(look))) => *base == look,
+        // all "mismatches" in domain types are false.
+        _ => false,
+    }
 }
 
 lazy_static! {
@@ -1319,6 +2007,379 @@ mod tests {
         assert!(!db.exists(login2.guid_str()).unwrap());
     }
 
+    #[test]
+    fn test_wipe_local() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let login = db
+            .add(
+                LoginEntry {
+                    fields: LoginFields {
+                        origin: "https://www.example.com".into(),
+                        http_realm: Some("https://www.example.com".into()),
+                        ..Default::default()
+                    },
+                    sec_fields: SecureLoginFields {
+                        username: "test_user".into(),
+                        password: "test_password".into(),
+                    },
+                },
+                &TEST_ENCRYPTOR,
+            )
+            .unwrap();
+        db.execute(
+            "INSERT INTO loginsSyncConflicts
+                (guid, conflictingFields, remoteWon, localModified, remoteModified, timeMerged)
+             VALUES (:guid, 'password', 1, 1000, 2000, 3000)",
+            named_params! { ":guid": login.guid_str() },
+        )
+        .unwrap();
+
+        db.wipe_local().expect("wipe_local should work");
+
+        assert!(!db.exists(login.guid_str()).unwrap());
+        assert!(db.get_sync_conflicts().unwrap().is_empty());
+        for table in ["loginsL", "loginsM", "loginsSyncConflicts"] {
+            let count: i64 = db
+                .query_one(&format!("SELECT COUNT(*) FROM {table}"))
+                .unwrap();
+            assert_eq!(count, 0, "{table} should be empty");
+        }
+    }
+
+    #[test]
+    fn test_query() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let add = |origin: &str, username: &str| {
+            db.add(
+                LoginEntry {
+                    fields: LoginFields {
+                        origin: origin.into(),
+                        form_action_origin: Some(origin.into()),
+                        ..Default::default()
+                    },
+                    sec_fields: SecureLoginFields {
+                        username: username.into(),
+                        password: "pass".into(),
+                    },
+                },
+                &TEST_ENCRYPTOR,
+            )
+            .unwrap()
+            .record
+            .id
+        };
+        let a = add("https://a.example.com", "alice");
+        let b = add("https://b.example.com", "bob");
+        let c = add("https://c.example.org", "carol");
+        db.execute_batch(&format!(
+            "UPDATE loginsL SET timeLastUsed = 3, timesUsed = 1 WHERE guid = '{a}';
+             UPDATE loginsL SET timeLastUsed = 1, timesUsed = 3 WHERE guid = '{b}';
+             UPDATE loginsL SET timeLastUsed = 2, timesUsed = 2 WHERE guid = '{c}';"
+        ))
+        .unwrap();
+        let ids = |query: LoginQuery, encdec: Option<&EncryptorDecryptor>| {
+            db.query(&query, encdec)
+                .unwrap()
+                .into_iter()
+                .map(|l| l.record.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ids(LoginQuery::default(), None),
+            vec![a.as_str(), c.as_str(), b.as_str()]
+        );
+        let by_times_used = LoginQuery {
+            sort: LoginSortOrder::TimesUsed,
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(by_times_used, None),
+            vec![b.as_str(), c.as_str(), a.as_str()]
+        );
+        let by_origin = LoginQuery {
+            sort: LoginSortOrder::Origin,
+            offset: 1,
+            limit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(ids(by_origin, None), vec![b.as_str()]);
+
+        let example_com = LoginQuery {
+            text: Some("EXAMPLE.com".into()),
+            ..Default::default()
+        };
+        assert_eq!(ids(example_com, None), vec![a.as_str(), b.as_str()]);
+        // Usernames can only be matched given the key.
+        let carol = LoginQuery {
+            text: Some("Carol".into()),
+            ..Default::default()
+        };
+        assert!(ids(carol.clone(), None).is_empty());
+        assert_eq!(ids(carol, Some(&TEST_ENCRYPTOR)), vec![c.as_str()]);
+        let paged = LoginQuery {
+            text: Some("example".into()),
+            offset: 1,
+            limit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(ids(paged, Some(&TEST_ENCRYPTOR)), vec![c.as_str()]);
+
+        // Logins after the requested page aren't decrypted.
+        db.execute(
+            "UPDATE loginsL SET secFields = 'not encrypted' WHERE guid = ?",
+            [&b],
+        )
+        .unwrap();
+        let alice = LoginQuery {
+            text: Some("alice".into()),
+            ..Default::default()
+        };
+        assert!(db.query(&alice, Some(&TEST_ENCRYPTOR)).is_err());
+        let first_alice = LoginQuery {
+            limit: Some(1),
+            ..alice
+        };
+        assert_eq!(ids(first_alice, Some(&TEST_ENCRYPTOR)), vec![a.as_str()]);
+    }
+
+    #[test]
+    fn test_password_history() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let entry = |password: &str| LoginEntry {
+            fields: LoginFields {
+                origin: "https://www.example.com".into(),
+                http_realm: Some("realm".into()),
+                ..Default::default()
+            },
+            sec_fields: SecureLoginFields {
+                username: "user".into(),
+                password: password.into(),
+            },
+        };
+        let passwords = |id: &str| {
+            db.get_password_history(id, &TEST_ENCRYPTOR)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.password)
+                .collect::<Vec<_>>()
+        };
+        let id = db.add(entry("one"), &TEST_ENCRYPTOR).unwrap().record.id;
+
+        // Disabled by default.
+        db.update(&id, entry("two"), &TEST_ENCRYPTOR).unwrap();
+        assert!(passwords(&id).is_empty());
+
+        db.set_password_history_limit(2).unwrap();
+        for password in ["three", "four", "four", "five"] {
+            db.update(&id, entry(password), &TEST_ENCRYPTOR).unwrap();
+        }
+        // Unchanged passwords aren't recorded, and only the most recent 2 are kept.
+        assert_eq!(passwords(&id), vec!["four", "three"]);
+
+        let history_id = db.get_password_history(&id, &TEST_ENCRYPTOR).unwrap()[1].id;
+        let restored = db
+            .restore_password(&id, history_id, &TEST_ENCRYPTOR)
+            .unwrap();
+        assert_eq!(
+            restored.decrypt_fields(&TEST_ENCRYPTOR).unwrap().password,
+            "three"
+        );
+        assert_eq!(passwords(&id), vec!["five", "four"]);
+        assert!(matches!(
+            db.restore_password(&id, history_id, &TEST_ENCRYPTOR),
+            Err(Error::NoSuchRecord(_))
+        ));
+
+        db.set_password_history_limit(1).unwrap();
+        assert_eq!(passwords(&id), vec!["five"]);
+
+        db.delete(&id).unwrap();
+        assert!(passwords(&id).is_empty());
+    }
+
+    #[test]
+    fn test_trash() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let login = db
+            .add(
+                LoginEntry {
+                    fields: LoginFields {
+                        origin: "https://www.example.com".into(),
+                        http_realm: Some("realm".into()),
+                        ..Default::default()
+                    },
+                    sec_fields: SecureLoginFields {
+                        username: "user".into(),
+                        password: "pass".into(),
+                    },
+                },
+                &TEST_ENCRYPTOR,
+            )
+            .unwrap();
+        let id = login.record.id.clone();
+
+        // Disabled by default.
+        db.delete(&id).unwrap();
+        assert!(db.list_deleted().unwrap().is_empty());
+        assert!(matches!(
+            db.undelete(&id, &TEST_ENCRYPTOR),
+            Err(Error::NoSuchRecord(_))
+        ));
+
+        let id = db
+            .add(
+                login.decrypt(&TEST_ENCRYPTOR).unwrap().entry(),
+                &TEST_ENCRYPTOR,
+            )
+            .unwrap()
+            .record
+            .id;
+        db.set_trash_retention(60_000).unwrap();
+        db.delete(&id).unwrap();
+        assert!(!db.exists(&id).unwrap());
+        let deleted = db.list_deleted().unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].login.record.id, id);
+
+        let restored = db.undelete(&id, &TEST_ENCRYPTOR).unwrap();
+        assert_eq!(restored.record.id, id);
+        assert_eq!(
+            restored.decrypt_fields(&TEST_ENCRYPTOR).unwrap().password,
+            "pass"
+        );
+        assert!(db.exists(&id).unwrap());
+        assert!(db.list_deleted().unwrap().is_empty());
+
+        // If the same login has been saved again since, restoring it would make a duplicate.
+        db.delete(&id).unwrap();
+        let resaved = db
+            .add(
+                login.decrypt(&TEST_ENCRYPTOR).unwrap().entry(),
+                &TEST_ENCRYPTOR,
+            )
+            .unwrap();
+        assert!(matches!(
+            db.undelete(&id, &TEST_ENCRYPTOR),
+            Err(Error::InvalidLogin(InvalidLogin::DuplicateLogin))
+        ));
+        assert_eq!(db.list_deleted().unwrap().len(), 1);
+        db.delete(resaved.guid_str()).unwrap();
+        db.undelete(&id, &TEST_ENCRYPTOR).unwrap();
+
+        // Once the retention period ends, the login can't be restored.
+        db.delete(&id).unwrap();
+        db.execute_batch("UPDATE loginsTrash SET timeDeleted = 0")
+            .unwrap();
+        assert!(db.list_deleted().unwrap().is_empty());
+        assert!(matches!(
+            db.undelete(&id, &TEST_ENCRYPTOR),
+            Err(Error::NoSuchRecord(_))
+        ));
+
+        // Deleting another login also forgets expired ones, even if the trash is never listed.
+        let id = db
+            .add(
+                login.decrypt(&TEST_ENCRYPTOR).unwrap().entry(),
+                &TEST_ENCRYPTOR,
+            )
+            .unwrap()
+            .record
+            .id;
+        db.delete(&id).unwrap();
+        db.execute_batch("UPDATE loginsTrash SET timeDeleted = 0")
+            .unwrap();
+        db.delete("not-a-guid").unwrap();
+        let trashed: i64 = db.query_one("SELECT COUNT(*) FROM loginsTrash").unwrap();
+        assert_eq!(trashed, 0);
+    }
+
+    #[test]
+    fn test_breached_logins() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let add = |origin: &str| {
+            db.add(
+                LoginEntry {
+                    fields: LoginFields {
+                        origin: origin.into(),
+                        http_realm: Some("realm".into()),
+                        ..Default::default()
+                    },
+                    sec_fields: SecureLoginFields {
+                        username: "user".into(),
+                        password: "pass".into(),
+                    },
+                },
+                &TEST_ENCRYPTOR,
+            )
+            .unwrap()
+        };
+        let breached = add("https://www.example.com");
+        add("https://example.org");
+        db.execute_batch("UPDATE loginsL SET timePasswordChanged = 1000")
+            .unwrap();
+        db.replace_breaches(&[
+            Breach {
+                name: "Example".into(),
+                domain: "example.com".into(),
+                breach_date: 2000,
+            },
+            // Before the password was set, so doesn't count.
+            Breach {
+                name: "Example Org".into(),
+                domain: "example.org".into(),
+                breach_date: 500,
+            },
+        ])
+        .unwrap();
+        let guids = |logins: Vec<EncryptedLogin>| {
+            logins.into_iter().map(|l| l.record.id).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            guids(db.get_breached_logins().unwrap()),
+            vec![breached.record.id.clone()]
+        );
+
+        // Dismissing the alert hides it...
+        db.dismiss_breach_alert(&breached.record.id).unwrap();
+        assert!(db.get_breached_logins().unwrap().is_empty());
+        // ...until there's a newer breach.
+        db.replace_breaches(&[Breach {
+            name: "Example again".into(),
+            domain: "example.com".into(),
+            breach_date: util::system_time_ms_i64(SystemTime::now()) + 1_000_000,
+        }])
+        .unwrap();
+        assert_eq!(
+            guids(db.get_breached_logins().unwrap()),
+            vec![breached.record.id.clone()]
+        );
+
+        assert!(matches!(
+            db.dismiss_breach_alert("not-a-guid"),
+            Err(Error::NoSuchRecord(_))
+        ));
+
+        // Deleting the login forgets the dismissal, including when it leaves the trash.
+        let dismissals = || -> i64 {
+            db.query_one("SELECT COUNT(*) FROM loginsBreachDismissals")
+                .unwrap()
+        };
+        db.dismiss_breach_alert(&breached.record.id).unwrap();
+        db.delete(&breached.record.id).unwrap();
+        assert_eq!(dismissals(), 0);
+
+        let trashed = add("https://www.example.com");
+        db.dismiss_breach_alert(&trashed.record.id).unwrap();
+        db.set_trash_retention(60_000).unwrap();
+        db.delete(&trashed.record.id).unwrap();
+        assert_eq!(dismissals(), 1);
+        db.execute_batch("UPDATE loginsTrash SET timeDeleted = 0")
+            .unwrap();
+        assert!(db.list_deleted().unwrap().is_empty());
+        assert_eq!(dismissals(), 0);
+    }
+
     mod test_find_login_to_update {
         use super::*;
 
@@ -58,6 +58,9 @@ pub enum Error {
     #[error("local encryption key not set")]
     EncryptionKeyMissing,
 
+    #[error("The encryption key does not match the canary")]
+    CanaryMismatch,
+
     #[error("Error synchronizing: {0}")]
     SyncAdapterError(#[from] sync15::Error),
 
@@ -87,6 +90,9 @@ pub enum Error {
 
     #[error("Migration Error: {0}")]
     MigrationError(String),
+
+    #[error("Invalid CSV file: {0}")]
+    InvalidCsv(String),
 }
 
 /// Error::InvalidLogin subtypes
@@ -121,6 +127,10 @@ impl GetErrorHandling for Error {
             Self::InvalidLogin(why) => ErrorHandling::convert(LoginsApiError::InvalidRecord {
                 reason: why.to_string(),
             }),
+            // This is the consumer handing us a file we don't understand, so nothing to report.
+            Self::InvalidCsv(why) => ErrorHandling::convert(LoginsApiError::InvalidRecord {
+                reason: why.to_string(),
+            }),
             Self::MalformedIncomingRecord => {
                 ErrorHandling::convert(LoginsApiError::InvalidRecord {
                     reason: "invalid incoming record".to_string(),
@@ -141,6 +151,10 @@ impl GetErrorHandling for Error {
             }
             Self::CryptoError { .. } => ErrorHandling::convert(LoginsApiError::IncorrectKey)
                 .report_error("logins-crypto-error"),
+            // The consumer asked us to verify a key and it was wrong - they can handle that.
+            Self::CanaryMismatch => {
+                ErrorHandling::convert(LoginsApiError::IncorrectKey).log_warning()
+            }
             Self::Interrupted(_) => ErrorHandling::convert(LoginsApiError::Interrupted {
                 reason: self.to_string(),
             }),
@@ -9,6 +9,9 @@
 mod error;
 mod login;
 
+mod audit;
+mod breaches;
+mod csv;
 mod db;
 pub mod encryption;
 pub mod migrate_sqlcipher_db;
@@ -19,13 +22,15 @@ mod util;
 
 uniffi::include_scaffolding!("logins");
 
+pub use crate::audit::{LoginAudit, ReusedPasswordGroup, WeakPasswordLogin, WeakPasswordReason};
+pub use crate::csv::{CsvImportOutcome, CsvImportRowResult};
 pub use crate::db::LoginDb;
 use crate::encryption::{check_canary, create_canary, create_key};
 pub use crate::error::*;
 pub use crate::login::*;
 pub use crate::migrate_sqlcipher_db::migrate_logins;
 pub use crate::store::*;
-pub use crate::sync::LoginsSyncEngine;
+pub use crate::sync::{LoginsSyncEngine, SyncConflict, SyncConflictWinner};
 
 // Public encryption functions.  We publish these as top-level functions to expose them across
 // UniFFI
@@ -497,6 +497,49 @@ impl EncryptedLogin {
     }
 }
 
+/// How the results of `LoginStore::query()` are sorted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoginSortOrder {
+    /// Most recently used first.
+    #[default]
+    TimeLastUsed,
+    /// Most used first.
+    TimesUsed,
+    /// Alphabetically by origin.
+    Origin,
+}
+
+/// A query for `LoginStore::query()`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LoginQuery {
+    /// If set, only logins where this appears (case-insensitively) in the origin, http realm,
+    /// form action origin or (if an encryption key is supplied) username are returned.
+    pub text: Option<String>,
+    pub sort: LoginSortOrder,
+    /// How many matching logins to skip, for paging through results.
+    pub offset: u32,
+    /// The maximum number of logins to return, or `None` for all of them.
+    pub limit: Option<u32>,
+}
+
+/// A login in the trash, from `LoginStore::list_deleted()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeletedLogin {
+    pub login: EncryptedLogin,
+    /// When the login was deleted, in milliseconds since the epoch.
+    pub time_deleted: i64,
+}
+
+/// A previous password of a login, from `LoginStore::get_password_history()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordHistoryEntry {
+    /// Identifies this entry, for `LoginStore::restore_password()`.
+    pub id: i64,
+    pub password: String,
+    /// When this password was replaced, in milliseconds since the epoch.
+    pub time_replaced: i64,
+}
+
 fn string_or_default(row: &Row<'_>, col: &str) -> Result<String> {
     Ok(row.get::<_, Option<String>>(col)?.unwrap_or_default())
 }
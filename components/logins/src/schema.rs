@@ -87,6 +87,64 @@
 //!    [GLOBAL_STATE_META_KEY]. This is a `sync15::GlobalState` stored as
 //!    JSON.
 //!
+//! ## `loginsBreaches`
+//!
+//! This table was added in version 3. It stores the known data breaches, as
+//! supplied by the app from the `fxmonitor-breaches` Remote Settings
+//! collection. It is entirely replaced each time the app supplies them.
+//!
+//! - `name`: The unique name of the breach.
+//! - `domain`: The domain which was breached. Logins for this domain or any
+//!   of its subdomains are considered affected.
+//! - `breachDate`: A millisecond timestamp of the date of the breach.
+//!
+//! ## `loginsBreachDismissals`
+//!
+//! This table was added in version 3. It records when the user dismissed a
+//! breach alert for a login, keyed by the login `guid`, with the millisecond
+//! timestamp in `timeDismissed`. Breaches before this time are no longer
+//! reported for the login. It is local only and never synced.
+//!
+//! ## `loginsPasswordHistory`
+//!
+//! This table was added in version 4. When password history is enabled (by
+//! storing a limit under [PASSWORD_HISTORY_LIMIT_META_KEY]), each time a
+//! local update changes a login's password, the previous password is stored
+//! here. It is local only and never synced.
+//!
+//! - `guid`: The guid of the login.
+//! - `encPassword`: The previous password, encrypted like `secFields`.
+//! - `timeReplaced`: A millisecond timestamp of when it was replaced.
+//!
+//! ## `loginsTrash`
+//!
+//! This table was added in version 5. When a trash retention period is set
+//! (stored under [TRASH_RETENTION_META_KEY]), deleting a login copies it here
+//! so it can be restored. It contains all fields in [COMMON_COLS], as well as
+//! `timeDeleted`, a millisecond timestamp of when it was deleted.
+//!
+//! The login is then deleted as usual - its `loginsL` row becomes a tombstone,
+//! and its `loginsM` row, if any, is marked as overridden - but the sync
+//! engine doesn't upload the tombstone until the retention period has ended.
+//! It is local only and never synced.
+//!
+//! ## `loginsSyncConflicts`
+//!
+//! This table was added in version 6. Each time sync does a three-way merge
+//! of a login changed both locally and remotely, the decision is recorded
+//! here so it can be explained later. Only the most recent
+//! [MAX_SYNC_CONFLICTS] are kept. It never contains any field values, only
+//! the names of the fields which conflicted.
+//!
+//! - `guid`: The guid of the login.
+//! - `conflictingFields`: A comma-separated list of the fields which were
+//!   changed to different values on both sides.
+//! - `remoteWon`: A boolean indicating whether the remote changes were
+//!   preferred, because they were newer.
+//! - `localModified`: A millisecond timestamp of the local change.
+//! - `remoteModified`: A millisecond timestamp of the remote change.
+//! - `timeMerged`: A millisecond timestamp of when the merge happened.
+//!
 
 use crate::error::*;
 use lazy_static::lazy_static;
@@ -95,7 +153,11 @@ use sql_support::ConnExt;
 
 /// Version 1: SQLCipher -> plaintext migration.
 /// Version 2: addition of `loginsM.enc_unknown_fields`.
-pub(super) const VERSION: i64 = 2;
+/// Version 3: addition of `loginsBreaches` and `loginsBreachDismissals`.
+/// Version 4: addition of `loginsPasswordHistory`.
+/// Version 5: addition of `loginsTrash`.
+/// Version 6: addition of `loginsSyncConflicts`.
+pub(super) const VERSION: i64 = 6;
 
 /// Every column shared by both tables except for `id`
 ///
@@ -169,6 +231,14 @@ lazy_static! {
         )",
         common_sql = COMMON_SQL
     );
+    static ref CREATE_TRASH_TABLE_SQL: String = format!(
+        "CREATE TABLE IF NOT EXISTS loginsTrash (
+            {common_sql},
+            -- Milliseconds.
+            timeDeleted INTEGER NOT NULL
+        )",
+        common_sql = COMMON_SQL
+    );
     static ref SET_VERSION_SQL: String =
         format!("PRAGMA user_version = {version}", version = VERSION);
 }
@@ -180,6 +250,50 @@ const CREATE_META_TABLE_SQL: &str = "
     )
 ";
 
+const CREATE_BREACHES_TABLE_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS loginsBreaches (
+        name       TEXT PRIMARY KEY,
+        domain     TEXT NOT NULL,
+        breachDate INTEGER NOT NULL
+    )
+";
+
+const CREATE_BREACH_DISMISSALS_TABLE_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS loginsBreachDismissals (
+        guid          TEXT PRIMARY KEY,
+        timeDismissed INTEGER NOT NULL
+    )
+";
+
+const CREATE_PASSWORD_HISTORY_TABLE_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS loginsPasswordHistory (
+        id           INTEGER PRIMARY KEY AUTOINCREMENT,
+        guid         TEXT NOT NULL,
+        encPassword  TEXT NOT NULL,
+        timeReplaced INTEGER NOT NULL
+    )
+";
+
+const CREATE_PASSWORD_HISTORY_GUID_INDEX_SQL: &str = "
+    CREATE INDEX IF NOT EXISTS idx_loginsPasswordHistory_guid
+    ON loginsPasswordHistory (guid)
+";
+
+const CREATE_SYNC_CONFLICTS_TABLE_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS loginsSyncConflicts (
+        id                INTEGER PRIMARY KEY AUTOINCREMENT,
+        guid              TEXT NOT NULL,
+        conflictingFields TEXT NOT NULL,
+        remoteWon         TINYINT NOT NULL,
+        localModified     INTEGER NOT NULL,
+        remoteModified    INTEGER NOT NULL,
+        timeMerged        INTEGER NOT NULL
+    )
+";
+
+/// The number of sync conflicts we keep in `loginsSyncConflicts`.
+pub(crate) const MAX_SYNC_CONFLICTS: u32 = 100;
+
 const CREATE_OVERRIDE_ORIGIN_INDEX_SQL: &str = "
     CREATE INDEX IF NOT EXISTS idx_loginsM_is_overridden_origin
     ON loginsM (is_overridden, origin)
@@ -194,6 +308,8 @@ pub(crate) static LAST_SYNC_META_KEY: &str = "last_sync_time";
 pub(crate) static GLOBAL_STATE_META_KEY: &str = "global_state_v2";
 pub(crate) static GLOBAL_SYNCID_META_KEY: &str = "global_sync_id";
 pub(crate) static COLLECTION_SYNCID_META_KEY: &str = "passwords_sync_id";
+pub(crate) static PASSWORD_HISTORY_LIMIT_META_KEY: &str = "password_history_limit";
+pub(crate) static TRASH_RETENTION_META_KEY: &str = "trash_retention_ms";
 
 pub(crate) fn init(db: &Connection) -> Result<()> {
     let user_version = db.query_one::<i64>("PRAGMA user_version")?;
@@ -229,13 +345,36 @@ fn upgrade(db: &Connection, from: i64) -> Result<()> {
     );
 
     // Schema upgrades.
+    let mut from = from;
     if from == 1 {
         // Just one new nullable column makes this fairly easy
         db.execute_batch("ALTER TABLE loginsM ADD enc_unknown_fields TEXT;")?;
+        from = 2;
+    }
+    if from == 2 {
+        db.execute_all(&[
+            CREATE_BREACHES_TABLE_SQL,
+            CREATE_BREACH_DISMISSALS_TABLE_SQL,
+        ])?;
+        from = 3;
+    }
+    if from == 3 {
+        db.execute_all(&[
+            CREATE_PASSWORD_HISTORY_TABLE_SQL,
+            CREATE_PASSWORD_HISTORY_GUID_INDEX_SQL,
+        ])?;
+        from = 4;
+    }
+    if from == 4 {
+        db.execute_batch(&CREATE_TRASH_TABLE_SQL)?;
+        from = 5;
+    }
+    if from == 5 {
+        db.execute_batch(CREATE_SYNC_CONFLICTS_TABLE_SQL)?;
     }
     // XXX - next migration, be sure to:
-    // from = 2;
-    // if from == 2 ...
+    // from = 6;
+    // if from == 6 ...
     db.execute_batch(&SET_VERSION_SQL)?;
     Ok(())
 }
@@ -248,6 +387,12 @@ pub(crate) fn create(db: &Connection) -> Result<()> {
         CREATE_OVERRIDE_ORIGIN_INDEX_SQL,
         CREATE_DELETED_ORIGIN_INDEX_SQL,
         CREATE_META_TABLE_SQL,
+        CREATE_BREACHES_TABLE_SQL,
+        CREATE_BREACH_DISMISSALS_TABLE_SQL,
+        CREATE_PASSWORD_HISTORY_TABLE_SQL,
+        CREATE_PASSWORD_HISTORY_GUID_INDEX_SQL,
+        &*CREATE_TRASH_TABLE_SQL,
+        CREATE_SYNC_CONFLICTS_TABLE_SQL,
         &*SET_VERSION_SQL,
     ])?;
     Ok(())
@@ -313,4 +458,84 @@ mod tests {
         db.execute_batch("SELECT enc_unknown_fields FROM loginsM")
             .unwrap();
     }
+
+    #[test]
+    fn test_upgrade_v2() {
+        let connection = Connection::open_in_memory().unwrap();
+        create(&connection).unwrap();
+        // Remove the tables added in v3 and pretend we are v2.
+        connection
+            .execute_batch(
+                "DROP TABLE loginsBreaches;
+                 DROP TABLE loginsBreachDismissals;
+                 PRAGMA user_version = 2;",
+            )
+            .unwrap();
+
+        let db = LoginDb::with_connection(connection).unwrap();
+        let version = db.query_one::<i64>("PRAGMA user_version").unwrap();
+        assert_eq!(version, VERSION);
+        db.execute_batch(
+            "SELECT name, domain, breachDate FROM loginsBreaches;
+             SELECT guid, timeDismissed FROM loginsBreachDismissals;",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_upgrade_v3() {
+        let connection = Connection::open_in_memory().unwrap();
+        create(&connection).unwrap();
+        // Remove the table added in v4 and pretend we are v3.
+        connection
+            .execute_batch(
+                "DROP TABLE loginsPasswordHistory;
+                 PRAGMA user_version = 3;",
+            )
+            .unwrap();
+
+        let db = LoginDb::with_connection(connection).unwrap();
+        let version = db.query_one::<i64>("PRAGMA user_version").unwrap();
+        assert_eq!(version, VERSION);
+        db.execute_batch("SELECT guid, encPassword, timeReplaced FROM loginsPasswordHistory;")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_upgrade_v4() {
+        let connection = Connection::open_in_memory().unwrap();
+        create(&connection).unwrap();
+        // Remove the table added in v5 and pretend we are v4.
+        connection
+            .execute_batch(
+                "DROP TABLE loginsTrash;
+                 PRAGMA user_version = 4;",
+            )
+            .unwrap();
+
+        let db = LoginDb::with_connection(connection).unwrap();
+        let version = db.query_one::<i64>("PRAGMA user_version").unwrap();
+        assert_eq!(version, VERSION);
+        db.execute_batch("SELECT guid, secFields, timeDeleted FROM loginsTrash;")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_upgrade_v5() {
+        let connection = Connection::open_in_memory().unwrap();
+        create(&connection).unwrap();
+        // Remove the table added in v6 and pretend we are v5.
+        connection
+            .execute_batch(
+                "DROP TABLE loginsSyncConflicts;
+                 PRAGMA user_version = 5;",
+            )
+            .unwrap();
+
+        let db = LoginDb::with_connection(connection).unwrap();
+        let version = db.query_one::<i64>("PRAGMA user_version").unwrap();
+        assert_eq!(version, VERSION);
+        db.execute_batch("SELECT guid, conflictingFields, remoteWon FROM loginsSyncConflicts;")
+            .unwrap();
+    }
 }
@@ -1,10 +1,16 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+use crate::audit::{self, LoginAudit};
+use crate::breaches;
+use crate::csv::{self, CsvImportRowResult};
 use crate::db::LoginDb;
 use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
-use crate::login::{EncryptedLogin, Login, LoginEntry};
+use crate::login::{
+    DeletedLogin, EncryptedLogin, Login, LoginEntry, LoginQuery, PasswordHistoryEntry,
+};
+use crate::sync::SyncConflict;
 use crate::LoginsSyncEngine;
 use parking_lot::Mutex;
 use std::path::Path;
@@ -73,6 +79,22 @@ impl LoginStore {
         self.db.lock().get_all()
     }
 
+    /// Find logins matching `query`. Usernames are only matched against the query text if the
+    /// encryption key is supplied, as that requires decrypting them. That's slow for large
+    /// stores: every login which doesn't match on its other fields is decrypted, up to the end
+    /// of the requested page, so callers should set a `limit` where they can.
+    #[handle_error(Error)]
+    pub fn query(
+        &self,
+        query: LoginQuery,
+        enc_key: Option<String>,
+    ) -> ApiResult<Vec<EncryptedLogin>> {
+        let encdec = enc_key
+            .map(|key| EncryptorDecryptor::new(&key))
+            .transpose()?;
+        self.db.lock().query(&query, encdec.as_ref())
+    }
+
     #[handle_error(Error)]
     pub fn get(&self, id: &str) -> ApiResult<Option<EncryptedLogin>> {
         self.db.lock().get_by_id(id)
@@ -83,6 +105,39 @@ impl LoginStore {
         self.db.lock().get_by_base_domain(base_domain)
     }
 
+    /// Replace the known breaches with the records from the `fxmonitor-breaches` Remote
+    /// Settings collection, supplied as a JSON array.
+    #[handle_error(Error)]
+    pub fn update_breaches(&self, breaches_json: &str) -> ApiResult<()> {
+        let breaches = breaches::parse_breaches(breaches_json)?;
+        self.db.lock().replace_breaches(&breaches)
+    }
+
+    #[handle_error(Error)]
+    pub fn get_breached_logins(&self) -> ApiResult<Vec<EncryptedLogin>> {
+        self.db.lock().get_breached_logins()
+    }
+
+    #[handle_error(Error)]
+    pub fn dismiss_breach_alert(&self, id: &str) -> ApiResult<()> {
+        self.db.lock().dismiss_breach_alert(id)
+    }
+
+    /// Get the recent decisions made by sync when a login was changed both locally and remotely.
+    #[handle_error(Error)]
+    pub fn get_sync_conflicts(&self) -> ApiResult<Vec<SyncConflict>> {
+        self.db.lock().get_sync_conflicts()
+    }
+
+    /// Find logins which reuse a password across sites, or which have weak passwords. Every
+    /// password is decrypted once, here, rather than the app needing to decrypt them all.
+    #[handle_error(Error)]
+    pub fn audit(&self, enc_key: &str) -> ApiResult<LoginAudit> {
+        let encdec = EncryptorDecryptor::new(enc_key)?;
+        let logins = self.db.lock().get_all()?;
+        audit::audit_logins(logins, &encdec)
+    }
+
     #[handle_error(Error)]
     pub fn find_login_to_update(
         &self,
@@ -103,6 +158,25 @@ impl LoginStore {
         self.db.lock().delete(id)
     }
 
+    /// Keep deleted logins in the trash for `retention_ms` milliseconds, during which they can be
+    /// restored with `undelete()` and their deletion isn't synced. 0, the default, disables the
+    /// trash and empties it.
+    #[handle_error(Error)]
+    pub fn set_trash_retention(&self, retention_ms: i64) -> ApiResult<()> {
+        self.db.lock().set_trash_retention(retention_ms)
+    }
+
+    #[handle_error(Error)]
+    pub fn list_deleted(&self) -> ApiResult<Vec<DeletedLogin>> {
+        self.db.lock().list_deleted()
+    }
+
+    #[handle_error(Error)]
+    pub fn undelete(&self, id: &str, enc_key: &str) -> ApiResult<EncryptedLogin> {
+        let encdec = EncryptorDecryptor::new(enc_key)?;
+        self.db.lock().undelete(id, &encdec)
+    }
+
     #[handle_error(Error)]
     pub fn wipe(&self) -> ApiResult<()> {
         // This should not be exposed - it wipes the server too and there's
@@ -122,6 +196,32 @@ impl LoginStore {
         Ok(())
     }
 
+    /// Re-encrypt all logins, switching from `old_key` to `new_key`.
+    ///
+    /// `canary` and `canary_text` are the values previously used with `create_canary()` for the
+    /// old key. The old key is checked against them before anything is changed, and
+    /// `IncorrectKey` is returned if it fails. The canary is not valid for the new key, so the
+    /// consumer should create a new one after this succeeds.
+    ///
+    /// Returns the number of records which were re-encrypted.
+    #[handle_error(Error)]
+    pub fn rekey(
+        &self,
+        old_key: &str,
+        new_key: &str,
+        canary: &str,
+        canary_text: &str,
+    ) -> ApiResult<u32> {
+        let old_encdec = EncryptorDecryptor::new(old_key)?;
+        if !old_encdec.check_canary(canary, canary_text)? {
+            return Err(Error::CanaryMismatch);
+        }
+        let new_encdec = EncryptorDecryptor::new(new_key)?;
+        let db = self.db.lock();
+        let scope = db.begin_interrupt_scope()?;
+        db.rekey(&old_encdec, &new_encdec, &scope)
+    }
+
     #[handle_error(Error)]
     pub fn reset(self: Arc<Self>) -> ApiResult<()> {
         // Reset should not exist here - all resets should be done via the
@@ -138,6 +238,34 @@ impl LoginStore {
         self.db.lock().update(id, entry, &encdec)
     }
 
+    /// Keep up to `limit` previous passwords for each login when they are changed by `update()`.
+    /// 0, the default, disables password history and clears any already kept.
+    #[handle_error(Error)]
+    pub fn set_password_history_limit(&self, limit: u32) -> ApiResult<()> {
+        self.db.lock().set_password_history_limit(limit)
+    }
+
+    #[handle_error(Error)]
+    pub fn get_password_history(
+        &self,
+        id: &str,
+        enc_key: &str,
+    ) -> ApiResult<Vec<PasswordHistoryEntry>> {
+        let encdec = EncryptorDecryptor::new(enc_key)?;
+        self.db.lock().get_password_history(id, &encdec)
+    }
+
+    #[handle_error(Error)]
+    pub fn restore_password(
+        &self,
+        id: &str,
+        history_id: i64,
+        enc_key: &str,
+    ) -> ApiResult<EncryptedLogin> {
+        let encdec = EncryptorDecryptor::new(enc_key)?;
+        self.db.lock().restore_password(id, history_id, &encdec)
+    }
+
     #[handle_error(Error)]
     pub fn add(&self, entry: LoginEntry, enc_key: &str) -> ApiResult<EncryptedLogin> {
         let encdec = EncryptorDecryptor::new(enc_key)?;
@@ -150,6 +278,31 @@ impl LoginStore {
         self.db.lock().add_or_update(entry, &encdec)
     }
 
+    /// Import logins from the text of a CSV file in the format desktop Firefox and other browsers
+    /// export. Returns what happened to each row of the file.
+    #[handle_error(Error)]
+    pub fn import_csv(&self, csv_text: &str, enc_key: &str) -> ApiResult<Vec<CsvImportRowResult>> {
+        let encdec = EncryptorDecryptor::new(enc_key)?;
+        let rows = csv::parse_logins(csv_text)?;
+        let db = self.db.lock();
+        let scope = db.begin_interrupt_scope()?;
+        db.import_csv_logins(rows, &encdec, &scope)
+    }
+
+    /// Export all logins as the text of a CSV file which desktop Firefox can import.
+    #[handle_error(Error)]
+    pub fn export_csv(&self, enc_key: &str) -> ApiResult<String> {
+        let encdec = EncryptorDecryptor::new(enc_key)?;
+        let logins = self
+            .db
+            .lock()
+            .get_all()?
+            .into_iter()
+            .map(|login| login.decrypt(&encdec))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(csv::logins_to_csv(&logins))
+    }
+
     /// A convenience wrapper around sync_multiple.
     // Unfortunately, iOS still uses this until they use the sync manager
     // This can almost die later - consumers should never call it (they should
@@ -233,8 +386,9 @@ impl LoginStore {
 mod test {
     use super::*;
     use crate::encryption::test_utils::{TEST_ENCRYPTION_KEY, TEST_ENCRYPTOR};
+    use crate::encryption::{create_canary, create_key};
     use crate::util;
-    use crate::{LoginFields, SecureLoginFields};
+    use crate::{CsvImportOutcome, LoginFields, SecureLoginFields};
     use more_asserts::*;
     use std::cmp::Reverse;
     use std::time::SystemTime;
@@ -368,6 +522,176 @@ mod test {
         assert_eq!(b_after_update.record.times_used, 2);
     }
 
+    #[test]
+    fn test_rekey() {
+        const CANARY_TEXT: &str = "canary text";
+        let store = LoginStore::new_in_memory().unwrap();
+        let canary = create_canary(CANARY_TEXT, &TEST_ENCRYPTION_KEY).unwrap();
+        let entry = LoginEntry {
+            fields: LoginFields {
+                origin: "https://www.example.com".into(),
+                http_realm: Some("the website".into()),
+                ..Default::default()
+            },
+            sec_fields: SecureLoginFields {
+                username: "user".into(),
+                password: "pass".into(),
+            },
+        };
+        let id = store
+            .add(entry.clone(), &TEST_ENCRYPTION_KEY)
+            .unwrap()
+            .record
+            .id;
+        // And a tombstone, which has nothing to re-encrypt.
+        let deleted_id = store
+            .add(
+                LoginEntry {
+                    fields: LoginFields {
+                        origin: "https://www.example2.com".into(),
+                        ..entry.fields.clone()
+                    },
+                    ..entry.clone()
+                },
+                &TEST_ENCRYPTION_KEY,
+            )
+            .unwrap()
+            .record
+            .id;
+        store.delete(&deleted_id).unwrap();
+
+        let new_key = create_key().unwrap();
+        // A key which doesn't match the canary is rejected without changing anything.
+        assert!(matches!(
+            store.rekey(&new_key, &new_key, &canary, CANARY_TEXT),
+            Err(LoginsApiError::IncorrectKey)
+        ));
+        assert!(matches!(
+            store.rekey(&TEST_ENCRYPTION_KEY, &new_key, &canary, "other text"),
+            Err(LoginsApiError::IncorrectKey)
+        ));
+        assert_logins_equiv(&entry, &store.get(&id).unwrap().unwrap());
+
+        assert_eq!(
+            store
+                .rekey(&TEST_ENCRYPTION_KEY, &new_key, &canary, CANARY_TEXT)
+                .unwrap(),
+            1
+        );
+        let new_encdec = EncryptorDecryptor::new(&new_key).unwrap();
+        let login = store.get(&id).unwrap().unwrap();
+        assert_eq!(login.decrypt_fields(&new_encdec).unwrap(), entry.sec_fields);
+        assert!(login.decrypt_fields(&TEST_ENCRYPTOR).is_err());
+    }
+
+    #[test]
+    fn test_csv_import_export() {
+        let store = LoginStore::new_in_memory().unwrap();
+        let existing = store
+            .add(
+                LoginEntry {
+                    fields: LoginFields {
+                        origin: "https://example.com".into(),
+                        form_action_origin: Some("https://example.com".into()),
+                        ..Default::default()
+                    },
+                    sec_fields: SecureLoginFields {
+                        username: "user".into(),
+                        password: "old-pass".into(),
+                    },
+                },
+                &TEST_ENCRYPTION_KEY,
+            )
+            .unwrap();
+
+        let csv_text = "url,username,password,httpRealm,formActionOrigin,guid,timeCreated,timeLastUsed,timePasswordChanged
+https://example.com/path,user,new-pass,,https://example.com,,,,
+https://example.com,user,new-pass,,https://example.com,,,,
+https://example.org,user,pass,,https://example.org,imported-guid,1000,2000,3000
+https://example.org,user,pass,,https://example.org,,,,
+not a url,user,pass,,https://example.org,,,,
+https://example.net,user,,,https://example.net,,,,
+";
+        let results = store
+            .import_csv(csv_text, &TEST_ENCRYPTION_KEY)
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.line, r.outcome))
+            .collect::<Vec<_>>();
+        assert_eq!(results.len(), 6);
+        // The origin is normalized, making this a dupe with a newer password.
+        assert_eq!(
+            results[0],
+            (
+                2,
+                CsvImportOutcome::Updated {
+                    id: existing.record.id.clone()
+                }
+            )
+        );
+        assert_eq!(
+            results[1],
+            (
+                3,
+                CsvImportOutcome::SkippedDupe {
+                    id: existing.record.id.clone()
+                }
+            )
+        );
+        assert_eq!(
+            results[2],
+            (
+                4,
+                CsvImportOutcome::Added {
+                    id: "imported-guid".into()
+                }
+            )
+        );
+        // Dupes within the file are detected too.
+        assert_eq!(
+            results[3],
+            (
+                5,
+                CsvImportOutcome::SkippedDupe {
+                    id: "imported-guid".into()
+                }
+            )
+        );
+        assert!(matches!(results[4], (6, CsvImportOutcome::Invalid { .. })));
+        assert!(matches!(results[5], (7, CsvImportOutcome::Invalid { .. })));
+
+        let updated = store.get(&existing.record.id).unwrap().unwrap();
+        assert_eq!(
+            updated.decrypt_fields(&TEST_ENCRYPTOR).unwrap().password,
+            "new-pass"
+        );
+        let imported = store.get("imported-guid").unwrap().unwrap();
+        assert_eq!(imported.record.time_created, 1000);
+        assert_eq!(imported.record.time_last_used, 2000);
+        assert_eq!(imported.record.time_password_changed, 3000);
+
+        // Exporting and re-importing into a new store gives us the same logins.
+        let exported = store.export_csv(&TEST_ENCRYPTION_KEY).unwrap();
+        let store2 = LoginStore::new_in_memory().unwrap();
+        let results = store2.import_csv(&exported, &TEST_ENCRYPTION_KEY).unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results
+            .iter()
+            .all(|r| matches!(r.outcome, CsvImportOutcome::Added { .. })));
+        let mut list = store2.list().unwrap();
+        let mut expect = store.list().unwrap();
+        list.sort_by_key(|l| l.guid());
+        expect.sort_by_key(|l| l.guid());
+        for (a, b) in list.iter().zip(expect.iter()) {
+            assert_eq!(a.record.id, b.record.id);
+            assert_eq!(a.fields, b.fields);
+            assert_eq!(
+                a.decrypt_fields(&TEST_ENCRYPTOR).unwrap(),
+                b.decrypt_fields(&TEST_ENCRYPTOR).unwrap()
+            );
+        }
+    }
+
     #[test]
     fn test_sync_manager_registration() {
         let store = Arc::new(LoginStore::new_in_memory().unwrap());
@@ -242,13 +242,24 @@ impl LoginsSyncEngine {
         const TOMBSTONE_SORTINDEX: i32 = 5_000_000;
         const DEFAULT_SORTINDEX: i32 = 1;
         let db = self.store.db.lock();
+        // Forget expired logins here too, so they don't stay on disk if the trash is never
+        // looked at.
+        let tx = db.unchecked_transaction()?;
+        db.purge_expired_trash()?;
+        tx.commit()?;
+        // Tombstones for logins which are still in the trash are held back until they expire.
+        let trash_cutoff = db.trash_cutoff()?;
         let mut stmt = db.prepare_cached(&format!(
             "SELECT L.*, M.enc_unknown_fields
              FROM loginsL L LEFT JOIN loginsM M ON L.guid = M.guid
-             WHERE sync_status IS NOT {synced}",
+             WHERE sync_status IS NOT {synced}
+               AND NOT (
+                   L.is_deleted
+                   AND L.guid IN (SELECT guid FROM loginsTrash WHERE timeDeleted > :trash_cutoff)
+               )",
             synced = SyncStatus::Synced as u8
         ))?;
-        let bsos = stmt.query_and_then([], |row| {
+        let bsos = stmt.query_and_then(named_params! { ":trash_cutoff": trash_cutoff }, |row| {
             self.scope.err_if_interrupted()?;
             Ok(if row.get::<_, bool>("is_deleted")? {
                 let envelope = OutgoingEnvelope {
@@ -673,6 +684,38 @@ mod tests {
         assert!(changes["changed"].get("deleted").is_none());
     }
 
+    #[test]
+    fn test_fetch_outgoing_trash() {
+        let store = LoginStore::new_in_memory().unwrap();
+        insert_login(&store.db.lock(), "trashed", None, Some("password"));
+        insert_login(&store.db.lock(), "expired", None, Some("password"));
+        store.db.lock().set_trash_retention(60_000).unwrap();
+        store.db.lock().delete("trashed").unwrap();
+        store.db.lock().delete("expired").unwrap();
+        store
+            .db
+            .lock()
+            .execute_batch("UPDATE loginsTrash SET timeDeleted = 0 WHERE guid = 'expired'")
+            .unwrap();
+
+        // Only the tombstone for the login whose retention period has ended is uploaded.
+        let store = Arc::new(store);
+        let mut engine = LoginsSyncEngine::new(Arc::clone(&store)).unwrap();
+        engine
+            .set_local_encryption_key(&TEST_ENCRYPTION_KEY)
+            .unwrap();
+        let changeset = engine.fetch_outgoing().unwrap();
+        assert_eq!(changeset.len(), 1);
+        assert_eq!(changeset[0].envelope.id, "expired");
+        // And the expired login has left the trash.
+        let trashed: Vec<String> = store
+            .db
+            .lock()
+            .query_rows_and_then("SELECT guid FROM loginsTrash", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(trashed, vec!["trashed"]);
+    }
+
     #[test]
     fn test_bad_record() {
         let store = LoginStore::new_in_memory().unwrap();
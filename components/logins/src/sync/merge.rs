@@ -180,6 +180,31 @@ macro_rules! impl_login_setter {
 impl_login_setter!(set_local, local, LocalLogin);
 impl_login_setter!(set_mirror, mirror, MirrorLogin);
 
+/// Which side's changes were preferred when a login changed both locally and remotely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncConflictWinner {
+    Local,
+    Remote,
+}
+
+/// A record of a three-way merge done by sync, from `LoginStore::get_sync_conflicts()`. Only the
+/// names of the conflicting fields are recorded, never their values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncConflict {
+    /// The id of the login.
+    pub id: String,
+    /// The fields which were changed to different values locally and remotely, for which the
+    /// winner's value was used. Other changes from both sides are kept.
+    pub conflicting_fields: Vec<String>,
+    pub winner: SyncConflictWinner,
+    /// When the login was changed locally, in milliseconds since the epoch.
+    pub local_modified: i64,
+    /// When the login was changed remotely, in milliseconds since the epoch.
+    pub remote_modified: i64,
+    /// When the merge happened, in milliseconds since the epoch.
+    pub time_merged: i64,
+}
+
 #[derive(Debug, Default, Clone)]
 pub(crate) struct LoginDelta {
     // "non-commutative" fields
@@ -216,7 +241,37 @@ macro_rules! merge_field {
     };
 }
 
+macro_rules! conflicting_fields {
+    ($a:ident, $b:ident, [$($field:ident),*]) => {{
+        let mut fields = Vec::new();
+        $(
+            if matches!((&$a.$field, &$b.$field), (Some(a), Some(b)) if a != b) {
+                fields.push(stringify!($field).to_string());
+            }
+        )*
+        fields
+    }};
+}
+
 impl LoginDelta {
+    /// The names of the fields which both deltas change, to different values. Timestamps and
+    /// the use count aren't included, as they are expected to change on both sides.
+    pub fn conflicting_fields(&self, other: &LoginDelta) -> Vec<String> {
+        conflicting_fields!(
+            self,
+            other,
+            [
+                origin,
+                password,
+                username,
+                http_realm,
+                form_action_origin,
+                password_field,
+                username_field
+            ]
+        )
+    }
+
     #[allow(clippy::cognitive_complexity)] // Looks like clippy considers this after macro-expansion...
     pub fn merge(self, mut b: LoginDelta, b_is_newer: bool) -> LoginDelta {
         let mut merged = self;
@@ -363,6 +418,25 @@ mod tests {
     use super::*;
     use crate::encryption::test_utils::TEST_ENCRYPTOR;
 
+    #[test]
+    fn test_conflicting_fields() {
+        let local = LoginDelta {
+            password: Some("local".into()),
+            username: Some("same".into()),
+            origin: Some("https://local.example.com".into()),
+            time_last_used: Some(1),
+            ..Default::default()
+        };
+        let remote = LoginDelta {
+            password: Some("remote".into()),
+            username: Some("same".into()),
+            http_realm: Some("realm".into()),
+            time_last_used: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(local.conflicting_fields(&remote), vec!["password"]);
+    }
+
     #[test]
     fn test_invalid_payload_timestamps() {
         #[allow(clippy::unreadable_literal)]
@@ -10,6 +10,7 @@ mod update_plan;
 use crate::error::*;
 pub use engine::LoginsSyncEngine;
 pub(crate) use merge::{LocalLogin, MirrorLogin};
+pub use merge::{SyncConflict, SyncConflictWinner};
 pub(self) use payload::{IncomingLogin, LoginPayload};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -2,11 +2,12 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use super::merge::{LocalLogin, MirrorLogin};
+use super::merge::{LocalLogin, MirrorLogin, SyncConflict, SyncConflictWinner};
 use super::{IncomingLogin, SyncStatus};
 use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
 use crate::login::EncryptedLogin;
+use crate::schema;
 use crate::util;
 use interrupt_support::SqlInterruptScope;
 use rusqlite::{named_params, Connection};
@@ -22,6 +23,7 @@ pub(super) struct UpdatePlan {
     // the bool is the `is_overridden` flag, the i64 is ServerTimestamp in millis
     pub mirror_inserts: Vec<(IncomingLogin, i64, bool)>,
     pub mirror_updates: Vec<(IncomingLogin, i64)>,
+    pub sync_conflicts: Vec<SyncConflict>,
 }
 
 impl UpdatePlan {
@@ -56,7 +58,20 @@ impl UpdatePlan {
         let local_delta = local.login.delta(&shared.login, encdec)?;
         let upstream_delta = upstream.login.delta(&shared.login, encdec)?;
 
-        let merged_delta = local_delta.merge(upstream_delta, remote_age < local_age);
+        let remote_won = remote_age < local_age;
+        self.sync_conflicts.push(SyncConflict {
+            id: local.guid_str().to_string(),
+            conflicting_fields: local_delta.conflicting_fields(&upstream_delta),
+            winner: if remote_won {
+                SyncConflictWinner::Remote
+            } else {
+                SyncConflictWinner::Local
+            },
+            local_modified: util::system_time_ms_i64(local.local_modified),
+            remote_modified: upstream_time.as_millis(),
+            time_merged: util::system_time_ms_i64(SystemTime::now()),
+        });
+        let merged_delta = local_delta.merge(upstream_delta, remote_won);
 
         // Update mirror to upstream
         self.mirror_updates
@@ -101,11 +116,28 @@ impl UpdatePlan {
             Ok(())
         })?;
 
+        // `delete_local` is also used when a two-way merge takes the incoming login, so it's only
+        // logins deleted from the mirror which are really gone. Their password history and breach
+        // alert dismissals go too, unless they're in the trash, which has its own rules for that.
         sql_support::each_chunk(&self.delete_mirror, |chunk, _| {
+            let vars = sql_support::repeat_sql_vars(chunk.len());
+            conn.execute(
+                &format!("DELETE FROM loginsM WHERE guid IN ({vars})"),
+                rusqlite::params_from_iter(chunk),
+            )?;
             conn.execute(
                 &format!(
-                    "DELETE FROM loginsM WHERE guid IN ({vars})",
-                    vars = sql_support::repeat_sql_vars(chunk.len())
+                    "DELETE FROM loginsPasswordHistory
+                     WHERE guid IN ({vars})
+                       AND guid NOT IN (SELECT guid FROM loginsTrash)"
+                ),
+                rusqlite::params_from_iter(chunk),
+            )?;
+            conn.execute(
+                &format!(
+                    "DELETE FROM loginsBreachDismissals
+                     WHERE guid IN ({vars})
+                       AND guid NOT IN (SELECT guid FROM loginsTrash)"
                 ),
                 rusqlite::params_from_iter(chunk),
             )?;
@@ -261,6 +293,43 @@ impl UpdatePlan {
         Ok(())
     }
 
+    fn perform_sync_conflict_inserts(
+        &self,
+        conn: &Connection,
+        scope: &SqlInterruptScope,
+    ) -> Result<()> {
+        if self.sync_conflicts.is_empty() {
+            return Ok(());
+        }
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO loginsSyncConflicts (
+                 guid, conflictingFields, remoteWon, localModified, remoteModified, timeMerged
+             ) VALUES (
+                 :guid, :conflicting_fields, :remote_won, :local_modified, :remote_modified,
+                 :time_merged
+             )",
+        )?;
+        for conflict in &self.sync_conflicts {
+            stmt.execute(named_params! {
+                ":guid": conflict.id,
+                ":conflicting_fields": conflict.conflicting_fields.join(","),
+                ":remote_won": conflict.winner == SyncConflictWinner::Remote,
+                ":local_modified": conflict.local_modified,
+                ":remote_modified": conflict.remote_modified,
+                ":time_merged": conflict.time_merged,
+            })?;
+            scope.err_if_interrupted()?;
+        }
+        conn.execute(
+            "DELETE FROM loginsSyncConflicts
+             WHERE id NOT IN (
+                 SELECT id FROM loginsSyncConflicts ORDER BY id DESC LIMIT :max
+             )",
+            named_params! { ":max": schema::MAX_SYNC_CONFLICTS },
+        )?;
+        Ok(())
+    }
+
     pub fn execute(&self, conn: &Connection, scope: &SqlInterruptScope) -> Result<()> {
         log::debug!(
             "UpdatePlan: deleting {} records...",
@@ -282,6 +351,11 @@ impl UpdatePlan {
             self.local_updates.len()
         );
         self.perform_local_updates(conn, scope)?;
+        log::debug!(
+            "UpdatePlan: Recording {} sync conflicts...",
+            self.sync_conflicts.len()
+        );
+        self.perform_sync_conflict_inserts(conn, scope)?;
         Ok(())
     }
 }
@@ -295,6 +369,7 @@ mod tests {
     };
     use crate::db::LoginDb;
     use crate::login::test_utils::enc_login;
+    use sql_support::ConnExt;
 
     fn inc_login(id: &str, password: &str) -> crate::sync::IncomingLogin {
         IncomingLogin {
@@ -323,6 +398,57 @@ mod tests {
         assert_eq!(get_mirror_guids(&db), vec!["login3", "login4"]);
     }
 
+    #[test]
+    fn test_deletes_forget_history_and_dismissals() {
+        let db = LoginDb::open_in_memory().unwrap();
+        for guid in ["deleted", "trashed", "merged"] {
+            insert_login(&db, guid, Some("password"), Some("password"));
+            db.execute(
+                "INSERT INTO loginsPasswordHistory (guid, encPassword, timeReplaced)
+                 VALUES (:guid, 'old', 1000)",
+                named_params! { ":guid": guid },
+            )
+            .unwrap();
+            db.execute(
+                "INSERT INTO loginsBreachDismissals (guid, timeDismissed) VALUES (:guid, 1000)",
+                named_params! { ":guid": guid },
+            )
+            .unwrap();
+        }
+        db.execute_batch(&format!(
+            "INSERT INTO loginsTrash ({common_cols}, timeDeleted)
+             SELECT {common_cols}, 1000 FROM loginsL WHERE guid = 'trashed'",
+            common_cols = schema::COMMON_COLS,
+        ))
+        .unwrap();
+
+        // An incoming tombstone for "deleted" and "trashed", and a two-way merge which takes
+        // the incoming "merged".
+        let mut plan = UpdatePlan::default();
+        plan.plan_delete(Guid::new("deleted"));
+        plan.plan_delete(Guid::new("trashed"));
+        plan.delete_local.push(Guid::new("merged"));
+        plan.execute(&db, &db.begin_interrupt_scope().unwrap())
+            .unwrap();
+
+        let history_guids: Vec<String> = db
+            .query_rows_and_then(
+                "SELECT guid FROM loginsPasswordHistory ORDER BY guid",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(history_guids, vec!["merged", "trashed"]);
+        let dismissal_guids: Vec<String> = db
+            .query_rows_and_then(
+                "SELECT guid FROM loginsBreachDismissals ORDER BY guid",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(dismissal_guids, vec!["merged", "trashed"]);
+    }
+
     #[test]
     fn test_mirror_updates() {
         let db = LoginDb::open_in_memory().unwrap();
@@ -384,4 +510,33 @@ mod tests {
         .unwrap();
         check_local_login(&db, "login", "new-password", before_update);
     }
+
+    #[test]
+    fn test_sync_conflicts() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let conflict = |id: &str| SyncConflict {
+            id: id.into(),
+            conflicting_fields: vec!["password".into(), "username".into()],
+            winner: SyncConflictWinner::Remote,
+            local_modified: 1000,
+            remote_modified: 2000,
+            time_merged: 3000,
+        };
+        UpdatePlan {
+            sync_conflicts: (0..schema::MAX_SYNC_CONFLICTS + 1)
+                .map(|i| conflict(&format!("login{i}")))
+                .collect(),
+            ..UpdatePlan::default()
+        }
+        .execute(&db, &db.begin_interrupt_scope().unwrap())
+        .unwrap();
+        let conflicts = db.get_sync_conflicts().unwrap();
+        assert_eq!(conflicts.len(), schema::MAX_SYNC_CONFLICTS as usize);
+        // Newest first, with the oldest dropped.
+        assert_eq!(
+            conflicts[0],
+            conflict(&format!("login{}", schema::MAX_SYNC_CONFLICTS))
+        );
+        assert_eq!(conflicts.last().unwrap().id, "login1");
+    }
 }
@@ -5,6 +5,8 @@
 package mozilla.appservices.places
 
 import mozilla.appservices.places.uniffi.BookmarkItem
+import mozilla.appservices.places.uniffi.BookmarksMigrationResult
+import mozilla.appservices.places.uniffi.TagInfo
 
 /**
  * Enumeration of the ids of the roots of the bookmarks tree.
@@ -91,6 +93,14 @@ interface ReadableBookmarksConnection : InterruptibleConnection {
      */
     fun getBookmarkUrlForKeyword(keyword: String): Url?
 
+    /**
+     * Returns the search keywords for a URL. A URL has at most one keyword.
+     *
+     * @param url The URL to get the keywords for.
+     * @return The keywords, or an empty list if the URL is invalid or has none.
+     */
+    fun getKeywordsForUrl(url: String): List<String>
+
     /**
      * Returns the list of bookmarks that match the provided search string.
      *
@@ -119,6 +129,39 @@ interface ReadableBookmarksConnection : InterruptibleConnection {
      * has its `interrupt()` method called on another thread.
      */
     fun getRecentBookmarks(limit: Int): List<BookmarkItem>
+
+    /**
+     * Exports all bookmarks in the Netscape `bookmarks.html` format which
+     * other browsers can import.
+     *
+     * @return The contents of the `bookmarks.html` file.
+     */
+    fun exportBookmarksToHtml(): String
+
+    /**
+     * Returns the tags for a URL, most recently modified first.
+     *
+     * @param url The URL to get the tags for.
+     * @return The tags, or an empty list if the URL is invalid or unknown.
+     */
+    fun getTagsForUrl(url: String): List<String>
+
+    /**
+     * Returns the URLs which have a tag.
+     *
+     * @param tag The tag to look for.
+     * @return The URLs with the tag.
+     *
+     * @throws InvalidBookmarkOperation If `tag` is empty or too long.
+     */
+    fun getUrlsWithTag(tag: String): List<Url>
+
+    /**
+     * Returns every tag which is in use, along with how many URLs have it.
+     *
+     * @return The tags, with the most used first.
+     */
+    fun getAllTags(): List<TagInfo>
 }
 
 /**
@@ -229,4 +272,101 @@ interface WritableBookmarksConnection : ReadableBookmarksConnection {
      * folder node.
      */
     fun updateBookmark(guid: Guid, parentGuid: Guid?, position: UInt?, title: String?, url: Url?)
+
+    /**
+     * Import the bookmarks in a Netscape `bookmarks.html` file, as exported by
+     * other browsers.
+     *
+     * The contents of folders marked as the toolbar or "other bookmarks" folder
+     * are imported into those roots, and everything else into `parentGUID`.
+     *
+     * @param html The contents of the `bookmarks.html` file.
+     * @param parentGUID The GUID of the folder to import the bookmarks into.
+     * @return The number of items imported.
+     *
+     * @throws CannotUpdateRoot If `parentGUID` is the [BookmarkRoot.Root] (e.g. "root________")
+     * @throws UnknownBookmarkItem If `parentGUID` does not refer to to a known bookmark.
+     * @throws InvalidParent If `parentGUID` does not refer to a folder node.
+     */
+    fun importBookmarksFromHtml(html: String, parentGUID: Guid): UInt
+
+    /**
+     * Import the bookmarks from a Chrome (or other Chromium-based browser)
+     * profile.
+     *
+     * The contents of Chrome's bookmarks bar, "other bookmarks" and mobile
+     * bookmarks are appended to the toolbar, unfiled and mobile roots.
+     *
+     * @param path The path to the profile's `Bookmarks` file.
+     * @return The number of bookmarks found, imported and skipped.
+     */
+    fun importBookmarksFromChrome(path: String): BookmarksMigrationResult
+
+    /**
+     * Tag a URL. Any bookmarks for the URL will be uploaded with the tag.
+     *
+     * @param url The URL to tag, which must already be known (for example,
+     * because it's bookmarked).
+     * @param tag The tag to add.
+     *
+     * @throws InvalidBookmarkOperation If `tag` is empty or too long.
+     * @throws UrlParseFailed If `url` is invalid.
+     */
+    fun tagUrl(url: String, tag: String)
+
+    /**
+     * Remove a tag from a URL. Does nothing if the URL doesn't have the tag.
+     *
+     * @param url The URL to untag.
+     * @param tag The tag to remove.
+     *
+     * @throws InvalidBookmarkOperation If `tag` is empty or too long.
+     */
+    fun untagUrl(url: String, tag: String)
+
+    /**
+     * Remove a tag from every URL which has it.
+     *
+     * @param tag The tag to remove.
+     */
+    fun removeTag(tag: String)
+
+    /**
+     * Rename a tag on every URL which has it, merging it into `newTag` if
+     * that tag already exists. Bookmarks for those URLs will be uploaded with
+     * the new tag.
+     *
+     * @param oldTag The tag to rename.
+     * @param newTag The new name for the tag.
+     *
+     * @throws InvalidBookmarkOperation If either tag is empty or too long.
+     */
+    fun renameTag(oldTag: String, newTag: String)
+
+    /**
+     * Set the search keyword for a URL. Keywords are case-insensitive.
+     *
+     * A URL can only have one keyword, and a keyword can only belong to one
+     * URL, so this replaces any existing keyword for `url`, and moves `keyword`
+     * if another URL has it. Bookmarks for both URLs will be uploaded.
+     *
+     * @param url The URL, which must already be known (for example, because
+     * it's bookmarked).
+     * @param keyword The keyword.
+     * @param postData Must be null - POST data for keywords isn't supported.
+     *
+     * @throws InvalidBookmarkOperation If `keyword` is empty, or `postData` isn't null.
+     * @throws UrlParseFailed If `url` is invalid.
+     */
+    fun setBookmarkKeyword(url: String, keyword: String, postData: String? = null)
+
+    /**
+     * Remove a search keyword.
+     *
+     * @param keyword The keyword to remove.
+     * @return Whether the keyword existed.
+     *
+     * @throws InvalidBookmarkOperation If `keyword` is empty.
+     */
+    fun removeBookmarkKeyword(keyword: String): Boolean
 }
@@ -7,6 +7,7 @@ package mozilla.appservices.places
 import mozilla.appservices.places.uniffi.BookmarkItem
 import mozilla.appservices.places.uniffi.BookmarkPosition
 import mozilla.appservices.places.uniffi.BookmarkUpdateInfo
+import mozilla.appservices.places.uniffi.BookmarksMigrationResult
 import mozilla.appservices.places.uniffi.ConnectionType
 import mozilla.appservices.places.uniffi.DocumentType
 import mozilla.appservices.places.uniffi.FrecencyThresholdOption
@@ -14,6 +15,7 @@ import mozilla.appservices.places.uniffi.HistoryHighlight
 import mozilla.appservices.places.uniffi.HistoryHighlightWeights
 import mozilla.appservices.places.uniffi.HistoryMetadata
 import mozilla.appservices.places.uniffi.HistoryMetadataObservation
+import mozilla.appservices.places.uniffi.HistoryMigrationResult
 import mozilla.appservices.places.uniffi.HistoryVisitInfo
 import mozilla.appservices.places.uniffi.HistoryVisitInfosWithBound
 import mozilla.appservices.places.uniffi.InsertableBookmark
@@ -23,6 +25,7 @@ import mozilla.appservices.places.uniffi.InsertableBookmarkSeparator
 import mozilla.appservices.places.uniffi.PlacesApiException
 import mozilla.appservices.places.uniffi.SearchResult
 import mozilla.appservices.places.uniffi.SqlInterruptHandle
+import mozilla.appservices.places.uniffi.TagInfo
 import mozilla.appservices.places.uniffi.TopFrecentSiteInfo
 import mozilla.appservices.places.uniffi.VisitObservation
 import mozilla.appservices.places.uniffi.placesApiNew
@@ -154,6 +157,12 @@ open class PlacesReaderConnection internal constructor(conn: UniffiPlacesConnect
         return this.conn.queryAutocomplete(query, limit)
     }
 
+    override fun querySearchIndex(query: String, limit: Int): List<SearchResult> {
+        return readQueryCounters.measure {
+            this.conn.querySearchIndex(query, limit)
+        }
+    }
+
     override fun matchUrl(query: String): Url? {
         return this.conn.matchUrl(query)
     }
@@ -250,6 +259,12 @@ open class PlacesReaderConnection internal constructor(conn: UniffiPlacesConnect
         return this.conn.bookmarksGetUrlForKeyword(keyword)
     }
 
+    override fun getKeywordsForUrl(url: String): List<String> {
+        return readQueryCounters.measure {
+            this.conn.bookmarksGetKeywordsForUrl(url)
+        }
+    }
+
     override fun searchBookmarks(query: String, limit: Int): List<BookmarkItem> {
         return readQueryCounters.measure {
             this.conn.bookmarksSearch(query, limit)
@@ -262,6 +277,30 @@ open class PlacesReaderConnection internal constructor(conn: UniffiPlacesConnect
         }
     }
 
+    override fun exportBookmarksToHtml(): String {
+        return readQueryCounters.measure {
+            this.conn.bookmarksExportToHtml()
+        }
+    }
+
+    override fun getTagsForUrl(url: String): List<String> {
+        return readQueryCounters.measure {
+            this.conn.getTagsForUrl(url)
+        }
+    }
+
+    override fun getUrlsWithTag(tag: String): List<Url> {
+        return readQueryCounters.measure {
+            this.conn.getUrlsWithTag(tag)
+        }
+    }
+
+    override fun getAllTags(): List<TagInfo> {
+        return readQueryCounters.measure {
+            this.conn.getAllTags()
+        }
+    }
+
     private val readQueryCounters: PlacesManagerCounterMetrics by lazy {
         PlacesManagerCounterMetrics(
             PlacesManagerMetrics.readQueryCount,
@@ -309,6 +348,12 @@ class PlacesWriterConnection internal constructor(conn: UniffiPlacesConnection,
         }
     }
 
+    override fun importHistoryFromChrome(dbPath: String): HistoryMigrationResult {
+        return writeQueryCounters.measure {
+            this.conn.placesHistoryImportFromChrome(dbPath)
+        }
+    }
+
     override fun deleteVisitsSince(since: Long) {
         deleteVisitsBetween(since, Long.MAX_VALUE)
     }
@@ -345,6 +390,12 @@ class PlacesWriterConnection internal constructor(conn: UniffiPlacesConnection,
         PlacesManagerMetrics.dbSizeAfterMaintenance.accumulateSamples(listOf(pruneMetrics.dbSizeAfter.toLong() / 1024))
     }
 
+    override fun rebuildSearchIndex() {
+        return writeQueryCounters.measure {
+            this.conn.runMaintenanceRebuildSearchIndex()
+        }
+    }
+
     override fun pruneDestructively() {
         this.conn.pruneDestructively()
     }
@@ -463,6 +514,54 @@ class PlacesWriterConnection internal constructor(conn: UniffiPlacesConnection,
         }
     }
 
+    override fun importBookmarksFromHtml(html: String, parentGUID: Guid): UInt {
+        return writeQueryCounters.measure {
+            this.conn.bookmarksImportFromHtml(html, parentGUID)
+        }
+    }
+
+    override fun importBookmarksFromChrome(path: String): BookmarksMigrationResult {
+        return writeQueryCounters.measure {
+            this.conn.placesBookmarksImportFromChrome(path)
+        }
+    }
+
+    override fun tagUrl(url: String, tag: String) {
+        return writeQueryCounters.measure {
+            this.conn.tagUrl(url, tag)
+        }
+    }
+
+    override fun untagUrl(url: String, tag: String) {
+        return writeQueryCounters.measure {
+            this.conn.untagUrl(url, tag)
+        }
+    }
+
+    override fun removeTag(tag: String) {
+        return writeQueryCounters.measure {
+            this.conn.removeTag(tag)
+        }
+    }
+
+    override fun renameTag(oldTag: String, newTag: String) {
+        return writeQueryCounters.measure {
+            this.conn.renameTag(oldTag, newTag)
+        }
+    }
+
+    override fun setBookmarkKeyword(url: String, keyword: String, postData: String?) {
+        return writeQueryCounters.measure {
+            this.conn.bookmarksSetKeyword(url, keyword, postData)
+        }
+    }
+
+    override fun removeBookmarkKeyword(keyword: String): Boolean {
+        return writeQueryCounters.measure {
+            this.conn.bookmarksRemoveKeyword(keyword)
+        }
+    }
+
     override fun acceptResult(searchString: String, url: String) {
         return this.conn.acceptResult(searchString, url)
     }
@@ -664,6 +763,18 @@ interface ReadableHistoryConnection : InterruptibleConnection {
      */
     fun queryAutocomplete(query: String, limit: Int): List<SearchResult>
 
+    /**
+     * Search the optional full-text search index, matching every word in
+     * [query] as a prefix of a word in a page's title, URL or bookmark titles.
+     * This is much faster than [queryAutocomplete] on large databases, but
+     * returns nothing until the index has been built with [rebuildSearchIndex].
+     *
+     * @param query a string to match results against.
+     * @param limit a maximum number of results to retrieve.
+     * @return a list of [SearchResult] matching the [query], best matches first.
+     */
+    fun querySearchIndex(query: String, limit: Int): List<SearchResult>
+
     /**
      * See if a url that's sufficiently close to `search` exists in
      * the database.
@@ -802,6 +913,14 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      */
     fun runMaintenance(dbSizeLimit: UInt = 0U)
 
+    /**
+     * Build the full-text search index used by [querySearchIndex] from
+     * scratch. The index is kept up to date after the first build, including
+     * for changes made by Sync, so this only needs calling once, when the app
+     * is idle. It reads every page, so it's not part of [runMaintenance].
+     */
+    fun rebuildSearchIndex()
+
     /**
      * Aggressively prune history visits. These deletions are not intended
      * to be synced, however due to the way history sync works, this can
@@ -881,6 +1000,16 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      */
     fun deleteVisit(url: String, visitTimestamp: Long)
 
+    /**
+     * Import the history from a Chrome (or other Chromium-based browser)
+     * profile. Visits which already exist are skipped, so this can be re-run
+     * after being interrupted.
+     *
+     * @param dbPath The path to the profile's `History` database.
+     * @return The number of visits found, imported and skipped.
+     */
+    fun importHistoryFromChrome(dbPath: String): HistoryMigrationResult
+
     /**
      * Records an accepted autocomplete match, recording the query string,
      * and chosen URL for subsequent matches.
@@ -333,6 +333,23 @@ public class PlacesReadConnection {
         }
     }
 
+    /**
+     * Returns the search keywords for a URL. A URL has at most one keyword.
+     *
+     * - Parameter url: The URL to get the keywords for.
+     * - Returns: The keywords, or an empty list if the URL is invalid or has none.
+     * - Throws:
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     */
+    open func getKeywordsForUrl(url: String) throws -> [String] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksGetKeywordsForUrl(url: url)
+        }
+    }
+
     /**
      * Returns the list of bookmarks that match the provided search string.
      *
@@ -398,6 +415,77 @@ public class PlacesReadConnection {
         }
     }
 
+    /**
+     * Exports all bookmarks in the Netscape `bookmarks.html` format which other
+     * browsers can import.
+     *
+     * - Returns: The contents of the `bookmarks.html` file.
+     * - Throws:
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     *     - `PlacesApiError.unexpected`: When an error that has not specifically been exposed
+     *                                    to Swift is encountered (for example IO errors from
+     *                                    the database code, etc).
+     */
+    open func exportBookmarksToHtml() throws -> String {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksExportToHtml()
+        }
+    }
+
+    /**
+     * Returns the tags for a URL, most recently modified first.
+     *
+     * - Parameter url: The URL to get the tags for.
+     * - Returns: The tags, or an empty list if the URL is invalid or unknown.
+     * - Throws:
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     */
+    open func getTagsForUrl(url: String) throws -> [String] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getTagsForUrl(url: url)
+        }
+    }
+
+    /**
+     * Returns the URLs which have a tag.
+     *
+     * - Parameter tag: The tag to look for.
+     * - Returns: The URLs with the tag.
+     * - Throws:
+     *     - `PlacesApiError.invalidBookmarkOperation`: If `tag` is empty or too long.
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     */
+    open func getUrlsWithTag(tag: String) throws -> [Url] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getUrlsWithTag(tag: tag)
+        }
+    }
+
+    /**
+     * Returns every tag which is in use, with the most used first.
+     *
+     * - Returns: The tags, along with how many URLs have each one.
+     * - Throws:
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     */
+    open func getAllTags() throws -> [TagInfo] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.getAllTags()
+        }
+    }
+
     open func getLatestHistoryMetadataForUrl(url: Url) throws -> HistoryMetadata? {
         return try queue.sync {
             try self.checkApi()
@@ -449,6 +537,22 @@ public class PlacesReadConnection {
         }
     }
 
+    /**
+     * Search the optional full-text search index, matching every word in `search`
+     * as a prefix of a word in a page's title, URL or bookmark titles. Returns nothing
+     * until the index has been built with `rebuildSearchIndex()`.
+     *
+     * - Parameter search: The string to match results against.
+     * - Parameter limit: The maximum number of results to return.
+     * - Returns: The matching results, best matches first.
+     */
+    open func querySearchIndex(search: String, limit: Int32) throws -> [SearchResult] {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.querySearchIndex(search: search, limit: limit)
+        }
+    }
+
     open func getVisitUrlsInRange(start: PlacesTimestamp, end: PlacesTimestamp, includeRemote: Bool)
         throws -> [Url]
     {
@@ -568,6 +672,29 @@ public class PlacesWriteConnection: PlacesReadConnection {
         }
     }
 
+    /**
+     * Build the full-text search index used by `querySearchIndex` from scratch.
+     *
+     * The index is kept up to date after the first build, including for changes
+     * made by Sync, so this only needs calling once, when the app is idle.
+     *
+     * - Throws:
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: if the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     *     - `PlacesApiError.unexpected`: When an error that has not specifically been exposed
+     *                                    to Swift is encountered (for example IO errors from
+     *                                    the database code, etc).
+     *     - `PlacesApiError.panic`: If the rust code panics while completing this
+     *                               operation. (If this occurs, please let us know).
+     */
+    open func rebuildSearchIndex() throws {
+        return try queue.sync {
+            try self.checkApi()
+            try self.conn.runMaintenanceRebuildSearchIndex()
+        }
+    }
+
     /**
      * Delete the bookmark with the provided GUID.
      *
@@ -787,6 +914,167 @@ public class PlacesWriteConnection: PlacesReadConnection {
         }
     }
 
+    /**
+     * Import the bookmarks in a Netscape `bookmarks.html` file, as exported by
+     * other browsers.
+     *
+     * The contents of folders marked as the toolbar or "other bookmarks" folder
+     * are imported into those roots, and everything else into `parentGUID`.
+     *
+     * - Parameter html: The contents of the `bookmarks.html` file.
+     * - Parameter parentGUID: The GUID of the folder to import the bookmarks into.
+     * - Returns: The number of items imported.
+     * - Throws:
+     *     - `PlacesApiError.cannotUpdateRoot`: If `parentGUID` is the root.
+     *     - `PlacesApiError.noSuchItem`: If `parentGUID` does not refer to a known bookmark.
+     *     - `PlacesApiError.invalidParent`: If `parentGUID` does not refer to a folder.
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     */
+    open func importBookmarksFromHtml(html: String, parentGUID: Guid) throws -> UInt32 {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksImportFromHtml(html: html, parentGuid: parentGUID)
+        }
+    }
+
+    /**
+     * Import the bookmarks from a Chrome (or other Chromium-based browser) profile.
+     *
+     * The contents of Chrome's bookmarks bar, "other bookmarks" and mobile bookmarks
+     * are appended to the toolbar, unfiled and mobile roots.
+     *
+     * - Parameter path: The path to the profile's `Bookmarks` file.
+     * - Returns: The number of bookmarks found, imported and skipped.
+     * - Throws:
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     *     - `PlacesApiError.unexpected`: If the file can't be read or isn't valid JSON.
+     */
+    open func importBookmarksFromChrome(path: String) throws -> BookmarksMigrationResult {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.placesBookmarksImportFromChrome(path: path)
+        }
+    }
+
+    /**
+     * Tags a URL. Any bookmarks for the URL will be uploaded with the tag.
+     *
+     * - Parameter url: The URL to tag, which must already be known (for example, because it's bookmarked).
+     * - Parameter tag: The tag to add.
+     * - Throws:
+     *     - `PlacesApiError.invalidBookmarkOperation`: If `tag` is empty or too long.
+     *     - `PlacesApiError.urlParseFailed`: If `url` is invalid.
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     */
+    open func tagUrl(url: String, tag: String) throws {
+        return try queue.sync {
+            try self.checkApi()
+            try self.conn.tagUrl(url: url, tag: tag)
+        }
+    }
+
+    /**
+     * Removes a tag from a URL. Does nothing if the URL doesn't have the tag.
+     *
+     * - Parameter url: The URL to untag.
+     * - Parameter tag: The tag to remove.
+     * - Throws:
+     *     - `PlacesApiError.invalidBookmarkOperation`: If `tag` is empty or too long.
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     */
+    open func untagUrl(url: String, tag: String) throws {
+        return try queue.sync {
+            try self.checkApi()
+            try self.conn.untagUrl(url: url, tag: tag)
+        }
+    }
+
+    /**
+     * Removes a tag from every URL which has it.
+     *
+     * - Parameter tag: The tag to remove.
+     * - Throws:
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     */
+    open func removeTag(tag: String) throws {
+        return try queue.sync {
+            try self.checkApi()
+            try self.conn.removeTag(tag: tag)
+        }
+    }
+
+    /**
+     * Renames a tag on every URL which has it, merging it into `newTag` if that tag
+     * already exists. Bookmarks for those URLs will be uploaded with the new tag.
+     *
+     * - Parameter oldTag: The tag to rename.
+     * - Parameter newTag: The new name for the tag.
+     * - Throws:
+     *     - `PlacesApiError.invalidBookmarkOperation`: If either tag is empty or too long.
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     */
+    open func renameTag(oldTag: String, newTag: String) throws {
+        return try queue.sync {
+            try self.checkApi()
+            try self.conn.renameTag(oldTag: oldTag, newTag: newTag)
+        }
+    }
+
+    /**
+     * Sets the search keyword for a URL. Keywords are case-insensitive.
+     *
+     * A URL can only have one keyword, and a keyword can only belong to one URL, so this
+     * replaces any existing keyword for `url`, and moves `keyword` if another URL has it.
+     * Bookmarks for both URLs will be uploaded.
+     *
+     * - Parameter url: The URL, which must already be known (for example, because it's bookmarked).
+     * - Parameter keyword: The keyword.
+     * - Parameter postData: Must be nil - POST data for keywords isn't supported.
+     * - Throws:
+     *     - `PlacesApiError.invalidBookmarkOperation`: If `keyword` is empty, or `postData` isn't nil.
+     *     - `PlacesApiError.urlParseFailed`: If `url` is invalid.
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     */
+    open func setBookmarkKeyword(url: String, keyword: String, postData: String? = nil) throws {
+        return try queue.sync {
+            try self.checkApi()
+            try self.conn.bookmarksSetKeyword(url: url, keyword: keyword, postData: postData)
+        }
+    }
+
+    /**
+     * Removes a search keyword.
+     *
+     * - Parameter keyword: The keyword to remove.
+     * - Returns: Whether the keyword existed.
+     * - Throws:
+     *     - `PlacesApiError.invalidBookmarkOperation`: If `keyword` is empty.
+     *     - `PlacesConnectionError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                                      object has been closed. This indicates API
+     *                                                      misuse.
+     */
+    @discardableResult
+    open func removeBookmarkKeyword(keyword: String) throws -> Bool {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.bookmarksRemoveKeyword(keyword: keyword)
+        }
+    }
+
     // Helper for the various creation functions.
     // Note: Caller synchronizes
     private func doInsert(item: InsertableBookmarkItem) throws -> Guid {
@@ -918,4 +1206,18 @@ public class PlacesWriteConnection: PlacesReadConnection {
             return try self.conn.placesHistoryImportFromIos(dbPath: path, lastSyncTimestamp: lastSyncTimestamp)
         }
     }
+
+    /**
+     * Import the history from a Chrome (or other Chromium-based browser) profile.
+     * Visits which already exist are skipped, so this can be re-run after being interrupted.
+     *
+     * - Parameter path: The path to the profile's `History` database.
+     * - Returns: The number of visits found, imported and skipped.
+     */
+    open func importHistoryFromChrome(path: String) throws -> HistoryMigrationResult {
+        return try queue.sync {
+            try self.checkApi()
+            return try self.conn.placesHistoryImportFromChrome(dbPath: path)
+        }
+    }
 }
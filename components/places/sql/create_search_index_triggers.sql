@@ -0,0 +1,71 @@
+-- This Source Code Form is subject to the terms of the Mozilla Public
+-- License, v. 2.0. If a copy of the MPL was not distributed with this
+-- file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+-- This file defines triggers for the read-write and Sync connections, so that
+-- both local and synced changes reach the search index.
+
+-- These triggers keep the optional full-text search index in `moz_places_fts`
+-- up to date. They do nothing until the index has been built, which sets the
+-- `search_index_enabled` key in `moz_meta`.
+CREATE TEMP TRIGGER moz_places_fts_afterinsert_trigger
+AFTER INSERT ON moz_places
+WHEN EXISTS(SELECT 1 FROM moz_meta WHERE key = 'search_index_enabled')
+BEGIN
+    INSERT INTO moz_places_fts(rowid, title, url, bookmark_titles)
+    VALUES(NEW.id, IFNULL(NEW.title, ''), NEW.url, '');
+END;
+
+CREATE TEMP TRIGGER moz_places_fts_afterupdate_trigger
+AFTER UPDATE OF title, url ON moz_places
+WHEN EXISTS(SELECT 1 FROM moz_meta WHERE key = 'search_index_enabled')
+BEGIN
+    DELETE FROM moz_places_fts WHERE rowid = OLD.id;
+    INSERT INTO moz_places_fts(rowid, title, url, bookmark_titles)
+    VALUES(NEW.id, IFNULL(NEW.title, ''), NEW.url,
+           IFNULL((SELECT group_concat(b.title, ' ') FROM moz_bookmarks b
+                   WHERE b.fk = NEW.id), ''));
+END;
+
+CREATE TEMP TRIGGER moz_places_fts_afterdelete_trigger
+AFTER DELETE ON moz_places
+WHEN EXISTS(SELECT 1 FROM moz_meta WHERE key = 'search_index_enabled')
+BEGIN
+    DELETE FROM moz_places_fts WHERE rowid = OLD.id;
+END;
+
+CREATE TEMP TRIGGER moz_bookmarks_fts_afterinsert_trigger
+AFTER INSERT ON moz_bookmarks
+WHEN NEW.fk NOT NULL
+     AND EXISTS(SELECT 1 FROM moz_meta WHERE key = 'search_index_enabled')
+BEGIN
+    UPDATE moz_places_fts SET
+        bookmark_titles = IFNULL((SELECT group_concat(b.title, ' ')
+                                  FROM moz_bookmarks b
+                                  WHERE b.fk = NEW.fk), '')
+    WHERE rowid = NEW.fk;
+END;
+
+CREATE TEMP TRIGGER moz_bookmarks_fts_afterupdate_trigger
+AFTER UPDATE OF title, fk ON moz_bookmarks
+WHEN (OLD.fk NOT NULL OR NEW.fk NOT NULL)
+     AND EXISTS(SELECT 1 FROM moz_meta WHERE key = 'search_index_enabled')
+BEGIN
+    UPDATE moz_places_fts SET
+        bookmark_titles = IFNULL((SELECT group_concat(b.title, ' ')
+                                  FROM moz_bookmarks b
+                                  WHERE b.fk = moz_places_fts.rowid), '')
+    WHERE rowid IN (OLD.fk, NEW.fk);
+END;
+
+CREATE TEMP TRIGGER moz_bookmarks_fts_afterdelete_trigger
+AFTER DELETE ON moz_bookmarks
+WHEN OLD.fk NOT NULL
+     AND EXISTS(SELECT 1 FROM moz_meta WHERE key = 'search_index_enabled')
+BEGIN
+    UPDATE moz_places_fts SET
+        bookmark_titles = IFNULL((SELECT group_concat(b.title, ' ')
+                                  FROM moz_bookmarks b
+                                  WHERE b.fk = OLD.fk), '')
+    WHERE rowid = OLD.fk;
+END;
@@ -242,6 +242,19 @@ CREATE TABLE IF NOT EXISTS moz_keywords(
     keyword TEXT NOT NULL UNIQUE
 );
 
+-- This is an optional full-text index over the titles and URLs of places,
+-- and the titles of their bookmarks, keyed by `moz_places.id`. It's empty,
+-- and not maintained, until it's built by
+-- `run_maintenance_rebuild_search_index()`; after that, the triggers in
+-- `create_main_triggers.sql` keep it up to date.
+CREATE VIRTUAL TABLE IF NOT EXISTS moz_places_fts USING fts5(
+    title,
+    url,
+    bookmark_titles,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
 ----------------------------------------------------------------------
 --------------------History Metadata----------------------------------
 ----------------------------------------------------------------------
@@ -11,7 +11,7 @@ use crate::types::SyncStatus;
 use rusqlite::Connection;
 use sql_support::ConnExt;
 
-pub const VERSION: u32 = 17;
+pub const VERSION: u32 = 18;
 
 // Shared schema and temp tables for the read-write and Sync connections.
 const CREATE_SHARED_SCHEMA_SQL: &str = include_str!("../../sql/create_shared_schema.sql");
@@ -24,6 +24,11 @@ const CREATE_SYNC_TRIGGERS_SQL: &str = include_str!("../../sql/create_sync_trigg
 // Triggers for the main read-write connection only.
 const CREATE_MAIN_TRIGGERS_SQL: &str = include_str!("../../sql/create_main_triggers.sql");
 
+// Triggers which maintain the search index, for the read-write and Sync
+// connections.
+const CREATE_SEARCH_INDEX_TRIGGERS_SQL: &str =
+    include_str!("../../sql/create_search_index_triggers.sql");
+
 lazy_static::lazy_static! {
     // Triggers for the read-write and Sync connections.
     static ref CREATE_SHARED_TRIGGERS_SQL: String = {
@@ -40,6 +45,9 @@ pub(crate) static MOZ_META_KEY_ORIGIN_FRECENCY_COUNT: &str = "origin_frecency_co
 pub(crate) static MOZ_META_KEY_ORIGIN_FRECENCY_SUM: &str = "origin_frecency_sum";
 pub(crate) static MOZ_META_KEY_ORIGIN_FRECENCY_SUM_OF_SQUARES: &str =
     "origin_frecency_sum_of_squares";
+// Set once the full-text search index has been built. Note that the triggers
+// in `create_search_index_triggers.sql` check for this key by name.
+pub(crate) static MOZ_META_KEY_SEARCH_INDEX_ENABLED: &str = "search_index_enabled";
 
 fn update_origin_frecency_stats(op: &str) -> String {
     format!(
@@ -89,6 +97,7 @@ pub fn finish(db: &Connection, conn_type: ConnectionType) -> rusqlite::Result<()
             db.execute_batch(CREATE_SHARED_TEMP_TABLES_SQL)?;
             db.execute_batch(&CREATE_SHARED_TRIGGERS_SQL)?;
             db.execute_batch(CREATE_MAIN_TRIGGERS_SQL)?;
+            db.execute_batch(CREATE_SEARCH_INDEX_TRIGGERS_SQL)?;
         }
 
         // The Sync connection needs shared and its own temp tables and
@@ -99,6 +108,7 @@ pub fn finish(db: &Connection, conn_type: ConnectionType) -> rusqlite::Result<()
             db.execute_batch(&CREATE_SHARED_TRIGGERS_SQL)?;
             db.execute_batch(CREATE_SYNC_TEMP_TABLES_SQL)?;
             db.execute_batch(CREATE_SYNC_TRIGGERS_SQL)?;
+            db.execute_batch(CREATE_SEARCH_INDEX_TRIGGERS_SQL)?;
             create_synced_bookmark_roots(db)?;
         }
     }
@@ -288,6 +298,18 @@ pub fn upgrade_from(db: &Connection, from: u32) -> rusqlite::Result<()> {
                 (),
             )?;
         }
+        17 => {
+            // Add the (initially empty) full-text search index.
+            db.execute_batch(
+                "CREATE VIRTUAL TABLE IF NOT EXISTS moz_places_fts USING fts5(
+                    title,
+                    url,
+                    bookmark_titles,
+                    tokenize = 'unicode61 remove_diacritics 2',
+                    prefix = '2 3'
+                )",
+            )?;
+        }
         // Add more migrations here...
 
         // Any other from value indicates that something very wrong happened
@@ -818,6 +840,7 @@ mod tests {
             "moz_keywords",
             "moz_places_metadata",
             "moz_places_metadata_search_queries",
+            "moz_places_fts",
         ];
         #[derive(Debug, Ord, PartialOrd, Eq, PartialEq)]
         struct ColumnInfo {
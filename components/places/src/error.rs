@@ -147,6 +147,12 @@ pub enum InvalidPlaceInfo {
     // Like Urls, a tag is considered private info, so the value isn't in the error.
     #[error("The tag value is invalid")]
     InvalidTag,
+    // Keywords are also private info.
+    #[error("The keyword value is invalid")]
+    InvalidKeyword,
+    // We don't store or sync POST data for keywords; see `moz_keywords`.
+    #[error("POST data for keywords is not supported")]
+    KeywordPostDataUnsupported,
     #[error("Cannot change the '{0}' property of a bookmark of type {1:?}")]
     IllegalChange(&'static str, BookmarkType),
 
@@ -204,6 +210,11 @@ impl GetErrorHandling for Error {
                     InvalidPlaceInfo::CannotUpdateRoot(..) => {
                         PlacesApiError::InvalidBookmarkOperation { reason: label }
                     }
+                    InvalidPlaceInfo::InvalidTag
+                    | InvalidPlaceInfo::InvalidKeyword
+                    | InvalidPlaceInfo::KeywordPostDataUnsupported => {
+                        PlacesApiError::InvalidBookmarkOperation { reason: label }
+                    }
                     _ => PlacesApiError::UnexpectedPlacesException { reason: label },
                 })
                 .report_error("places-invalid-place-info")
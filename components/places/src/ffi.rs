@@ -8,8 +8,8 @@ use crate::api::matcher::{self, search_frecent, SearchParams};
 pub use crate::api::places_api::places_api_new;
 pub use crate::error::Result;
 pub use crate::error::{ApiResult, PlacesApiError};
-pub use crate::import::common::HistoryMigrationResult;
-use crate::import::import_ios_history;
+pub use crate::import::common::{BookmarksMigrationResult, HistoryMigrationResult};
+use crate::import::{import_chrome_bookmarks, import_chrome_history, import_ios_history};
 use crate::storage;
 use crate::storage::bookmarks;
 pub use crate::storage::bookmarks::BookmarkPosition;
@@ -17,8 +17,9 @@ pub use crate::storage::history_metadata::{
     DocumentType, HistoryHighlight, HistoryHighlightWeights, HistoryMetadata,
     HistoryMetadataObservation,
 };
+pub use crate::storage::tags::TagInfo;
 pub use crate::storage::RunMaintenanceMetrics;
-use crate::storage::{history, history_metadata};
+use crate::storage::{history, history_metadata, tags};
 use crate::types::VisitTransitionSet;
 use crate::ConnectionType;
 use crate::UniffiCustomTypeConverter;
@@ -454,6 +455,19 @@ impl PlacesConnection {
         self.with_conn(storage::run_maintenance_checkpoint)
     }
 
+    #[handle_error(crate::Error)]
+    pub fn run_maintenance_rebuild_search_index(&self) -> ApiResult<()> {
+        self.with_conn(storage::search_index::run_maintenance_rebuild_search_index)
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn query_search_index(&self, search: String, limit: i32) -> ApiResult<Vec<SearchResult>> {
+        self.with_conn(|conn| {
+            storage::search_index::search_index(conn, &search, limit as u32)
+                .map(|search_results| search_results.into_iter().map(Into::into).collect())
+        })
+    }
+
     #[handle_error(crate::Error)]
     pub fn query_autocomplete(&self, search: String, limit: i32) -> ApiResult<Vec<SearchResult>> {
         self.with_conn(|conn| {
@@ -563,6 +577,42 @@ impl PlacesConnection {
         self.with_conn(|conn| bookmarks::bookmarks_get_url_for_keyword(conn, keyword.as_str()))
     }
 
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_set_keyword(
+        &self,
+        url: String,
+        keyword: String,
+        post_data: Option<String>,
+    ) -> ApiResult<()> {
+        self.with_conn(|conn| {
+            bookmarks::bookmarks_set_keyword(
+                conn,
+                &Url::parse(&url)?,
+                &keyword,
+                post_data.as_deref(),
+            )
+        })
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_remove_keyword(&self, keyword: String) -> ApiResult<bool> {
+        self.with_conn(|conn| bookmarks::bookmarks_remove_keyword(conn, &keyword))
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_get_keywords_for_url(&self, url: String) -> ApiResult<Vec<String>> {
+        self.with_conn(|conn| match Url::parse(&url) {
+            Ok(url) => bookmarks::bookmarks_get_keywords_for_url(conn, &url),
+            Err(e) => {
+                log::warn!(
+                    "Invalid URL passed to bookmarks_get_keywords_for_url, {}",
+                    e
+                );
+                Ok(Vec::new())
+            }
+        })
+    }
+
     #[handle_error(crate::Error)]
     pub fn bookmarks_insert(&self, data: InsertableBookmarkItem) -> ApiResult<Guid> {
         self.with_conn(|conn| bookmarks::insert_bookmark(conn, data))
@@ -573,6 +623,64 @@ impl PlacesConnection {
         self.with_conn(|conn| bookmarks::update_bookmark_from_info(conn, item))
     }
 
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_import_from_html(&self, html: String, parent_guid: Guid) -> ApiResult<u32> {
+        self.with_conn(|conn| bookmarks::html::import_html(conn, &html, &parent_guid))
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_export_to_html(&self) -> ApiResult<String> {
+        self.with_conn(bookmarks::html::export_html)
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn tag_url(&self, url: String, tag: String) -> ApiResult<()> {
+        self.with_conn(|conn| tags::tag_url(conn, &Url::parse(&url)?, &tag))
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn untag_url(&self, url: String, tag: String) -> ApiResult<()> {
+        self.with_conn(|conn| match Url::parse(&url) {
+            Ok(url) => tags::untag_url(conn, &url, &tag),
+            Err(e) => {
+                // There's nothing to untag if the URL is invalid.
+                log::warn!("Invalid URL passed to untag_url, {}", e);
+                Ok(())
+            }
+        })
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn get_tags_for_url(&self, url: String) -> ApiResult<Vec<String>> {
+        self.with_conn(|conn| match Url::parse(&url) {
+            Ok(url) => tags::get_tags_for_url(conn, &url),
+            Err(e) => {
+                log::warn!("Invalid URL passed to get_tags_for_url, {}", e);
+                Ok(Vec::new())
+            }
+        })
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn get_urls_with_tag(&self, tag: String) -> ApiResult<Vec<Url>> {
+        self.with_conn(|conn| tags::get_urls_with_tag(conn, &tag))
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn get_all_tags(&self) -> ApiResult<Vec<TagInfo>> {
+        self.with_conn(tags::get_all_tags)
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn remove_tag(&self, tag: String) -> ApiResult<()> {
+        self.with_conn(|conn| tags::remove_tag(conn, &tag))
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn rename_tag(&self, old_tag: String, new_tag: String) -> ApiResult<()> {
+        self.with_conn(|conn| tags::rename_tag(conn, &old_tag, &new_tag))
+    }
+
     #[handle_error(crate::Error)]
     pub fn places_history_import_from_ios(
         &self,
@@ -581,6 +689,22 @@ impl PlacesConnection {
     ) -> ApiResult<HistoryMigrationResult> {
         self.with_conn(|conn| import_ios_history(conn, &db_path, last_sync_timestamp))
     }
+
+    #[handle_error(crate::Error)]
+    pub fn places_history_import_from_chrome(
+        &self,
+        db_path: String,
+    ) -> ApiResult<HistoryMigrationResult> {
+        self.with_conn(|conn| import_chrome_history(conn, &db_path))
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn places_bookmarks_import_from_chrome(
+        &self,
+        path: String,
+    ) -> ApiResult<BookmarksMigrationResult> {
+        self.with_conn(|conn| import_chrome_bookmarks(conn, &path))
+    }
 }
 
 impl AsRef<SqlInterruptHandle> for PlacesConnection {
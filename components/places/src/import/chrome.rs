@@ -0,0 +1,12 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+pub mod bookmarks;
+pub mod history;
+pub use bookmarks::import as import_bookmarks;
+pub use history::import as import_history;
+
+// Chrome stores times as microseconds since 1601-01-01 (the Windows FILETIME
+// epoch), which is this many milliseconds before the unix epoch.
+const CHROME_EPOCH_OFFSET_MS: i64 = 11_644_473_600_000;
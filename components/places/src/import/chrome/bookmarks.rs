@@ -0,0 +1,273 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::time::Instant;
+
+use super::CHROME_EPOCH_OFFSET_MS;
+use crate::error::Result;
+use crate::import::common::BookmarksMigrationResult;
+use crate::storage::bookmarks::{
+    insert_bookmark_in_tx, BookmarkPosition, BookmarkRootGuid, InsertableBookmark, InsertableFolder,
+};
+use crate::PlacesDb;
+use serde_derive::*;
+use sync_guid::Guid as SyncGuid;
+use types::Timestamp;
+use url::Url;
+
+// The `Bookmarks` file is JSON, with a tree of nodes under each of the roots.
+// We only describe the parts we care about.
+#[derive(Debug, Deserialize)]
+struct ChromeBookmarks {
+    roots: ChromeRoots,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChromeRoots {
+    bookmark_bar: Option<ChromeNode>,
+    other: Option<ChromeNode>,
+    synced: Option<ChromeNode>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ChromeNode {
+    Url {
+        #[serde(default)]
+        name: String,
+        url: String,
+        date_added: Option<String>,
+    },
+    Folder {
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        children: Vec<ChromeNode>,
+        date_added: Option<String>,
+        date_modified: Option<String>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+// Chrome writes its timestamps as strings of microseconds since 1601. Missing,
+// zero or otherwise nonsensical times are left for the insert to fill in.
+fn parse_timestamp(value: &Option<String>) -> Option<Timestamp> {
+    let micros = value.as_ref()?.trim().parse::<i64>().ok()?;
+    let millis = micros / 1000 - CHROME_EPOCH_OFFSET_MS;
+    let ts = Timestamp(u64::try_from(millis).ok()?);
+    if Timestamp::EARLIEST <= ts && ts <= Timestamp::now() {
+        Some(ts)
+    } else {
+        None
+    }
+}
+
+fn insert_nodes(
+    db: &PlacesDb,
+    parent_guid: &SyncGuid,
+    nodes: Vec<ChromeNode>,
+    result: &mut BookmarksMigrationResult,
+) -> Result<()> {
+    for node in nodes {
+        match node {
+            ChromeNode::Url {
+                name,
+                url,
+                date_added,
+            } => {
+                result.num_total += 1;
+                let url = match Url::parse(&url) {
+                    Ok(url) if url.as_str().len() <= crate::storage::URL_LENGTH_MAX => url,
+                    _ => {
+                        result.num_failed += 1;
+                        continue;
+                    }
+                };
+                let date_added = parse_timestamp(&date_added);
+                insert_bookmark_in_tx(
+                    db,
+                    InsertableBookmark {
+                        parent_guid: parent_guid.clone(),
+                        position: BookmarkPosition::Append,
+                        date_added,
+                        last_modified: date_added,
+                        guid: None,
+                        url,
+                        title: Some(name).filter(|name| !name.is_empty()),
+                    }
+                    .into(),
+                )?;
+                result.num_succeeded += 1;
+            }
+            ChromeNode::Folder {
+                name,
+                children,
+                date_added,
+                date_modified,
+            } => {
+                let guid = insert_bookmark_in_tx(
+                    db,
+                    InsertableFolder {
+                        parent_guid: parent_guid.clone(),
+                        position: BookmarkPosition::Append,
+                        date_added: parse_timestamp(&date_added),
+                        last_modified: parse_timestamp(&date_modified),
+                        guid: None,
+                        title: Some(name).filter(|name| !name.is_empty()),
+                        children: vec![],
+                    }
+                    .into(),
+                )?;
+                insert_nodes(db, &guid, children, result)?;
+            }
+            ChromeNode::Unknown => {}
+        }
+    }
+    Ok(())
+}
+
+/// Imports the bookmarks from a Chrome (or other Chromium-based browser)
+/// profile, given the path to its `Bookmarks` JSON file.
+///
+/// The contents of Chrome's "Bookmarks bar", "Other bookmarks" and "Mobile
+/// bookmarks" are appended to our toolbar, unfiled and mobile roots. Chrome's
+/// GUIDs aren't valid for us, so every item gets a new one. The counts in the
+/// result are of bookmarks - folders are created as needed but not counted.
+pub fn import(
+    conn: &PlacesDb,
+    path: impl AsRef<std::path::Path>,
+) -> Result<BookmarksMigrationResult> {
+    let import_start = Instant::now();
+    let contents = std::fs::read_to_string(path)?;
+    let bookmarks: ChromeBookmarks = serde_json::from_str(&contents)?;
+    let roots = [
+        (bookmarks.roots.bookmark_bar, BookmarkRootGuid::Toolbar),
+        (bookmarks.roots.other, BookmarkRootGuid::Unfiled),
+        (bookmarks.roots.synced, BookmarkRootGuid::Mobile),
+    ];
+
+    let mut result = BookmarksMigrationResult::default();
+    let tx = conn.begin_transaction()?;
+    let mut insert_result = Ok(());
+    for (node, root) in roots {
+        if let Some(ChromeNode::Folder { children, .. }) = node {
+            insert_result = insert_nodes(conn, root.guid(), children, &mut result);
+            if insert_result.is_err() {
+                break;
+            }
+        }
+    }
+    crate::storage::delete_pending_temp_tables(conn)?;
+    match insert_result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    insert_result?;
+    result.total_duration = import_start.elapsed().as_millis() as u64;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::tests::assert_json_tree;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(
+            parse_timestamp(&Some("13300000000000000".to_string())),
+            Some(Timestamp(1_655_526_400_000))
+        );
+        assert_eq!(parse_timestamp(&Some("0".to_string())), None);
+        assert_eq!(parse_timestamp(&Some("nope".to_string())), None);
+        assert_eq!(parse_timestamp(&None), None);
+    }
+
+    #[test]
+    fn test_import() -> Result<()> {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("Bookmarks");
+        let contents = json!({
+            "checksum": "ignored",
+            "version": 1,
+            "roots": {
+                "bookmark_bar": {
+                    "type": "folder",
+                    "name": "Bookmarks bar",
+                    "children": [
+                        {
+                            "type": "url",
+                            "name": "Example",
+                            "url": "https://example.com/",
+                            "date_added": "13300000000000000",
+                        },
+                        {
+                            "type": "folder",
+                            "name": "Stuff",
+                            "children": [
+                                { "type": "url", "name": "", "url": "https://www.mozilla.org/" },
+                                { "type": "url", "name": "Bad", "url": "not a url" },
+                            ],
+                        },
+                    ],
+                },
+                "other": {
+                    "type": "folder",
+                    "name": "Other bookmarks",
+                    "children": [
+                        { "type": "url", "name": "Other", "url": "https://example.org/" },
+                    ],
+                },
+                "synced": {
+                    "type": "folder",
+                    "name": "Mobile bookmarks",
+                    "children": [],
+                },
+            },
+        });
+        std::fs::write(&path, contents.to_string()).unwrap();
+
+        let conn = new_mem_connection();
+        let result = import(&conn, &path)?;
+        assert_eq!(result.num_total, 4);
+        assert_eq!(result.num_succeeded, 3);
+        assert_eq!(result.num_failed, 1);
+
+        assert_json_tree(
+            &conn,
+            BookmarkRootGuid::Toolbar.guid(),
+            json!({
+                "guid": &BookmarkRootGuid::Toolbar.as_guid(),
+                "children": [
+                    {"title": "Example", "url": "https://example.com/"},
+                    {
+                        "title": "Stuff",
+                        "children": [{"url": "https://www.mozilla.org/"}],
+                    },
+                ],
+            }),
+        );
+        assert_json_tree(
+            &conn,
+            BookmarkRootGuid::Unfiled.guid(),
+            json!({
+                "guid": &BookmarkRootGuid::Unfiled.as_guid(),
+                "children": [{"title": "Other", "url": "https://example.org/"}],
+            }),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_bad_json() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("Bookmarks");
+        std::fs::write(&path, "{\"roots\":").unwrap();
+        let conn = new_mem_connection();
+        assert!(import(&conn, &path).is_err());
+    }
+}
@@ -0,0 +1,356 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::time::Instant;
+
+use super::CHROME_EPOCH_OFFSET_MS;
+use crate::error::Result;
+use crate::import::common::{
+    attached_database, define_history_migration_functions, select_count, ExecuteOnDrop,
+    HistoryMigrationResult,
+};
+use crate::storage::update_all_frecencies_at_once;
+use crate::types::VisitTransition;
+use crate::PlacesDb;
+use rusqlite::Connection;
+use types::Timestamp;
+use url::Url;
+
+// How many Chrome visit ids we move per transaction. Each batch is its own
+// transaction so that a large profile doesn't hold the write lock (and block
+// syncs and new visits) for the entire import.
+const VISIT_BATCH_SIZE: i64 = 5000;
+
+// Chrome's `PageTransition` values - the low byte is the "core" type, and the
+// high bits are qualifiers.
+// See https://source.chromium.org/chromium/chromium/src/+/main:ui/base/page_transition_types.h
+const CHROME_CORE_MASK: i64 = 0xFF;
+const CHROME_LINK: i64 = 0;
+const CHROME_TYPED: i64 = 1;
+const CHROME_AUTO_BOOKMARK: i64 = 2;
+const CHROME_AUTO_SUBFRAME: i64 = 3;
+const CHROME_MANUAL_SUBFRAME: i64 = 4;
+const CHROME_GENERATED: i64 = 5;
+const CHROME_RELOAD: i64 = 8;
+const CHROME_KEYWORD: i64 = 9;
+const CHROME_KEYWORD_GENERATED: i64 = 10;
+const CHROME_SERVER_REDIRECT: i64 = 0x8000_0000;
+
+/// Maps a Chrome transition onto the closest `VisitTransition`.
+fn visit_transition(transition: i64) -> VisitTransition {
+    // Chrome marks the target of a server redirect with a qualifier, but
+    // doesn't record whether it was permanent, so we assume it was temporary.
+    // Client redirects (meta refresh etc) are treated as links, like desktop.
+    if transition & CHROME_SERVER_REDIRECT != 0 {
+        return VisitTransition::RedirectTemporary;
+    }
+    match transition & CHROME_CORE_MASK {
+        CHROME_TYPED | CHROME_GENERATED | CHROME_KEYWORD | CHROME_KEYWORD_GENERATED => {
+            VisitTransition::Typed
+        }
+        CHROME_AUTO_BOOKMARK => VisitTransition::Bookmark,
+        CHROME_AUTO_SUBFRAME => VisitTransition::Embed,
+        CHROME_MANUAL_SUBFRAME => VisitTransition::FramedLink,
+        CHROME_RELOAD => VisitTransition::Reload,
+        // CHROME_LINK, AUTO_TOPLEVEL, FORM_SUBMIT and anything we don't know.
+        _ => VisitTransition::Link,
+    }
+}
+
+fn define_chrome_functions(c: &Connection) -> Result<()> {
+    use rusqlite::functions::FunctionFlags;
+    c.create_scalar_function(
+        "chrome_visit_type",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| Ok(visit_transition(ctx.get::<i64>(0).unwrap_or(CHROME_LINK)) as u8),
+    )?;
+    Ok(())
+}
+
+/// This import is used to bring over the history from a Chrome (or other
+/// Chromium-based browser) profile, given the path to its `History` database.
+///
+/// ### Basic process
+///
+/// - Attach the Chrome database, read-only.
+/// - Slurp the URLs into a temp table "chromeHistoryStaging", to punycode them.
+/// - Add any entries to moz_places that are needed, in a single transaction.
+/// - Copy the visits over in batches of `VISIT_BATCH_SIZE`, each in its own
+///   transaction, skipping visits we already have (so the import can be
+///   safely re-run after being interrupted).
+/// - Update frecency for the affected places.
+/// - Cleanup (drop the staging table, detach the Chrome database).
+pub fn import(
+    conn: &PlacesDb,
+    path: impl AsRef<std::path::Path>,
+) -> Result<HistoryMigrationResult> {
+    let mut url = crate::util::ensure_url_path(path)?;
+    url.query_pairs_mut().append_pair("mode", "ro");
+    do_import(conn, url)
+}
+
+fn do_import(conn: &PlacesDb, chrome_db_file_url: Url) -> Result<HistoryMigrationResult> {
+    let scope = conn.begin_interrupt_scope()?;
+    define_history_migration_functions(conn)?;
+    define_chrome_functions(conn)?;
+    let import_start = Instant::now();
+    log::info!("Attaching database {}", chrome_db_file_url);
+    let auto_detach = attached_database(conn, &chrome_db_file_url, "chrome")?;
+    let num_total = select_count(conn, &COUNT_CHROME_HISTORY_VISITS)?;
+    log::info!("The number of visits is: {:?}", num_total);
+    let num_existing = select_count(conn, &COUNT_PLACES_HISTORY_VISITS)?;
+
+    let drop_staging = ExecuteOnDrop::new(conn, DROP_STAGING_TABLE.to_string());
+    let tx = conn.begin_transaction()?;
+    log::info!("Creating and populating staging table");
+    tx.execute_batch(&CREATE_STAGING_TABLE)?;
+    tx.execute_batch(&FILL_STAGING)?;
+    scope.err_if_interrupted()?;
+
+    log::info!("Updating old titles that may be missing, but now are available");
+    tx.execute_batch(&UPDATE_PLACES_TITLES)?;
+    scope.err_if_interrupted()?;
+
+    log::info!("Populating missing entries in moz_places");
+    tx.execute_batch(&FILL_MOZ_PLACES)?;
+    scope.err_if_interrupted()?;
+    tx.commit()?;
+
+    log::info!("Inserting the history visits");
+    let max_visit_id: i64 =
+        conn.query_row("SELECT IFNULL(MAX(id), 0) FROM chrome.visits", [], |row| {
+            row.get(0)
+        })?;
+    let mut start = 0;
+    while start < max_visit_id {
+        scope.err_if_interrupted()?;
+        let end = start + VISIT_BATCH_SIZE;
+        let tx = conn.begin_transaction()?;
+        tx.execute(
+            &INSERT_HISTORY_VISITS,
+            &[(":start", &start), (":end", &end)],
+        )?;
+        tx.commit()?;
+        start = end;
+    }
+
+    log::info!("Insert all affected entries into stale frecencies");
+    let now = Timestamp::now().as_millis();
+    conn.execute(&ADD_TO_STALE_FRECENCIES, &[(":now", &now)])?;
+    drop_staging.execute_now()?;
+    log::info!("Successfully imported history visits!");
+
+    let num_succeeded = select_count(conn, &COUNT_PLACES_HISTORY_VISITS)?
+        .saturating_sub(num_existing)
+        .min(num_total);
+    let num_failed = num_total.saturating_sub(num_succeeded);
+
+    // As for iOS, frecencies are updated separately, so that readers can see
+    // the imported history without waiting for them.
+    log::info!("Updating all frecencies");
+    update_all_frecencies_at_once(conn, &scope)?;
+    log::info!("Frecencies updated!");
+    auto_detach.execute_now()?;
+
+    Ok(HistoryMigrationResult {
+        num_total,
+        num_succeeded,
+        num_failed,
+        total_duration: import_start.elapsed().as_millis() as u64,
+    })
+}
+
+lazy_static::lazy_static! {
+    static ref COUNT_CHROME_HISTORY_VISITS: &'static str =
+        "SELECT COUNT(*) FROM chrome.visits"
+    ;
+
+    // As for iOS, we use a staging table so that we can normalize URLs.
+    static ref CREATE_STAGING_TABLE: &'static str = "
+        CREATE TEMP TABLE IF NOT EXISTS temp.chromeHistoryStaging(
+            id INTEGER PRIMARY KEY,
+            url TEXT,
+            url_hash INTEGER NOT NULL,
+            title TEXT
+        ) WITHOUT ROWID;"
+    ;
+
+    static ref DROP_STAGING_TABLE: &'static str =
+        "DROP TABLE IF EXISTS temp.chromeHistoryStaging;"
+    ;
+
+    static ref FILL_STAGING: &'static str = "
+        INSERT OR IGNORE INTO temp.chromeHistoryStaging(id, url, url_hash, title)
+            SELECT
+                u.id,
+                validate_url(u.url),
+                hash(validate_url(u.url)),
+                sanitize_utf8(NULLIF(u.title, ''))
+            FROM chrome.urls u
+            WHERE validate_url(u.url) IS NOT NULL"
+    ;
+
+    // Chrome uses an empty title rather than NULL, so only fill in titles we
+    // don't already have.
+    static ref UPDATE_PLACES_TITLES: &'static str =
+        "UPDATE main.moz_places
+            SET title = IFNULL(title, (SELECT t.title
+                                       FROM temp.chromeHistoryStaging t
+                                       WHERE t.url_hash = main.moz_places.url_hash AND t.url = main.moz_places.url))"
+    ;
+
+    static ref FILL_MOZ_PLACES: &'static str =
+        "INSERT OR IGNORE INTO main.moz_places(guid, url, url_hash, title, frecency, sync_change_counter)
+            SELECT
+                IFNULL(
+                    (SELECT p.guid FROM main.moz_places p WHERE p.url_hash = t.url_hash AND p.url = t.url),
+                    generate_guid()
+                ),
+                t.url,
+                t.url_hash,
+                t.title,
+                -1,
+                1
+            FROM temp.chromeHistoryStaging t"
+    ;
+
+    static ref INSERT_HISTORY_VISITS: String = format!(
+        "INSERT INTO main.moz_historyvisits(from_visit, place_id, visit_date, visit_type, is_local)
+            SELECT
+                NULL, -- Chrome's from_visit ids don't survive the import.
+                p.id,
+                d.visit_date,
+                chrome_visit_type(d.transition),
+                1
+            FROM (
+                SELECT
+                    v.url AS url_id,
+                    sanitize_timestamp(v.visit_time / 1000 - {chrome_epoch_offset_ms}) AS visit_date,
+                    v.transition
+                FROM chrome.visits v
+                WHERE v.id > :start AND v.id <= :end
+            ) d
+            JOIN temp.chromeHistoryStaging t ON t.id = d.url_id
+            JOIN main.moz_places p ON p.url_hash = t.url_hash AND p.url = t.url
+            WHERE NOT EXISTS(SELECT 1 FROM main.moz_historyvisits e
+                             WHERE e.place_id = p.id AND e.visit_date = d.visit_date)",
+        chrome_epoch_offset_ms = CHROME_EPOCH_OFFSET_MS,
+    );
+
+    static ref COUNT_PLACES_HISTORY_VISITS: &'static str =
+        "SELECT COUNT(*) FROM main.moz_historyvisits"
+    ;
+
+    // Unlike iOS, we may be importing into a profile which already has
+    // history, so every place we added visits to needs its frecency updated.
+    static ref ADD_TO_STALE_FRECENCIES: &'static str =
+        "INSERT OR IGNORE INTO main.moz_places_stale_frecencies(place_id, stale_at)
+            SELECT
+                p.id,
+                :now
+            FROM temp.chromeHistoryStaging t
+            JOIN main.moz_places p ON p.url_hash = t.url_hash AND p.url = t.url"
+    ;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::history::get_visit_count;
+    use crate::types::VisitTransitionSet;
+
+    #[test]
+    fn test_visit_transition() {
+        assert_eq!(visit_transition(0x3000_0000), VisitTransition::Link);
+        assert_eq!(visit_transition(0x0200_0001), VisitTransition::Typed);
+        assert_eq!(visit_transition(CHROME_KEYWORD), VisitTransition::Typed);
+        assert_eq!(
+            visit_transition(CHROME_AUTO_BOOKMARK),
+            VisitTransition::Bookmark
+        );
+        assert_eq!(
+            visit_transition(CHROME_AUTO_SUBFRAME),
+            VisitTransition::Embed
+        );
+        assert_eq!(
+            visit_transition(CHROME_MANUAL_SUBFRAME),
+            VisitTransition::FramedLink
+        );
+        assert_eq!(visit_transition(CHROME_RELOAD), VisitTransition::Reload);
+        assert_eq!(
+            visit_transition(0x8000_0000 | 0x2000_0000),
+            VisitTransition::RedirectTemporary
+        );
+        assert_eq!(visit_transition(7), VisitTransition::Link);
+    }
+
+    fn create_chrome_db(path: &std::path::Path) {
+        let chrome = Connection::open(path).expect("should open chrome db");
+        chrome
+            .execute_batch(
+                "CREATE TABLE urls(id INTEGER PRIMARY KEY, url LONGVARCHAR, title LONGVARCHAR,
+                                   visit_count INTEGER DEFAULT 0 NOT NULL,
+                                   last_visit_time INTEGER NOT NULL);
+                 CREATE TABLE visits(id INTEGER PRIMARY KEY, url INTEGER NOT NULL,
+                                     visit_time INTEGER NOT NULL, from_visit INTEGER,
+                                     transition INTEGER DEFAULT 0 NOT NULL);
+                 INSERT INTO urls(id, url, title, visit_count, last_visit_time) VALUES
+                    (1, 'https://example.com/', 'Example', 2, 13300000000000000),
+                    (2, 'https://www.mozilla.org/', '', 1, 13300000001000000),
+                    (3, 'not a url', 'Bad', 1, 13300000002000000);
+                 INSERT INTO visits(id, url, visit_time, transition) VALUES
+                    (1, 1, 13300000000000000, 805306369),
+                    (2, 1, 13300000000500000, 8),
+                    (3, 2, 13300000001000000, 2147483648),
+                    (4, 3, 13300000002000000, 0);",
+            )
+            .expect("should populate chrome db");
+    }
+
+    #[test]
+    fn test_import() -> Result<()> {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("History");
+        create_chrome_db(&path);
+
+        let conn = new_mem_connection();
+        let result = import(&conn, &path)?;
+        assert_eq!(result.num_total, 4);
+        assert_eq!(result.num_succeeded, 3);
+        assert_eq!(result.num_failed, 1);
+
+        let url = Url::parse("https://example.com/").unwrap();
+        let page =
+            crate::storage::fetch_page_info(&conn, &url)?.expect("should have imported the page");
+        assert_eq!(page.page.title, "Example");
+        // 13300000000000000μs since 1601 is 1655526400000ms since 1970.
+        let visits = crate::storage::history::get_visit_infos(
+            &conn,
+            Timestamp(1_655_526_400_000),
+            Timestamp(1_655_526_401_000),
+            VisitTransitionSet::empty(),
+        )?;
+        assert_eq!(visits.len(), 3);
+        let transitions = visits
+            .iter()
+            .map(|v| (v.url.as_str().to_string(), v.visit_type))
+            .collect::<Vec<_>>();
+        assert!(transitions.contains(&("https://example.com/".to_string(), VisitTransition::Typed)));
+        assert!(
+            transitions.contains(&("https://example.com/".to_string(), VisitTransition::Reload))
+        );
+        assert!(transitions.contains(&(
+            "https://www.mozilla.org/".to_string(),
+            VisitTransition::RedirectTemporary
+        )));
+
+        // Importing again should be a no-op.
+        let result = import(&conn, &path)?;
+        assert_eq!(result.num_succeeded, 0);
+        assert_eq!(get_visit_count(&conn, VisitTransitionSet::empty())?, 3);
+        Ok(())
+    }
+}
@@ -25,6 +25,7 @@ pub use root_guid::{BookmarkRootGuid, USER_CONTENT_ROOTS};
 
 mod conversions;
 pub mod fetch;
+pub mod html;
 pub mod json_tree;
 mod root_guid;
 
@@ -284,7 +285,7 @@ pub fn maybe_truncate_title<'a>(t: &Option<&'a str>) -> Option<&'a str> {
     t.map(|title| slice_up_to(title, TITLE_LENGTH_MAX))
 }
 
-fn insert_bookmark_in_tx(db: &PlacesDb, bm: InsertableItem) -> Result<SyncGuid> {
+pub(crate) fn insert_bookmark_in_tx(db: &PlacesDb, bm: InsertableItem) -> Result<SyncGuid> {
     // find the row ID of the parent.
     if bm.parent_guid() == BookmarkRootGuid::Root {
         return Err(InvalidPlaceInfo::CannotUpdateRoot(BookmarkRootGuid::Root).into());
@@ -786,6 +787,114 @@ pub fn bookmarks_get_url_for_keyword(db: &PlacesDb, keyword: &str) -> Result<Opt
     }
 }
 
+// Keywords are case-insensitive, and stored in lowercase, like desktop.
+fn normalize_keyword(keyword: &str) -> Result<String> {
+    let keyword = keyword.trim();
+    if keyword.is_empty() {
+        return Err(InvalidPlaceInfo::InvalidKeyword.into());
+    }
+    Ok(keyword.to_lowercase())
+}
+
+/// Sets the keyword for a URL, which must already be known (for example,
+/// because it's bookmarked).
+///
+/// A URL can only have one keyword, and a keyword can only be used for one
+/// URL, so this replaces the URL's existing keyword, and moves the keyword if
+/// it's used for another URL - which is what desktop does. The bookmarks for
+/// both URLs are marked for upload, so the change is synced.
+///
+/// We don't support POST data for keywords, because we don't sync it, so
+/// `post_data` must be `None`.
+pub fn bookmarks_set_keyword(
+    db: &PlacesDb,
+    url: &Url,
+    keyword: &str,
+    post_data: Option<&str>,
+) -> Result<()> {
+    if post_data.is_some() {
+        return Err(InvalidPlaceInfo::KeywordPostDataUnsupported.into());
+    }
+    let keyword = normalize_keyword(keyword)?;
+    let place_id = match fetch_page_info(db, url)? {
+        Some(info) => info.page.row_id,
+        None => return Err(InvalidPlaceInfo::NoSuchUrl.into()),
+    };
+    let tx = db.begin_transaction()?;
+    let existing: Option<String> = db.try_query_row(
+        "SELECT keyword FROM moz_keywords WHERE place_id = :place_id",
+        &[(":place_id", &place_id)],
+        |row| row.get(0),
+        true,
+    )?;
+    if existing.as_deref() == Some(keyword.as_str()) {
+        tx.commit()?;
+        return Ok(());
+    }
+    // Bump the bookmarks for this URL, and for the URL which currently has
+    // the keyword, before we remove it.
+    db.execute_cached(
+        "UPDATE moz_bookmarks SET
+             syncChangeCounter = syncChangeCounter + 1
+         WHERE fk = :place_id
+            OR fk = (SELECT place_id FROM moz_keywords WHERE keyword = :keyword)",
+        &[
+            (":place_id", &place_id as &dyn rusqlite::ToSql),
+            (":keyword", &keyword),
+        ],
+    )?;
+    db.execute_cached(
+        "DELETE FROM moz_keywords
+         WHERE place_id = :place_id OR keyword = :keyword",
+        &[
+            (":place_id", &place_id as &dyn rusqlite::ToSql),
+            (":keyword", &keyword),
+        ],
+    )?;
+    db.execute_cached(
+        "INSERT INTO moz_keywords(place_id, keyword)
+         VALUES(:place_id, :keyword)",
+        &[
+            (":place_id", &place_id as &dyn rusqlite::ToSql),
+            (":keyword", &keyword),
+        ],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Removes a keyword, marking the bookmarks for its URL for upload. Returns
+/// `false` if the keyword didn't exist.
+pub fn bookmarks_remove_keyword(db: &PlacesDb, keyword: &str) -> Result<bool> {
+    let keyword = normalize_keyword(keyword)?;
+    let tx = db.begin_transaction()?;
+    db.execute_cached(
+        "UPDATE moz_bookmarks SET
+             syncChangeCounter = syncChangeCounter + 1
+         WHERE fk = (SELECT place_id FROM moz_keywords WHERE keyword = :keyword)",
+        &[(":keyword", &keyword)],
+    )?;
+    let removed = db.execute_cached(
+        "DELETE FROM moz_keywords
+         WHERE keyword = :keyword",
+        &[(":keyword", &keyword)],
+    )?;
+    tx.commit()?;
+    Ok(removed > 0)
+}
+
+/// Get the keywords for a URL. Since a URL can only have one keyword, this
+/// returns at most one.
+pub fn bookmarks_get_keywords_for_url(db: &PlacesDb, url: &Url) -> Result<Vec<String>> {
+    db.query_rows_and_then_cached(
+        "SELECT k.keyword FROM moz_keywords k
+         JOIN moz_places h ON h.id = k.place_id
+         WHERE h.url_hash = hash(:url) AND h.url = :url",
+        &[(":url", &url.as_str())],
+        |row| -> Result<_> { Ok(row.get::<_, String>(0)?) },
+    )
+}
+
 /// Erases all bookmarks and resets all Sync metadata.
 pub fn delete_everything(db: &PlacesDb) -> Result<()> {
     let tx = db.begin_transaction()?;
@@ -1066,6 +1175,83 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_set_keyword() -> Result<()> {
+        let conn = new_mem_connection();
+        let url1 = Url::parse("https://example.com/")?;
+        let url2 = Url::parse("https://example.org/")?;
+        let mut guids = vec![];
+        for url in [&url1, &url2] {
+            guids.push(insert_bookmark(
+                &conn,
+                InsertableBookmark {
+                    parent_guid: BookmarkRootGuid::Unfiled.into(),
+                    position: BookmarkPosition::Append,
+                    date_added: None,
+                    last_modified: None,
+                    guid: None,
+                    url: url.clone(),
+                    title: None,
+                }
+                .into(),
+            )?);
+        }
+        let get_counters = || -> Vec<u32> {
+            guids
+                .iter()
+                .map(|guid| {
+                    conn.query_row(
+                        "SELECT syncChangeCounter FROM moz_bookmarks WHERE guid = :guid",
+                        &[(":guid", guid)],
+                        |row| row.get(0),
+                    )
+                    .expect("should get the change counter")
+                })
+                .collect()
+        };
+        conn.execute("UPDATE moz_bookmarks SET syncChangeCounter = 0", [])?;
+
+        bookmarks_set_keyword(&conn, &url1, " Donut ", None)?;
+        assert_eq!(bookmarks_get_keywords_for_url(&conn, &url1)?, vec!["donut"]);
+        assert_eq!(
+            bookmarks_get_url_for_keyword(&conn, "donut")?,
+            Some(url1.clone())
+        );
+        assert_eq!(get_counters(), vec![1, 0]);
+
+        // Setting the same keyword again is a no-op.
+        bookmarks_set_keyword(&conn, &url1, "donut", None)?;
+        assert_eq!(get_counters(), vec![1, 0]);
+
+        // Replacing the keyword for a URL.
+        bookmarks_set_keyword(&conn, &url1, "cake", None)?;
+        assert_eq!(bookmarks_get_keywords_for_url(&conn, &url1)?, vec!["cake"]);
+        assert_eq!(bookmarks_get_url_for_keyword(&conn, "donut")?, None);
+        assert_eq!(get_counters(), vec![2, 0]);
+
+        // Using the keyword for another URL moves it.
+        bookmarks_set_keyword(&conn, &url2, "cake", None)?;
+        assert!(bookmarks_get_keywords_for_url(&conn, &url1)?.is_empty());
+        assert_eq!(bookmarks_get_keywords_for_url(&conn, &url2)?, vec!["cake"]);
+        assert_eq!(get_counters(), vec![3, 1]);
+
+        assert!(bookmarks_remove_keyword(&conn, "CAKE")?);
+        assert!(!bookmarks_remove_keyword(&conn, "cake")?);
+        assert!(bookmarks_get_keywords_for_url(&conn, &url2)?.is_empty());
+        assert_eq!(get_counters(), vec![3, 2]);
+
+        assert!(bookmarks_set_keyword(&conn, &url1, " ", None).is_err());
+        assert!(bookmarks_set_keyword(&conn, &url1, "pie", Some("q=%s")).is_err());
+        assert!(bookmarks_set_keyword(
+            &conn,
+            &Url::parse("https://unknown.example.com/")?,
+            "pie",
+            None
+        )
+        .is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_bookmark_invalid_url_for_keyword() -> Result<()> {
         let conn = new_mem_connection();
@@ -0,0 +1,784 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// This supports importing and exporting bookmarks in the "Netscape bookmark
+// file" format - the `bookmarks.html` files which every browser can export
+// and import.
+//
+// The format is "HTML", but only in the loosest sense - it's a list of
+// `<DT>` items inside nested `<DL>` lists, with most closing tags omitted, so
+// rather than using an HTML parser we tokenize it ourselves and only look at
+// the handful of tags which matter.
+
+use super::json_tree::{fetch_tree, BookmarkTreeNode, FetchDepth};
+use super::{
+    insert_bookmark_in_tx, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+    InsertableFolder, InsertableSeparator,
+};
+use crate::db::PlacesDb;
+use crate::error::Result;
+use crate::storage::tags::{tag_url_in_tx, validate_tag};
+use rusqlite::Row;
+use sql_support::ConnExt;
+use std::collections::HashMap;
+use std::fmt::Write;
+use sync_guid::Guid as SyncGuid;
+use types::Timestamp;
+use url::Url;
+
+// The titles desktop uses for the roots, which we use when exporting the roots
+// as folders.
+const TOOLBAR_TITLE: &str = "Bookmarks Toolbar";
+const UNFILED_TITLE: &str = "Other Bookmarks";
+const MOBILE_TITLE: &str = "Mobile Bookmarks";
+
+#[derive(Debug, Default)]
+struct HtmlBookmark {
+    url: Option<Url>,
+    title: String,
+    date_added: Option<Timestamp>,
+    last_modified: Option<Timestamp>,
+    keyword: Option<String>,
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct HtmlFolder {
+    title: String,
+    date_added: Option<Timestamp>,
+    last_modified: Option<Timestamp>,
+    // Folders marked as being one of the roots have their children imported
+    // into that root, rather than into a new folder.
+    root: Option<BookmarkRootGuid>,
+    children: Vec<HtmlItem>,
+}
+
+#[derive(Debug)]
+enum HtmlItem {
+    Bookmark(HtmlBookmark),
+    Separator,
+    Folder(HtmlFolder),
+}
+
+// What we're reading the text of.
+enum Capture {
+    None,
+    Bookmark(HtmlBookmark),
+    Folder(HtmlFolder),
+    Ignored,
+}
+
+// The timestamps are supposed to be in seconds, but some exporters write
+// milliseconds or microseconds, so we guess based on the magnitude.
+fn parse_timestamp(value: Option<&String>) -> Option<Timestamp> {
+    let value = value?.trim().parse::<u64>().ok()?;
+    let millis = if value > 100_000_000_000_000 {
+        value / 1000
+    } else if value > 100_000_000_000 {
+        value
+    } else {
+        value.checked_mul(1000)?
+    };
+    if millis == 0 {
+        None
+    } else {
+        Some(Timestamp(millis))
+    }
+}
+
+fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        result.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity_end = rest.find(';').filter(|end| *end <= 10);
+        let decoded = entity_end.and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => {
+                    let code = if let Some(hex) = entity
+                        .strip_prefix("#x")
+                        .or_else(|| entity.strip_prefix("#X"))
+                    {
+                        u32::from_str_radix(hex, 16).ok()
+                    } else {
+                        entity.strip_prefix('#').and_then(|dec| dec.parse().ok())
+                    };
+                    code.and_then(char::from_u32)
+                }
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+// Parses the contents of a tag (ie, what's between `<` and `>`) into the
+// upper-cased tag name, whether it's a closing tag and the attributes, with
+// upper-cased names.
+fn parse_tag(tag: &str) -> (String, bool, HashMap<String, String>) {
+    let (closing, tag) = match tag.strip_prefix('/') {
+        Some(tag) => (true, tag),
+        None => (false, tag),
+    };
+    let name_end = tag
+        .find(|c: char| c.is_whitespace() || c == '/')
+        .unwrap_or(tag.len());
+    let name = tag[..name_end].to_ascii_uppercase();
+    let mut attrs = HashMap::new();
+    let mut rest = &tag[name_end..];
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            break;
+        }
+        let attr_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(rest.len());
+        let attr = rest[..attr_end].to_ascii_uppercase();
+        rest = rest[attr_end..].trim_start();
+        let value = match rest.strip_prefix('=') {
+            None => String::new(),
+            Some(after_eq) => {
+                let after_eq = after_eq.trim_start();
+                let (value, remaining) = match after_eq.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let quoted = &after_eq[1..];
+                        let end = quoted.find(quote).unwrap_or(quoted.len());
+                        (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+                    }
+                    _ => {
+                        let end = after_eq.find(char::is_whitespace).unwrap_or(after_eq.len());
+                        (&after_eq[..end], &after_eq[end..])
+                    }
+                };
+                rest = remaining;
+                unescape(value)
+            }
+        };
+        attrs.insert(attr, value);
+    }
+    (name, closing, attrs)
+}
+
+// Parses a `bookmarks.html` file into the items it contains. This is very
+// forgiving, as the files in the wild are.
+fn parse(html: &str) -> Vec<HtmlItem> {
+    // The folders we're inside, starting with the implicit top-level one.
+    let mut folders = vec![HtmlFolder::default()];
+    // For each `<DL>` we're inside, whether it holds the children of a folder.
+    let mut lists: Vec<bool> = Vec::new();
+    // A folder whose heading we've seen, but not yet its `<DL>`.
+    let mut pending_folder: Option<HtmlFolder> = None;
+    let mut capture = Capture::None;
+    let mut text = String::new();
+
+    // Folders are normally followed by their `<DL>`, but if not they're empty.
+    fn flush_pending(pending: &mut Option<HtmlFolder>, folders: &mut [HtmlFolder]) {
+        if let Some(folder) = pending.take() {
+            if let Some(parent) = folders.last_mut() {
+                parent.children.push(HtmlItem::Folder(folder));
+            }
+        }
+    }
+
+    let mut rest = html;
+    while let Some(lt) = rest.find('<') {
+        text.push_str(&rest[..lt]);
+        rest = &rest[lt..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let gt = match rest.find('>') {
+            Some(gt) => gt,
+            None => break,
+        };
+        let (name, closing, mut attrs) = parse_tag(&rest[1..gt]);
+        rest = &rest[gt + 1..];
+        match (name.as_str(), closing) {
+            ("A", false) => {
+                flush_pending(&mut pending_folder, &mut folders);
+                let url = attrs.get("HREF").and_then(|href| match Url::parse(href) {
+                    // `place:` URLs are queries specific to desktop.
+                    Ok(url) if url.scheme() != "place" => Some(url),
+                    Ok(_) => None,
+                    Err(e) => {
+                        log::warn!("ignoring bookmark with an invalid url: {:?}", e);
+                        None
+                    }
+                });
+                capture = Capture::Bookmark(HtmlBookmark {
+                    url,
+                    date_added: parse_timestamp(attrs.get("ADD_DATE")),
+                    last_modified: parse_timestamp(attrs.get("LAST_MODIFIED")),
+                    keyword: attrs
+                        .remove("SHORTCUTURL")
+                        .map(|keyword| keyword.trim().to_lowercase())
+                        .filter(|keyword| !keyword.is_empty()),
+                    tags: attrs
+                        .get("TAGS")
+                        .map(|tags| {
+                            tags.split(',')
+                                .filter_map(|tag| validate_tag(tag).ensure_valid().ok())
+                                .map(str::to_string)
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                    ..Default::default()
+                });
+                text.clear();
+            }
+            ("H3", false) => {
+                flush_pending(&mut pending_folder, &mut folders);
+                let is_set = |attr: &str| {
+                    attrs
+                        .get(attr)
+                        .map_or(false, |value| value.eq_ignore_ascii_case("true"))
+                };
+                let root = if is_set("PERSONAL_TOOLBAR_FOLDER") {
+                    Some(BookmarkRootGuid::Toolbar)
+                } else if is_set("UNFILED_BOOKMARKS_FOLDER") {
+                    Some(BookmarkRootGuid::Unfiled)
+                } else {
+                    None
+                };
+                capture = Capture::Folder(HtmlFolder {
+                    date_added: parse_timestamp(attrs.get("ADD_DATE")),
+                    last_modified: parse_timestamp(attrs.get("LAST_MODIFIED")),
+                    root,
+                    ..Default::default()
+                });
+                text.clear();
+            }
+            // The title of the file and the descriptions of items, which we
+            // don't keep.
+            ("H1" | "TITLE" | "DD", false) => {
+                flush_pending(&mut pending_folder, &mut folders);
+                capture = Capture::Ignored;
+            }
+            ("A" | "H3" | "H1" | "TITLE", true) => {
+                let title = unescape(text.trim());
+                match std::mem::replace(&mut capture, Capture::None) {
+                    Capture::Bookmark(mut bookmark) => {
+                        if bookmark.url.is_some() {
+                            bookmark.title = title;
+                            if let Some(folder) = folders.last_mut() {
+                                folder.children.push(HtmlItem::Bookmark(bookmark));
+                            }
+                        }
+                    }
+                    Capture::Folder(mut folder) => {
+                        folder.title = title;
+                        pending_folder = Some(folder);
+                    }
+                    Capture::None | Capture::Ignored => (),
+                }
+            }
+            ("HR", false) => {
+                flush_pending(&mut pending_folder, &mut folders);
+                capture = Capture::None;
+                if let Some(folder) = folders.last_mut() {
+                    folder.children.push(HtmlItem::Separator);
+                }
+            }
+            ("DL", false) => {
+                capture = Capture::None;
+                match pending_folder.take() {
+                    Some(folder) => {
+                        folders.push(folder);
+                        lists.push(true);
+                    }
+                    None => lists.push(false),
+                }
+            }
+            ("DL", true) => {
+                flush_pending(&mut pending_folder, &mut folders);
+                capture = Capture::None;
+                if lists.pop() == Some(true) && folders.len() > 1 {
+                    let folder = folders.pop().unwrap();
+                    if let Some(parent) = folders.last_mut() {
+                        parent.children.push(HtmlItem::Folder(folder));
+                    }
+                }
+            }
+            _ => (),
+        }
+        if !matches!(capture, Capture::Bookmark(_) | Capture::Folder(_)) {
+            text.clear();
+        }
+    }
+    flush_pending(&mut pending_folder, &mut folders);
+    // Close any folders which weren't closed.
+    while folders.len() > 1 {
+        let folder = folders.pop().unwrap();
+        folders
+            .last_mut()
+            .unwrap()
+            .children
+            .push(HtmlItem::Folder(folder));
+    }
+    folders.pop().unwrap().children
+}
+
+// Keywords are unique, so if the keyword is already used for another URL, or
+// the URL already has a keyword, we keep the existing one.
+fn add_keyword(db: &PlacesDb, url: &Url, keyword: &str) -> Result<()> {
+    db.execute_cached(
+        "INSERT OR IGNORE INTO moz_keywords(place_id, keyword)
+         SELECT id, :keyword FROM moz_places
+         WHERE url_hash = hash(:url) AND url = :url",
+        rusqlite::named_params! {
+            ":keyword": keyword,
+            ":url": url.as_str(),
+        },
+    )?;
+    Ok(())
+}
+
+fn insert_items(
+    db: &PlacesDb,
+    parent_guid: &SyncGuid,
+    items: Vec<HtmlItem>,
+    count: &mut u32,
+) -> Result<()> {
+    for item in items {
+        match item {
+            HtmlItem::Bookmark(bookmark) => {
+                let url = bookmark.url.expect("only bookmarks with a url are parsed");
+                insert_bookmark_in_tx(
+                    db,
+                    InsertableBookmark {
+                        parent_guid: parent_guid.clone(),
+                        position: BookmarkPosition::Append,
+                        date_added: bookmark.date_added,
+                        last_modified: bookmark.last_modified,
+                        guid: None,
+                        url: url.clone(),
+                        title: Some(bookmark.title).filter(|title| !title.is_empty()),
+                    }
+                    .into(),
+                )?;
+                if let Some(keyword) = bookmark.keyword {
+                    add_keyword(db, &url, &keyword)?;
+                }
+                for tag in bookmark.tags {
+                    tag_url_in_tx(db, &url, &tag)?;
+                }
+            }
+            HtmlItem::Separator => {
+                insert_bookmark_in_tx(
+                    db,
+                    InsertableSeparator {
+                        parent_guid: parent_guid.clone(),
+                        position: BookmarkPosition::Append,
+                        date_added: None,
+                        last_modified: None,
+                        guid: None,
+                    }
+                    .into(),
+                )?;
+            }
+            HtmlItem::Folder(folder) => {
+                if let Some(root) = folder.root {
+                    insert_items(db, root.guid(), folder.children, count)?;
+                    continue;
+                }
+                let guid = insert_bookmark_in_tx(
+                    db,
+                    InsertableFolder {
+                        parent_guid: parent_guid.clone(),
+                        position: BookmarkPosition::Append,
+                        date_added: folder.date_added,
+                        last_modified: folder.last_modified,
+                        guid: None,
+                        title: Some(folder.title).filter(|title| !title.is_empty()),
+                        children: vec![],
+                    }
+                    .into(),
+                )?;
+                insert_items(db, &guid, folder.children, count)?;
+            }
+        }
+        *count += 1;
+    }
+    Ok(())
+}
+
+/// Imports the bookmarks in a `bookmarks.html` file into the folder with
+/// `parent_guid`, returning how many items were imported. The contents of
+/// folders marked as the toolbar or "other bookmarks" folder are imported
+/// into those roots instead.
+pub fn import_html(db: &PlacesDb, html: &str, parent_guid: &SyncGuid) -> Result<u32> {
+    let items = parse(html);
+    let tx = db.begin_transaction()?;
+    let mut count = 0;
+    let result = insert_items(db, parent_guid, items, &mut count);
+    crate::storage::delete_pending_temp_tables(db)?;
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result.map(|_| count)
+}
+
+fn write_item(
+    out: &mut String,
+    node: &BookmarkTreeNode,
+    keywords: &HashMap<String, String>,
+    tags: &HashMap<String, String>,
+    depth: usize,
+) {
+    let indent = "    ".repeat(depth);
+    match node {
+        BookmarkTreeNode::Bookmark { b } => {
+            let url = b.url.as_str();
+            let _ = write!(
+                out,
+                "{}<DT><A HREF=\"{}\"{}",
+                indent,
+                escape(url),
+                timestamp_attrs(b.date_added, b.last_modified)
+            );
+            if let Some(keyword) = keywords.get(url) {
+                let _ = write!(out, " SHORTCUTURL=\"{}\"", escape(keyword));
+            }
+            if let Some(tags) = tags.get(url) {
+                let _ = write!(out, " TAGS=\"{}\"", escape(tags));
+            }
+            let _ = writeln!(
+                out,
+                ">{}</A>",
+                escape(b.title.as_deref().unwrap_or_default())
+            );
+        }
+        BookmarkTreeNode::Separator { .. } => {
+            let _ = writeln!(out, "{}<HR>", indent);
+        }
+        BookmarkTreeNode::Folder { f } => {
+            let _ = writeln!(
+                out,
+                "{}<DT><H3{}>{}</H3>",
+                indent,
+                timestamp_attrs(f.date_added, f.last_modified),
+                escape(f.title.as_deref().unwrap_or_default())
+            );
+            write_folder_contents(out, &f.children, keywords, tags, depth);
+        }
+    }
+}
+
+fn write_folder_contents(
+    out: &mut String,
+    children: &[BookmarkTreeNode],
+    keywords: &HashMap<String, String>,
+    tags: &HashMap<String, String>,
+    depth: usize,
+) {
+    let indent = "    ".repeat(depth);
+    let _ = writeln!(out, "{}<DL><p>", indent);
+    for child in children {
+        write_item(out, child, keywords, tags, depth + 1);
+    }
+    let _ = writeln!(out, "{}</DL><p>", indent);
+}
+
+fn timestamp_attrs(date_added: Option<Timestamp>, last_modified: Option<Timestamp>) -> String {
+    let mut attrs = String::new();
+    if let Some(date_added) = date_added {
+        let _ = write!(attrs, " ADD_DATE=\"{}\"", date_added.as_millis() / 1000);
+    }
+    if let Some(last_modified) = last_modified {
+        let _ = write!(
+            attrs,
+            " LAST_MODIFIED=\"{}\"",
+            last_modified.as_millis() / 1000
+        );
+    }
+    attrs
+}
+
+fn fetch_root_children(db: &PlacesDb, root: BookmarkRootGuid) -> Result<Vec<BookmarkTreeNode>> {
+    Ok(match fetch_tree(db, root.guid(), &FetchDepth::Deepest)? {
+        Some((BookmarkTreeNode::Folder { f }, _, _)) => f.children,
+        _ => vec![],
+    })
+}
+
+// Maps URLs to a string for the URL, from a query returning the URL and string.
+fn fetch_url_map(db: &PlacesDb, sql: &str) -> Result<HashMap<String, String>> {
+    Ok(db
+        .query_rows_and_then(sql, [], |row: &Row<'_>| -> Result<_> {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .into_iter()
+        .collect())
+}
+
+/// Exports all bookmarks as a `bookmarks.html` file, in the same shape
+/// desktop exports them - the menu's children at the top level, followed by
+/// folders for the other roots.
+pub fn export_html(db: &PlacesDb) -> Result<String> {
+    let keywords = fetch_url_map(
+        db,
+        "SELECT h.url, k.keyword FROM moz_keywords k
+         JOIN moz_places h ON h.id = k.place_id",
+    )?;
+    let tags = fetch_url_map(
+        db,
+        "SELECT h.url, GROUP_CONCAT(t.tag, ',') FROM moz_tags_relation r
+         JOIN moz_tags t ON t.id = r.tag_id
+         JOIN moz_places h ON h.id = r.place_id
+         GROUP BY h.id",
+    )?;
+
+    let mut out = String::from(
+        "<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<!-- This is an automatically generated file.
+     It will be read and overwritten.
+     DO NOT EDIT! -->
+<META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks Menu</H1>
+
+<DL><p>
+",
+    );
+    for child in fetch_root_children(db, BookmarkRootGuid::Menu)? {
+        write_item(&mut out, &child, &keywords, &tags, 1);
+    }
+    for (root, title, attr) in [
+        (
+            BookmarkRootGuid::Toolbar,
+            TOOLBAR_TITLE,
+            " PERSONAL_TOOLBAR_FOLDER=\"true\"",
+        ),
+        (
+            BookmarkRootGuid::Unfiled,
+            UNFILED_TITLE,
+            " UNFILED_BOOKMARKS_FOLDER=\"true\"",
+        ),
+        (BookmarkRootGuid::Mobile, MOBILE_TITLE, ""),
+    ] {
+        let children = fetch_root_children(db, root)?;
+        if children.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "    <DT><H3{}>{}</H3>", attr, title);
+        write_folder_contents(&mut out, &children, &keywords, &tags, 1);
+    }
+    out.push_str("</DL>\n");
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::bookmarks::bookmarks_get_url_for_keyword;
+    use crate::storage::tags::get_tags_for_url;
+    use crate::tests::assert_json_tree;
+    use serde_json::json;
+
+    const CHROME_EXPORT: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<!-- This is an automatically generated file.
+     It will be read and overwritten.
+     DO NOT EDIT! -->
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks</H1>
+<DL><p>
+    <DT><H3 ADD_DATE="1643764207" LAST_MODIFIED="1643764208" PERSONAL_TOOLBAR_FOLDER="true">Bookmarks bar</H3>
+    <DL><p>
+        <DT><A HREF="https://www.mozilla.org/" ADD_DATE="1643764207" SHORTCUTURL="Moz" TAGS="work,mozilla">Mozilla &amp; friends</A>
+        <HR>
+        <DT><H3 ADD_DATE="1643764207">News</H3>
+        <DL><p>
+            <DT><A HREF="https://example.com/news" ADD_DATE="1643764207000">Example news</A>
+            <DD>A description we don't keep
+            <DT><A HREF="not a url">Invalid</A>
+            <DT><A HREF="place:sort=8&maxResults=10">Most visited</A>
+        </DL><p>
+        <DT><H3>Empty</H3>
+    </DL><p>
+    <DT><A HREF="https://example.com/other">Other</A>
+</DL><p>
+"#;
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("a &amp; b"), "a & b");
+        assert_eq!(unescape("&lt;&gt;&quot;&#39;&#x41;"), "<>\"'A");
+        assert_eq!(unescape("AT&T & co;"), "AT&T & co;");
+        assert_eq!(unescape(&escape("<\"a\" & 'b'>")), "<\"a\" & 'b'>");
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let seconds = 1_643_764_207u64;
+        for value in [seconds, seconds * 1000, seconds * 1_000_000] {
+            assert_eq!(
+                parse_timestamp(Some(&value.to_string())),
+                Some(Timestamp(seconds * 1000))
+            );
+        }
+        assert_eq!(parse_timestamp(Some(&"0".to_string())), None);
+        assert_eq!(parse_timestamp(Some(&"bogus".to_string())), None);
+        assert_eq!(parse_timestamp(None), None);
+    }
+
+    #[test]
+    fn test_import() -> Result<()> {
+        let conn = new_mem_connection();
+        let count = import_html(&conn, CHROME_EXPORT, BookmarkRootGuid::Unfiled.guid())?;
+        assert_eq!(count, 6);
+
+        // The toolbar folder was imported into the toolbar.
+        assert_json_tree(
+            &conn,
+            BookmarkRootGuid::Toolbar.guid(),
+            json!({
+                "guid": &BookmarkRootGuid::Toolbar.as_guid(),
+                "children": [
+                    {
+                        "title": "Mozilla & friends",
+                        "url": "https://www.mozilla.org/",
+                        "date_added": 1_643_764_207_000u64,
+                    },
+                    {
+                        "type": 3,
+                    },
+                    {
+                        "title": "News",
+                        "children": [
+                            {
+                                "title": "Example news",
+                                "url": "https://example.com/news",
+                                "date_added": 1_643_764_207_000u64,
+                            },
+                        ],
+                    },
+                    {
+                        "title": "Empty",
+                        "children": [],
+                    },
+                ],
+            }),
+        );
+        assert_json_tree(
+            &conn,
+            BookmarkRootGuid::Unfiled.guid(),
+            json!({
+                "guid": &BookmarkRootGuid::Unfiled.as_guid(),
+                "children": [
+                    {
+                        "title": "Other",
+                        "url": "https://example.com/other",
+                    },
+                ],
+            }),
+        );
+
+        let url = Url::parse("https://www.mozilla.org/")?;
+        assert_eq!(
+            bookmarks_get_url_for_keyword(&conn, "moz")?,
+            Some(url.clone())
+        );
+        let mut tags = get_tags_for_url(&conn, &url)?;
+        tags.sort();
+        assert_eq!(tags, vec!["mozilla".to_string(), "work".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_bad_parent() {
+        let conn = new_mem_connection();
+        assert!(import_html(&conn, CHROME_EXPORT, &SyncGuid::from("nonexistent_")).is_err());
+        // Nothing was imported.
+        assert_json_tree(
+            &conn,
+            BookmarkRootGuid::Toolbar.guid(),
+            json!({
+                "guid": &BookmarkRootGuid::Toolbar.as_guid(),
+                "children": [],
+            }),
+        );
+    }
+
+    #[test]
+    fn test_export_round_trip() -> Result<()> {
+        let conn = new_mem_connection();
+        import_html(&conn, CHROME_EXPORT, BookmarkRootGuid::Menu.guid())?;
+        let exported = export_html(&conn)?;
+        assert!(exported.starts_with("<!DOCTYPE NETSCAPE-Bookmark-file-1>"));
+        assert!(exported.contains("SHORTCUTURL=\"moz\""));
+        assert!(exported.contains(">Mozilla &amp; friends</A>"));
+
+        // Importing our export into another database gives the same tree.
+        let other = new_mem_connection();
+        import_html(&other, &exported, BookmarkRootGuid::Menu.guid())?;
+        for root in [
+            BookmarkRootGuid::Menu,
+            BookmarkRootGuid::Toolbar,
+            BookmarkRootGuid::Unfiled,
+        ] {
+            let (tree, _, _) = fetch_tree(&conn, root.guid(), &FetchDepth::Deepest)?.unwrap();
+            let mut expected = serde_json::to_value(tree)?;
+            strip_ids_and_times(&mut expected);
+            expected["guid"] = json!(root.as_guid());
+            assert_json_tree(&other, root.guid(), expected);
+        }
+        assert_eq!(
+            bookmarks_get_url_for_keyword(&other, "moz")?,
+            Some(Url::parse("https://www.mozilla.org/")?)
+        );
+        Ok(())
+    }
+
+    // The guids differ between databases, and items without timestamps in
+    // the file get the time they were imported.
+    fn strip_ids_and_times(value: &mut serde_json::Value) {
+        if let Some(obj) = value.as_object_mut() {
+            obj.remove("guid");
+            obj.remove("date_added");
+            obj.remove("last_modified");
+            if let Some(children) = obj.get_mut("children").and_then(|c| c.as_array_mut()) {
+                children.iter_mut().for_each(strip_ids_and_times);
+            }
+        }
+    }
+}
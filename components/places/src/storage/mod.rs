@@ -8,6 +8,7 @@
 pub mod bookmarks;
 pub mod history;
 pub mod history_metadata;
+pub mod search_index;
 pub mod tags;
 
 use crate::db::PlacesDb;
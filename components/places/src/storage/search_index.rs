@@ -0,0 +1,277 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Support for the optional full-text search index in `moz_places_fts`. This
+// is much faster than the matcher in `api/matcher.rs` on large databases,
+// because it doesn't need to look at every place, but it only knows about
+// words - it won't, for example, match the middle of a word.
+
+use super::{get_meta, put_meta};
+use crate::api::matcher::{MatchReason, SearchResult};
+use crate::db::schema::MOZ_META_KEY_SEARCH_INDEX_ENABLED;
+use crate::db::PlacesDb;
+use crate::error::Result;
+use sql_support::ConnExt;
+use url::Url;
+
+/// Returns true if the search index has been built, and so is being kept up
+/// to date.
+pub fn is_search_index_enabled(db: &PlacesDb) -> Result<bool> {
+    Ok(get_meta::<bool>(db, MOZ_META_KEY_SEARCH_INDEX_ENABLED)?.unwrap_or(false))
+}
+
+/// Run maintenance on the places DB (search index step)
+///
+/// Builds the full-text search index from scratch, and enables the triggers
+/// which keep it up to date from then on, including for changes made by Sync.
+/// This reads every place, so it's intended to be run during idle time.
+pub fn run_maintenance_rebuild_search_index(db: &PlacesDb) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    db.execute_batch(
+        "DELETE FROM moz_places_fts;
+         INSERT INTO moz_places_fts(rowid, title, url, bookmark_titles)
+         SELECT
+             h.id,
+             IFNULL(h.title, ''),
+             h.url,
+             IFNULL((SELECT group_concat(b.title, ' ') FROM moz_bookmarks b
+                     WHERE b.fk = h.id), '')
+         FROM moz_places h;
+         INSERT INTO moz_places_fts(moz_places_fts) VALUES('optimize');",
+    )?;
+    put_meta(db, MOZ_META_KEY_SEARCH_INDEX_ENABLED, &true)?;
+    tx.commit()?;
+    Ok(())
+}
+
+// Turns what the user typed into an FTS5 query which matches places that
+// have every word as a prefix of one of their words. Each word is quoted, so
+// that FTS5 operators and punctuation are treated as text.
+fn to_fts_query(search: &str) -> Option<String> {
+    let terms = search
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Searches the full-text index for places matching every word in `search`,
+/// as a prefix. Results are ranked by bm25, boosted by frecency - a very
+/// frecent page can rank up to twice as high as an equally relevant page
+/// which has never been visited. Titles, including bookmark titles, count
+/// for more than URLs.
+///
+/// Returns nothing if the index hasn't been built with
+/// `run_maintenance_rebuild_search_index()`.
+pub fn search_index(db: &PlacesDb, search: &str, limit: u32) -> Result<Vec<SearchResult>> {
+    let query = match to_fts_query(search) {
+        Some(query) => query,
+        None => return Ok(Vec::new()),
+    };
+    db.query_rows_and_then_cached(
+        "SELECT h.url, h.title, h.frecency,
+                (SELECT b.title FROM moz_bookmarks b
+                 WHERE b.fk = h.id AND b.title NOT NULL
+                 ORDER BY b.lastModified DESC LIMIT 1) AS btitle,
+                EXISTS(SELECT 1 FROM moz_bookmarks b WHERE b.fk = h.id) AS bookmarked
+         FROM moz_places_fts f
+         JOIN moz_places h ON h.id = f.rowid
+         WHERE moz_places_fts MATCH :query
+           AND h.hidden = 0
+         ORDER BY bm25(moz_places_fts, 2.0, 1.0, 2.0)
+                  * (1.0 + MAX(h.frecency, 0) / (MAX(h.frecency, 0) + 1000.0))
+         LIMIT :limit",
+        rusqlite::named_params! {
+            ":query": query,
+            ":limit": limit,
+        },
+        |row| -> Result<_> {
+            let url = Url::parse(&row.get::<_, String>("url")?)?;
+            let title = row
+                .get::<_, Option<String>>("btitle")?
+                .or(row.get::<_, Option<String>>("title")?)
+                .unwrap_or_default();
+            let reasons = if row.get::<_, bool>("bookmarked")? {
+                vec![MatchReason::Bookmark]
+            } else {
+                vec![MatchReason::Url]
+            };
+            Ok(SearchResult {
+                search_string: search.to_owned(),
+                url,
+                title,
+                icon_url: None,
+                frecency: row.get("frecency")?,
+                reasons,
+            })
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::{new_mem_api, new_mem_connection};
+    use crate::api::places_api::ConnectionType;
+    use crate::history_sync::record::HistoryRecordVisit;
+    use crate::observation::VisitObservation;
+    use crate::storage::bookmarks::{
+        insert_bookmark, update_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+        UpdatableBookmark, UpdatableItem,
+    };
+    use crate::storage::history::history_sync::{apply_synced_deletion, apply_synced_visits};
+    use crate::storage::history::{apply_observation, delete_visits_for, url_to_guid};
+    use crate::types::{UnknownFields, VisitTransition};
+    use sync_guid::Guid as SyncGuid;
+    use types::Timestamp;
+
+    fn visit(db: &PlacesDb, url: &str, title: &str) {
+        apply_observation(
+            db,
+            VisitObservation::new(Url::parse(url).unwrap())
+                .with_title(title.to_string())
+                .with_visit_type(VisitTransition::Link),
+        )
+        .expect("should apply the observation");
+    }
+
+    fn search_urls(db: &PlacesDb, search: &str) -> Vec<String> {
+        search_index(db, search, 10)
+            .expect("should search")
+            .into_iter()
+            .map(|r| r.url.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_to_fts_query() {
+        assert_eq!(to_fts_query("  "), None);
+        assert_eq!(to_fts_query("moz"), Some("\"moz\"*".to_string()));
+        assert_eq!(
+            to_fts_query("foo \"bar OR"),
+            Some("\"foo\"* \"\"\"bar\"* \"OR\"*".to_string())
+        );
+    }
+
+    #[test]
+    fn test_search_index() -> Result<()> {
+        let conn = new_mem_connection();
+        visit(&conn, "https://www.mozilla.org/", "Internet for people");
+        assert!(!is_search_index_enabled(&conn)?);
+        // Nothing is indexed until the index is built.
+        assert!(search_urls(&conn, "internet").is_empty());
+
+        run_maintenance_rebuild_search_index(&conn)?;
+        assert!(is_search_index_enabled(&conn)?);
+        assert_eq!(
+            search_urls(&conn, "intern PEOPLE"),
+            vec!["https://www.mozilla.org/"]
+        );
+        assert_eq!(
+            search_urls(&conn, "mozilla"),
+            vec!["https://www.mozilla.org/"]
+        );
+        assert!(search_urls(&conn, "profit").is_empty());
+
+        // New places, and bookmark titles, are picked up by the triggers.
+        visit(&conn, "https://example.com/recipes", "Recipes");
+        let guid = insert_bookmark(
+            &conn,
+            InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse("https://example.com/recipes")?,
+                title: Some("Dinner ideas".into()),
+            }
+            .into(),
+        )?;
+        assert_eq!(
+            search_urls(&conn, "dinner"),
+            vec!["https://example.com/recipes"]
+        );
+        let results = search_index(&conn, "recipes", 10)?;
+        assert_eq!(results[0].title, "Dinner ideas");
+        assert_eq!(results[0].reasons, vec![MatchReason::Bookmark]);
+
+        update_bookmark(
+            &conn,
+            &guid,
+            &UpdatableItem::Bookmark {
+                b: UpdatableBookmark {
+                    title: Some("Lunch ideas".into()),
+                    ..Default::default()
+                },
+            },
+        )?;
+        assert!(search_urls(&conn, "dinner").is_empty());
+        assert_eq!(
+            search_urls(&conn, "lunch"),
+            vec!["https://example.com/recipes"]
+        );
+
+        // Deleting a place removes it from the index.
+        let mozilla_guid =
+            url_to_guid(&conn, &Url::parse("https://www.mozilla.org/")?)?.expect("should exist");
+        delete_visits_for(&conn, &mozilla_guid)?;
+        assert!(search_urls(&conn, "people").is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_index_sync() -> Result<()> {
+        let api = new_mem_api();
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        visit(&conn, "https://example.com/old", "Old page");
+        run_maintenance_rebuild_search_index(&conn)?;
+
+        // Places and visits applied by Sync are indexed...
+        let sync_conn = api.get_sync_connection()?;
+        apply_synced_visits(
+            &sync_conn.lock(),
+            &SyncGuid::random(),
+            &Url::parse("https://example.com/synced")?,
+            &Some("Synced page".to_string()),
+            &[HistoryRecordVisit {
+                date: Timestamp::now().into(),
+                transition: VisitTransition::Link as u8,
+                unknown_fields: UnknownFields::new(),
+            }],
+            &UnknownFields::new(),
+        )?;
+        assert_eq!(
+            search_urls(&conn, "synced"),
+            vec!["https://example.com/synced"]
+        );
+
+        // ...and so are deletions.
+        let old_guid =
+            url_to_guid(&conn, &Url::parse("https://example.com/old")?)?.expect("should exist");
+        apply_synced_deletion(&sync_conn.lock(), &old_guid)?;
+        assert!(search_urls(&conn, "old").is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_index_frecency() -> Result<()> {
+        let conn = new_mem_connection();
+        visit(&conn, "https://example.com/a", "Rust news");
+        for _ in 0..5 {
+            visit(&conn, "https://example.com/b", "Rust news");
+        }
+        run_maintenance_rebuild_search_index(&conn)?;
+        // Equally relevant, so the more frecent page wins.
+        assert_eq!(
+            search_urls(&conn, "rust"),
+            vec!["https://example.com/b", "https://example.com/a"]
+        );
+        Ok(())
+    }
+}
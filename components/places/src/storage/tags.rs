@@ -68,7 +68,13 @@ pub fn validate_tag(tag: &str) -> ValidatedTag<'_> {
 pub fn tag_url(db: &PlacesDb, url: &Url, tag: &str) -> Result<()> {
     let tag = validate_tag(tag).ensure_valid()?;
     let tx = db.begin_transaction()?;
+    tag_url_in_tx(db, url, tag)?;
+    tx.commit()?;
+    Ok(())
+}
 
+// Does the work of `tag_url` for a tag which has already been validated.
+pub(crate) fn tag_url_in_tx(db: &PlacesDb, url: &Url, tag: &str) -> Result<()> {
     // This function will not create a new place.
     // Fetch the place id, so we (a) avoid creating a new tag when we aren't
     // going to reference it and (b) to avoid a sub-query.
@@ -91,7 +97,6 @@ pub fn tag_url(db: &PlacesDb, url: &Url, tag: &str) -> Result<()> {
             (":place_id", &place_id),
         ],
     )?;
-    tx.commit()?;
     Ok(())
 }
 
@@ -228,6 +233,93 @@ pub fn get_tags_for_url(db: &PlacesDb, url: &Url) -> Result<Vec<String>> {
     Ok(tags)
 }
 
+/// A tag, along with the number of URLs which have it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TagInfo {
+    pub tag: String,
+    pub url_count: u32,
+}
+
+/// Retrieves all tags which are in use.
+///
+/// # Arguments
+///
+/// * `conn` - A database connection on which to operate.
+///
+/// # Returns
+///
+/// * A Vec<TagInfo> with every tag which has at least one URL, sorted by the
+///   number of URLs (most to least), then by name.
+pub fn get_all_tags(db: &PlacesDb) -> Result<Vec<TagInfo>> {
+    db.query_rows_and_then(
+        "SELECT t.tag, COUNT(*) AS url_count
+         FROM moz_tags t
+         JOIN moz_tags_relation r ON r.tag_id = t.id
+         GROUP BY t.id
+         ORDER BY url_count DESC, t.tag",
+        [],
+        |row| -> Result<_> {
+            Ok(TagInfo {
+                tag: row.get("tag")?,
+                url_count: row.get("url_count")?,
+            })
+        },
+    )
+}
+
+/// Renames a tag on all URLs which have it. If the new tag already exists,
+/// the two tags are merged.
+///
+/// # Arguments
+///
+/// * `conn` - A database connection on which to operate.
+///
+/// * `old_tag` - The tag to rename.
+///
+/// * `new_tag` - The new name for the tag.
+///
+/// # Returns
+///
+/// There is no success return value - the operation is ignored if no URLs
+/// have the old tag.
+pub fn rename_tag(db: &PlacesDb, old_tag: &str, new_tag: &str) -> Result<()> {
+    let old_tag = validate_tag(old_tag).ensure_valid()?;
+    let new_tag = validate_tag(new_tag).ensure_valid()?;
+    if old_tag == new_tag {
+        return Ok(());
+    }
+    let tx = db.begin_transaction()?;
+    // Rather than updating the tag in place, we move each URL over to the new
+    // tag, so that the `moz_tags_relation` triggers bump the change counters
+    // of the affected bookmarks and they're reuploaded with the new tag.
+    db.execute_cached(
+        "INSERT OR IGNORE INTO moz_tags(tag, lastModified)
+         SELECT :new_tag, now()
+         WHERE EXISTS(SELECT 1 FROM moz_tags WHERE tag = :old_tag)",
+        &[(":old_tag", &old_tag), (":new_tag", &new_tag)],
+    )?;
+    db.execute_cached(
+        "INSERT OR IGNORE INTO moz_tags_relation(tag_id, place_id)
+         SELECT (SELECT id FROM moz_tags WHERE tag = :new_tag), r.place_id
+         FROM moz_tags_relation r
+         JOIN moz_tags t ON t.id = r.tag_id
+         WHERE t.tag = :old_tag",
+        &[(":old_tag", &old_tag), (":new_tag", &new_tag)],
+    )?;
+    db.execute_cached(
+        "DELETE FROM moz_tags_relation
+         WHERE tag_id = (SELECT id FROM moz_tags WHERE tag = :old_tag)",
+        &[(":old_tag", &old_tag)],
+    )?;
+    db.execute_cached(
+        "DELETE FROM moz_tags
+         WHERE tag = :old_tag",
+        &[(":old_tag", &old_tag)],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -364,4 +456,94 @@ mod tests {
             .expect("should work")
             .expect("should exist");
     }
+
+    #[test]
+    fn test_get_all_tags() {
+        let conn = new_mem_connection();
+        let url1 = Url::parse("http://example.com").expect("valid url");
+        let url2 = Url::parse("http://example2.com").expect("valid url");
+        new_page_info(&conn, &url1, None).expect("should create the page");
+        new_page_info(&conn, &url2, None).expect("should create the page");
+        assert_eq!(get_all_tags(&conn).expect("should work"), vec![]);
+
+        tag_url(&conn, &url1, "common").expect("should work");
+        tag_url(&conn, &url2, "common").expect("should work");
+        tag_url(&conn, &url2, "b-tag").expect("should work");
+        tag_url(&conn, &url1, "a-tag").expect("should work");
+        // Unused tags aren't returned.
+        tag_url(&conn, &url1, "unused").expect("should work");
+        untag_url(&conn, &url1, "unused").expect("should work");
+
+        assert_eq!(
+            get_all_tags(&conn).expect("should work"),
+            vec![
+                TagInfo {
+                    tag: "common".to_string(),
+                    url_count: 2
+                },
+                TagInfo {
+                    tag: "a-tag".to_string(),
+                    url_count: 1
+                },
+                TagInfo {
+                    tag: "b-tag".to_string(),
+                    url_count: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_tag() {
+        use crate::storage::bookmarks::{
+            insert_bookmark, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+        };
+
+        let conn = new_mem_connection();
+        let url1 = Url::parse("http://example.com").expect("valid url");
+        let url2 = Url::parse("http://example2.com").expect("valid url");
+        let guid = insert_bookmark(
+            &conn,
+            InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.as_guid(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: url1.clone(),
+                title: None,
+            }
+            .into(),
+        )
+        .expect("should insert the bookmark");
+        new_page_info(&conn, &url2, None).expect("should create the page");
+
+        tag_url(&conn, &url1, "old").expect("should work");
+        tag_url(&conn, &url2, "old").expect("should work");
+        tag_url(&conn, &url2, "new").expect("should work");
+
+        let get_change_counter = || -> u32 {
+            conn.query_row(
+                "SELECT syncChangeCounter FROM moz_bookmarks WHERE guid = :guid",
+                &[(":guid", &guid)],
+                |row| row.get(0),
+            )
+            .expect("should get the change counter")
+        };
+        let counter_before = get_change_counter();
+
+        rename_tag(&conn, "old", " new ").expect("should work");
+        check_tags_for_url(&conn, &url1, vec!["new".to_string()]);
+        check_tags_for_url(&conn, &url2, vec!["new".to_string()]);
+        check_urls_with_tag(&conn, "old", vec![]);
+        assert_eq!(get_foreign_count(&conn, &url2), 1);
+        assert!(get_change_counter() > counter_before);
+
+        // Renaming a tag which doesn't exist does nothing.
+        rename_tag(&conn, "missing", "new").expect("should work");
+        check_urls_with_tag(&conn, "missing", vec![]);
+        check_urls_with_tag(&conn, "new", vec![url1.clone(), url2.clone()]);
+
+        assert!(rename_tag(&conn, "new", "").is_err());
+    }
 }
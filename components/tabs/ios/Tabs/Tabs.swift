@@ -19,6 +19,13 @@ open class TabsStorage {
         }
     }
 
+    /// Search the tabs open on other devices.
+    open func searchRemoteTabs(query: String, perClientLimit: UInt32, totalLimit: UInt32) -> [RemoteTabSearchResult] {
+        return queue.sync {
+            self.store.searchRemoteTabs(query: query, perClientLimit: perClientLimit, totalLimit: totalLimit)
+        }
+    }
+
     /// Set the local tabs.
     open func setLocalTabs(remoteTabs: [RemoteTabRecord]) {
         queue.sync {
@@ -26,6 +33,41 @@ open class TabsStorage {
         }
     }
 
+    /// Set the tabs recently closed on this device.
+    open func setRecentlyClosedTabs(recentlyClosed: [RecentlyClosedTab]) {
+        queue.sync {
+            self.store.setRecentlyClosedTabs(recentlyClosed: recentlyClosed)
+        }
+    }
+
+    /// Get the tabs recently closed on another device.
+    open func getRecentlyClosed(clientId: String) throws -> [RecentlyClosedTab] {
+        return try queue.sync {
+            try self.store.getRecentlyClosed(clientId: clientId)
+        }
+    }
+
+    /// Ask another device to close its tabs with these URLs.
+    open func closeRemoteTabs(clientId: String, urls: [String]) throws {
+        try queue.sync {
+            try self.store.closeRemoteTabs(clientId: clientId, urls: urls)
+        }
+    }
+
+    /// Get the requests from other devices to close our tabs.
+    open func getPendingCloseTabRequests() throws -> [PendingCloseTabRequest] {
+        return try queue.sync {
+            try self.store.getPendingCloseTabRequests()
+        }
+    }
+
+    /// Acknowledge the requests to close tabs with these URLs.
+    open func acknowledgeCloseTabRequests(urls: [String]) throws {
+        try queue.sync {
+            try self.store.acknowledgeCloseTabRequests(urls: urls)
+        }
+    }
+
     open func reset() throws {
         try queue.sync {
             try self.store.reset()
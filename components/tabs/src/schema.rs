@@ -24,6 +24,29 @@ const CREATE_SCHEMA_SQL: &str = "
     );
 ";
 
+// Requests to close tabs on other devices. `outgoing_close_tab_requests` are
+// the ones made by the user of this device, which we send in our tabs record
+// until the other device closes the tab or the request expires.
+// `incoming_close_tab_requests` are the ones we've seen in other devices'
+// records for this device, which the app acknowledges once it has closed
+// the tabs.
+const CREATE_CLOSE_TAB_REQUESTS_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS outgoing_close_tab_requests (
+        device_id       TEXT NOT NULL,
+        url             TEXT NOT NULL,
+        time_requested  INTEGER NOT NULL,
+        PRIMARY KEY (device_id, url)
+    );
+
+    CREATE TABLE IF NOT EXISTS incoming_close_tab_requests (
+        from_client_id      TEXT NOT NULL,
+        url                 TEXT NOT NULL,
+        time_requested      INTEGER NOT NULL,
+        time_acknowledged   INTEGER,
+        PRIMARY KEY (from_client_id, url, time_requested)
+    );
+";
+
 const CREATE_META_TABLE_SQL: &str = "
     CREATE TABLE IF NOT EXISTS moz_meta (
         key    TEXT PRIMARY KEY,
@@ -38,12 +61,21 @@ pub(crate) static COLLECTION_SYNCID_META_KEY: &str = "tabs_sync_id";
 // of connected clients when syncing, however getting the list of tabs could be called at anytime
 // so we store it so we can translate from the tabs sync record ID to the FxA device id for the client
 pub(crate) static REMOTE_CLIENTS_KEY: &str = "remote_clients";
+// The local tabs, as last set by the app, so we can upload them in a sync
+// which happens before the app sets them after a restart.
+pub(crate) static LOCAL_TABS_KEY: &str = "local_tabs";
+// Likewise for the tabs recently closed locally.
+pub(crate) static RECENTLY_CLOSED_KEY: &str = "recently_closed";
+// A hash of the payload of our last uploaded record and when we uploaded it,
+// so we can avoid uploading the same record again.
+pub(crate) static LAST_UPLOAD_HASH_KEY: &str = "last_upload_hash";
+pub(crate) static LAST_UPLOAD_TIME_KEY: &str = "last_upload_time";
 
 pub struct TabsMigrationLogic;
 
 impl MigrationLogic for TabsMigrationLogic {
     const NAME: &'static str = "tabs storage db";
-    const END_VERSION: u32 = 2;
+    const END_VERSION: u32 = 3;
 
     fn prepare(&self, conn: &Connection, _db_empty: bool) -> MigrationResult<()> {
         let initial_pragmas = "
@@ -62,13 +94,18 @@ impl MigrationLogic for TabsMigrationLogic {
 
     fn init(&self, db: &Transaction<'_>) -> MigrationResult<()> {
         log::debug!("Creating schemas");
-        db.execute_all(&[CREATE_SCHEMA_SQL, CREATE_META_TABLE_SQL])?;
+        db.execute_all(&[
+            CREATE_SCHEMA_SQL,
+            CREATE_META_TABLE_SQL,
+            CREATE_CLOSE_TAB_REQUESTS_SQL,
+        ])?;
         Ok(())
     }
 
     fn upgrade_from(&self, db: &Transaction<'_>, version: u32) -> MigrationResult<()> {
         match version {
             1 => upgrade_from_v1(db),
+            2 => upgrade_from_v2(db),
             _ => Err(MigrationError::IncompatibleVersion(version)),
         }
     }
@@ -83,6 +120,11 @@ fn upgrade_from_v1(db: &Connection) -> MigrationResult<()> {
     Ok(())
 }
 
+fn upgrade_from_v2(db: &Connection) -> MigrationResult<()> {
+    db.execute_batch(CREATE_CLOSE_TAB_REQUESTS_SQL)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,6 +146,8 @@ mod tests {
         let conn = db.open_or_create().unwrap();
         conn.execute_batch(CREATE_SCHEMA_SQL)
             .expect("should allow running twice");
+        conn.execute_batch(CREATE_CLOSE_TAB_REQUESTS_SQL)
+            .expect("should allow running twice");
     }
 
     #[test]
@@ -147,4 +191,20 @@ mod tests {
         // Verify we can query for a valid guid now
         assert_eq!(row.unwrap(), "my-device");
     }
+
+    #[test]
+    fn test_tabs_db_upgrade_from_v2() {
+        let db_file = MigratedDatabaseFile::new(
+            TabsMigrationLogic,
+            &format!("{CREATE_SCHEMA_SQL}{CREATE_META_TABLE_SQL}; PRAGMA user_version=2;"),
+        );
+        db_file.run_all_upgrades();
+        let db = db_file.open();
+        db.execute_batch(
+            "SELECT device_id, url, time_requested FROM outgoing_close_tab_requests;
+             SELECT from_client_id, url, time_requested, time_acknowledged
+             FROM incoming_close_tab_requests;",
+        )
+        .expect("close tab request tables should exist");
+    }
 }
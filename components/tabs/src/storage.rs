@@ -9,7 +9,7 @@ const TAB_ENTRIES_LIMIT: usize = 5;
 
 use crate::error::*;
 use crate::schema;
-use crate::sync::record::TabsRecord;
+use crate::sync::record::{TabsRecord, TabsRecordCloseTabRequest};
 use crate::DeviceType;
 use rusqlite::{
     types::{FromSql, ToSql},
@@ -19,8 +19,9 @@ use serde_derive::{Deserialize, Serialize};
 use sql_support::open_database::{self, open_database_with_flags};
 use sql_support::ConnExt;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 use sync15::{RemoteClient, ServerTimestamp};
 pub type TabsDeviceType = crate::DeviceType;
 pub type RemoteTabRecord = RemoteTab;
@@ -29,13 +30,34 @@ pub(crate) const TABS_CLIENT_TTL: u32 = 15_552_000; // 180 days, same as CLIENTS
 const FAR_FUTURE: i64 = 4_102_405_200_000; // 2100/01/01
 const MAX_PAYLOAD_SIZE: usize = 512 * 1024; // Twice as big as desktop, still smaller than server max (2MB)
 const MAX_TITLE_CHAR_LENGTH: usize = 512; // We put an upper limit on title sizes for tabs to reduce memory
+const MAX_RECENTLY_CLOSED_TABS: usize = 25; // We only sync the most recently closed tabs
+const MAX_RECENTLY_CLOSED_PAYLOAD_SIZE: usize = 64 * 1024; // Leaving most of the payload for the open tabs
+const MAX_CLOSE_TAB_REQUESTS: usize = 100; // We only send the most recent requests to close remote tabs
+const MAX_CLOSE_TAB_REQUESTS_PAYLOAD_SIZE: usize = 32 * 1024; // Also taken out of the budget for the open tabs
+const UNCHANGED_UPLOAD_INTERVAL_MS: i64 = 24 * 60 * 60 * 1000; // We upload unchanged tabs once a day, so we don't look stale
+const CLOSE_TAB_REQUEST_TTL_MS: i64 = 7 * 24 * 60 * 60 * 1000; // We give up on closing remote tabs after a week
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct RemoteTab {
     pub title: String,
     pub url_history: Vec<String>,
     pub icon: Option<String>,
     pub last_used: i64, // In ms.
+    // serde default so we can read old rows that didn't persist these.
+    #[serde(default)]
+    pub pinned: bool,
+    // Inactive tabs are ones the user hasn't used for some time.
+    #[serde(default)]
+    pub inactive: bool,
+    #[serde(default)]
+    pub group: Option<RemoteTabGroup>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteTabGroup {
+    pub id: String,
+    pub name: String,
+    pub color: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -56,6 +78,33 @@ pub struct ClientRemoteTabs {
     pub remote_tabs: Vec<RemoteTab>,
 }
 
+// A request from another device to close one of our tabs, which the app
+// should act on and then acknowledge.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingCloseTabRequest {
+    pub url: String,
+    pub time_requested: i64, // In ms.
+}
+
+// A remote tab which matched `TabsStorage::search_remote_tabs()`, with the
+// client it's open on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteTabSearchResult {
+    pub client_id: String,
+    pub client_name: String,
+    pub device_type: DeviceType,
+    pub tab: RemoteTab,
+}
+
+// A tab which a client closed recently, so the user can reopen it on another
+// device.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentlyClosedTab {
+    pub title: String,
+    pub url: String,
+    pub time_closed: i64, // In ms.
+}
+
 fn devicetype_default_deser() -> DeviceType {
     // replace with `DeviceType::default_deser` once #4861 lands.
     DeviceType::Unknown
@@ -68,8 +117,8 @@ fn devicetype_is_unknown(val: &DeviceType) -> bool {
 
 // Tabs has unique requirements for storage:
 // * The "local_tabs" exist only so we can sync them out. There's no facility to
-//   query "local tabs", so there's no need to store these persistently - ie, they
-//   are write-only.
+//   query "local tabs", but we persist them when a database exists, so a sync
+//   soon after startup can upload them before the app sets them again.
 // * The "remote_tabs" exist purely for incoming items via sync - there's no facility
 //   to set them locally - they are read-only.
 // Note that this means a database is only actually needed after Sync fetches remote tabs,
@@ -80,6 +129,7 @@ fn devicetype_is_unknown(val: &DeviceType) -> bool {
 // no remote tabs in an existing DB is also a normal situation)
 pub struct TabsStorage {
     local_tabs: RefCell<Option<Vec<RemoteTab>>>,
+    recently_closed: RefCell<Option<Vec<RecentlyClosedTab>>>,
     db_path: PathBuf,
     db_connection: Option<Connection>,
 }
@@ -88,6 +138,7 @@ impl TabsStorage {
     pub fn new(db_path: impl AsRef<Path>) -> Self {
         Self {
             local_tabs: RefCell::default(),
+            recently_closed: RefCell::default(),
             db_path: db_path.as_ref().to_path_buf(),
             db_connection: None,
         }
@@ -145,13 +196,80 @@ impl TabsStorage {
     }
 
     pub fn update_local_state(&mut self, local_state: Vec<RemoteTab>) {
+        if let Err(e) = self.persist_if_exists(schema::LOCAL_TABS_KEY, &local_state) {
+            error_support::report_error!("tabs-write-local", "Failed to persist local tabs: {}", e);
+        }
         self.local_tabs.borrow_mut().replace(local_state);
     }
 
+    pub fn update_recently_closed(&mut self, recently_closed: Vec<RecentlyClosedTab>) {
+        if let Err(e) = self.persist_if_exists(schema::RECENTLY_CLOSED_KEY, &recently_closed) {
+            error_support::report_error!(
+                "tabs-write-local",
+                "Failed to persist recently closed tabs: {}",
+                e
+            );
+        }
+        self.recently_closed.borrow_mut().replace(recently_closed);
+    }
+
+    // Only users who sync have a database, and we don't create one just to
+    // persist the local state.
+    fn persist_if_exists<T: serde::Serialize>(&mut self, key: &str, value: &T) -> Result<()> {
+        let json = serde_json::to_string(value)?;
+        if let Some(db) = self.open_if_exists()? {
+            db.execute_cached(
+                "REPLACE INTO moz_meta (key, value) VALUES (:key, :value)",
+                rusqlite::named_params! {
+                    ":key": key,
+                    ":value": json,
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    fn load_persisted<T: serde::de::DeserializeOwned>(&mut self, key: &str) -> Option<T> {
+        let persisted = self.get_meta::<String>(key).and_then(|json| {
+            json.map(|json| serde_json::from_str::<T>(&json))
+                .transpose()
+                .map_err(Error::from)
+        });
+        match persisted {
+            Ok(value) => value,
+            Err(e) => {
+                error_support::report_error!(
+                    "tabs-read-local",
+                    "Failed to read persisted {}: {}",
+                    key,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    // If the app hasn't set the local tabs since we started, use the ones it
+    // set last time.
+    fn load_local_tabs(&mut self) {
+        if self.local_tabs.borrow().is_none() {
+            let local_tabs = self.load_persisted(schema::LOCAL_TABS_KEY);
+            *self.local_tabs.borrow_mut() = local_tabs;
+        }
+    }
+
+    fn load_recently_closed(&mut self) {
+        if self.recently_closed.borrow().is_none() {
+            let recently_closed = self.load_persisted(schema::RECENTLY_CLOSED_KEY);
+            *self.recently_closed.borrow_mut() = recently_closed;
+        }
+    }
+
     // We try our best to fit as many tabs in a payload as possible, this includes
     // limiting the url history entries, title character count and finally drop enough tabs
     // until we have small enough payload that the server will accept
-    pub fn prepare_local_tabs_for_upload(&self) -> Option<Vec<RemoteTab>> {
+    pub fn prepare_local_tabs_for_upload(&mut self) -> Option<Vec<RemoteTab>> {
+        self.load_local_tabs();
         if let Some(local_tabs) = self.local_tabs.borrow().as_ref() {
             let mut sanitized_tabs: Vec<RemoteTab> = local_tabs
                 .iter()
@@ -179,12 +297,88 @@ impl TabsStorage {
             // Sort the tabs so when we trim tabs it's the oldest tabs
             sanitized_tabs.sort_by(|a, b| b.last_used.cmp(&a.last_used));
             // If trimming the tab length failed for some reason, just return the untrimmed tabs
-            trim_tabs_length(&mut sanitized_tabs, MAX_PAYLOAD_SIZE);
+            trim_tabs_length(
+                &mut sanitized_tabs,
+                MAX_PAYLOAD_SIZE
+                    - MAX_RECENTLY_CLOSED_PAYLOAD_SIZE
+                    - MAX_CLOSE_TAB_REQUESTS_PAYLOAD_SIZE,
+            );
             return Some(sanitized_tabs);
         }
         None
     }
 
+    // The most recently closed tabs we'll include in our record, with the same
+    // sanitizing as the open tabs.
+    pub fn prepare_recently_closed_for_upload(&mut self) -> Vec<RecentlyClosedTab> {
+        self.load_recently_closed();
+        let mut recently_closed: Vec<RecentlyClosedTab> =
+            match self.recently_closed.borrow().as_ref() {
+                None => return vec![],
+                Some(recently_closed) => recently_closed
+                    .iter()
+                    .filter(|tab| is_url_syncable(&tab.url))
+                    .cloned()
+                    .map(|mut tab| {
+                        tab.title = slice_up_to(tab.title, MAX_TITLE_CHAR_LENGTH);
+                        tab
+                    })
+                    .collect(),
+            };
+        recently_closed.sort_by(|a, b| b.time_closed.cmp(&a.time_closed));
+        recently_closed.truncate(MAX_RECENTLY_CLOSED_TABS);
+        while !recently_closed.is_empty()
+            && serde_json::to_string(&recently_closed)
+                .unwrap_or_default()
+                .len()
+                > MAX_RECENTLY_CLOSED_PAYLOAD_SIZE
+        {
+            recently_closed.pop();
+        }
+        recently_closed
+    }
+
+    /// The tabs recently closed on the client with this ID (as found in
+    /// `ClientRemoteTabs`), most recently closed first.
+    pub fn get_recently_closed(&mut self, client_id: &str) -> Result<Vec<RecentlyClosedTab>> {
+        // As for close tab requests, the app uses FxA device IDs but records
+        // are stored by their own ID.
+        let remote_clients: HashMap<String, RemoteClient> =
+            match self.get_meta::<String>(schema::REMOTE_CLIENTS_KEY)? {
+                None => HashMap::default(),
+                Some(json) => serde_json::from_str(&json)?,
+            };
+        let record_id = remote_clients
+            .iter()
+            .find(|(_, client)| client.fxa_device_id.as_deref() == Some(client_id))
+            .map(|(id, _)| id.as_str())
+            .unwrap_or(client_id);
+        let conn = match self.open_if_exists()? {
+            None => return Ok(vec![]),
+            Some(conn) => conn,
+        };
+        let record: Option<TabsRecord> = conn
+            .try_query_one::<String, _>(
+                "SELECT record FROM tabs WHERE guid = :guid",
+                rusqlite::named_params! { ":guid": record_id },
+                true,
+            )?
+            .map(|json| serde_json::from_str(&json))
+            .transpose()?;
+        let mut recently_closed: Vec<RecentlyClosedTab> = record
+            .map(|record| record.recently_closed)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|tab| RecentlyClosedTab {
+                title: tab.title,
+                url: tab.url,
+                time_closed: tab.closed.checked_mul(1000).unwrap_or_default(),
+            })
+            .collect();
+        recently_closed.sort_by(|a, b| b.time_closed.cmp(&a.time_closed));
+        Ok(recently_closed)
+    }
+
     pub fn get_remote_tabs(&mut self) -> Option<Vec<ClientRemoteTabs>> {
         let conn = match self.open_if_exists() {
             Err(e) => {
@@ -261,6 +455,133 @@ impl TabsStorage {
         Some(crts)
     }
 
+    /// Queue requests for the device with the given ID to close its tabs with
+    /// these URLs. The requests are sent with our tabs record until the device
+    /// uploads a record without the tabs, or they expire.
+    pub fn add_close_tab_requests(&mut self, device_id: &str, urls: &[String]) -> Result<()> {
+        let now = now_millis();
+        let conn = self.open_or_create()?;
+        let tx = conn.unchecked_transaction()?;
+        for url in urls {
+            tx.execute_cached(
+                "INSERT OR REPLACE INTO outgoing_close_tab_requests (device_id, url, time_requested)
+                 VALUES (:device_id, :url, :time_requested)",
+                rusqlite::named_params! {
+                    ":device_id": device_id,
+                    ":url": url,
+                    ":time_requested": now,
+                },
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Requests from other devices to close our tabs which haven't been
+    /// acknowledged, oldest first.
+    pub fn get_pending_close_tab_requests(&mut self) -> Result<Vec<PendingCloseTabRequest>> {
+        let conn = match self.open_if_exists()? {
+            None => return Ok(vec![]),
+            Some(conn) => conn,
+        };
+        conn.query_rows_and_then_cached(
+            "SELECT url, MAX(time_requested) FROM incoming_close_tab_requests
+             WHERE time_acknowledged IS NULL
+             GROUP BY url
+             ORDER BY 2",
+            [],
+            |row| -> Result<_> {
+                Ok(PendingCloseTabRequest {
+                    url: row.get(0)?,
+                    time_requested: row.get(1)?,
+                })
+            },
+        )
+    }
+
+    /// Acknowledge all pending requests to close tabs with these URLs, so we
+    /// don't report them again.
+    pub fn acknowledge_close_tab_requests(&mut self, urls: &[String]) -> Result<()> {
+        let now = now_millis();
+        if let Some(conn) = self.open_if_exists()? {
+            let tx = conn.unchecked_transaction()?;
+            for url in urls {
+                tx.execute_cached(
+                    "UPDATE incoming_close_tab_requests SET time_acknowledged = :now
+                     WHERE url = :url AND time_acknowledged IS NULL",
+                    rusqlite::named_params! {
+                        ":now": now,
+                        ":url": url,
+                    },
+                )?;
+            }
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Remote tabs whose title or current URL contains `query` (ignoring
+    /// case), most recently used first, skipping tabs with a URL that's open
+    /// locally. At most `per_client_limit` tabs are returned for each client,
+    /// and `total_limit` in all.
+    pub fn search_remote_tabs(
+        &mut self,
+        query: &str,
+        per_client_limit: usize,
+        total_limit: usize,
+    ) -> Vec<RemoteTabSearchResult> {
+        self.load_local_tabs();
+        let local_urls: HashSet<String> = self
+            .local_tabs
+            .borrow()
+            .iter()
+            .flatten()
+            .filter_map(|tab| tab.url_history.first().cloned())
+            .collect();
+        let query = query.to_lowercase();
+        let mut results: Vec<RemoteTabSearchResult> = self
+            .get_remote_tabs()
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|crt| {
+                let ClientRemoteTabs {
+                    client_id,
+                    client_name,
+                    device_type,
+                    remote_tabs,
+                    ..
+                } = crt;
+                remote_tabs
+                    .into_iter()
+                    .map(move |tab| RemoteTabSearchResult {
+                        client_id: client_id.clone(),
+                        client_name: client_name.clone(),
+                        device_type,
+                        tab,
+                    })
+            })
+            .filter(|result| match result.tab.url_history.first() {
+                None => false,
+                Some(url) => {
+                    !local_urls.contains(url)
+                        && (url.to_lowercase().contains(&query)
+                            || result.tab.title.to_lowercase().contains(&query))
+                }
+            })
+            .collect();
+        results.sort_by(|a, b| b.tab.last_used.cmp(&a.tab.last_used));
+        let mut per_client_counts: HashMap<String, usize> = HashMap::new();
+        results.retain(|result| {
+            let count = per_client_counts
+                .entry(result.client_id.clone())
+                .or_default();
+            *count += 1;
+            *count <= per_client_limit
+        });
+        results.truncate(total_limit);
+        results
+    }
+
     // Keep DB from growing infinitely since we only ask for records since our last sync
     // and may or may not know about the client it's associated with -- but we could at some point
     // and should start returning those tabs immediately. If that client hasn't been seen in 3 weeks,
@@ -329,6 +650,149 @@ impl TabsStorage {
         Ok(())
     }
 
+    // Returns the requests to include in our outgoing record, first dropping
+    // the ones which expired or which the other device has acted on - ie, it
+    // uploaded a record without the tab after we made the request. If there
+    // are too many to fit in the record, the oldest ones wait until there's
+    // room.
+    pub(crate) fn prepare_close_tab_requests_for_upload(
+        &mut self,
+    ) -> Result<Vec<TabsRecordCloseTabRequest>> {
+        // The app uses FxA device IDs, but requests are addressed to the IDs
+        // of tabs records.
+        let remote_clients: HashMap<String, RemoteClient> =
+            match self.get_meta::<String>(schema::REMOTE_CLIENTS_KEY)? {
+                None => HashMap::default(),
+                Some(json) => serde_json::from_str(&json)?,
+            };
+        let record_ids: HashMap<&str, &str> = remote_clients
+            .iter()
+            .filter_map(|(id, client)| Some((client.fxa_device_id.as_deref()?, id.as_str())))
+            .collect();
+        let conn = match self.open_if_exists()? {
+            None => return Ok(vec![]),
+            Some(conn) => conn,
+        };
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_cached(
+            "DELETE FROM outgoing_close_tab_requests WHERE time_requested < :cutoff",
+            rusqlite::named_params! {
+                ":cutoff": now_millis() - CLOSE_TAB_REQUEST_TTL_MS,
+            },
+        )?;
+        let requests: Vec<(String, String, i64)> = tx.query_rows_and_then_cached(
+            "SELECT device_id, url, time_requested FROM outgoing_close_tab_requests
+             ORDER BY time_requested DESC",
+            [],
+            |row| -> Result<_> { Ok((row.get(0)?, row.get(1)?, row.get(2)?)) },
+        )?;
+        let mut targets: HashMap<String, Option<(TabsRecord, i64)>> = HashMap::new();
+        let mut to_upload = Vec::with_capacity(requests.len());
+        for (device_id, url, time_requested) in requests {
+            let client_id = record_ids
+                .get(device_id.as_str())
+                .copied()
+                .unwrap_or(device_id.as_str())
+                .to_string();
+            if !targets.contains_key(&client_id) {
+                let target = tx.try_query_row(
+                    "SELECT record, last_modified FROM tabs WHERE guid = :guid",
+                    &[(":guid", &client_id)],
+                    |row| -> Result<_> {
+                        Ok((
+                            serde_json::from_str::<TabsRecord>(&row.get::<_, String>(0)?)?,
+                            row.get::<_, i64>(1)?,
+                        ))
+                    },
+                    true,
+                )?;
+                targets.insert(client_id.clone(), target);
+            }
+            let closed = match &targets[&client_id] {
+                Some((record, last_modified)) => {
+                    *last_modified > time_requested
+                        && !record
+                            .tabs
+                            .iter()
+                            .any(|tab| tab.url_history.first() == Some(&url))
+                }
+                None => false,
+            };
+            if closed {
+                tx.execute_cached(
+                    "DELETE FROM outgoing_close_tab_requests
+                     WHERE device_id = :device_id AND url = :url",
+                    rusqlite::named_params! {
+                        ":device_id": device_id,
+                        ":url": url,
+                    },
+                )?;
+            } else {
+                to_upload.push(TabsRecordCloseTabRequest {
+                    client_id,
+                    url,
+                    requested: time_requested / 1000,
+                });
+            }
+        }
+        tx.commit()?;
+        to_upload.truncate(MAX_CLOSE_TAB_REQUESTS);
+        while !to_upload.is_empty()
+            && serde_json::to_string(&to_upload).unwrap_or_default().len()
+                > MAX_CLOSE_TAB_REQUESTS_PAYLOAD_SIZE
+        {
+            to_upload.pop();
+        }
+        Ok(to_upload)
+    }
+
+    // Remember the requests in incoming records for us to close tabs.
+    pub(crate) fn record_incoming_close_tab_requests(
+        &mut self,
+        local_id: &str,
+        records: &[(TabsRecord, ServerTimestamp)],
+    ) -> Result<()> {
+        let cutoff = now_millis() - CLOSE_TAB_REQUEST_TTL_MS;
+        let conn = self.open_or_create()?;
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_cached(
+            "DELETE FROM incoming_close_tab_requests WHERE time_requested < :cutoff",
+            rusqlite::named_params! { ":cutoff": cutoff },
+        )?;
+        for (record, _) in records {
+            for request in &record.close_tab_requests {
+                let time_requested = request.requested.saturating_mul(1000);
+                if request.client_id != local_id || time_requested < cutoff {
+                    continue;
+                }
+                // Clients send their requests until we close the tab, so we
+                // ignore the ones we've already seen.
+                tx.execute_cached(
+                    "INSERT OR IGNORE INTO incoming_close_tab_requests
+                        (from_client_id, url, time_requested)
+                     VALUES (:from_client_id, :url, :time_requested)",
+                    rusqlite::named_params! {
+                        ":from_client_id": record.id,
+                        ":url": request.url,
+                        ":time_requested": time_requested,
+                    },
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub(crate) fn wipe_close_tab_requests(&mut self) -> Result<()> {
+        if let Some(db) = self.open_if_exists()? {
+            db.execute_batch(
+                "DELETE FROM outgoing_close_tab_requests;
+                 DELETE FROM incoming_close_tab_requests;",
+            )?;
+        }
+        Ok(())
+    }
+
     pub(crate) fn wipe_remote_tabs(&mut self) -> Result<()> {
         if let Some(db) = self.open_if_exists()? {
             db.execute_batch("DELETE FROM tabs")?;
@@ -336,8 +800,33 @@ impl TabsStorage {
         Ok(())
     }
 
-    pub(crate) fn wipe_local_tabs(&self) {
+    pub(crate) fn wipe_local_tabs(&mut self) -> Result<()> {
         self.local_tabs.replace(None);
+        self.recently_closed.replace(None);
+        self.delete_meta(schema::LOCAL_TABS_KEY)?;
+        self.delete_meta(schema::RECENTLY_CLOSED_KEY)?;
+        self.forget_last_upload()
+    }
+
+    // Whether we should upload a record with a payload which has this hash -
+    // we skip it if it's what we last uploaded, unless that was a while ago.
+    pub(crate) fn should_upload(&mut self, payload_hash: &str) -> Result<bool> {
+        let last_hash = self.get_meta::<String>(schema::LAST_UPLOAD_HASH_KEY)?;
+        let last_time = self
+            .get_meta::<i64>(schema::LAST_UPLOAD_TIME_KEY)?
+            .unwrap_or_default();
+        Ok(last_hash.as_deref() != Some(payload_hash)
+            || now_millis() - last_time >= UNCHANGED_UPLOAD_INTERVAL_MS)
+    }
+
+    pub(crate) fn set_last_upload(&mut self, payload_hash: &str) -> Result<()> {
+        self.put_meta(schema::LAST_UPLOAD_HASH_KEY, &payload_hash)?;
+        self.put_meta(schema::LAST_UPLOAD_TIME_KEY, &now_millis())
+    }
+
+    pub(crate) fn forget_last_upload(&mut self) -> Result<()> {
+        self.delete_meta(schema::LAST_UPLOAD_HASH_KEY)?;
+        self.delete_meta(schema::LAST_UPLOAD_TIME_KEY)
     }
 
     pub(crate) fn put_meta(&mut self, key: &str, value: &dyn ToSql) -> Result<()> {
@@ -372,6 +861,13 @@ impl TabsStorage {
     }
 }
 
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
 // Trim the amount of tabs in a list to fit the specified memory size
 fn trim_tabs_length(tabs: &mut Vec<RemoteTab>, payload_size_max_bytes: usize) {
     // Ported from https://searchfox.org/mozilla-central/rev/84fb1c4511312a0b9187f647d90059e3a6dd27f8/services/sync/modules/util.sys.mjs#422
@@ -499,6 +995,7 @@ mod tests {
                 url_history: vec!["about:blank".to_owned(), "https://foo.bar".to_owned()],
                 icon: None,
                 last_used: 0,
+                ..Default::default()
             },
             RemoteTab {
                 title: "".to_owned(),
@@ -514,6 +1011,7 @@ mod tests {
                 ],
                 icon: None,
                 last_used: 0,
+                ..Default::default()
             },
             RemoteTab {
                 title: "".to_owned(),
@@ -528,12 +1026,14 @@ mod tests {
                 ],
                 icon: None,
                 last_used: 0,
+                ..Default::default()
             },
             RemoteTab {
                 title: "".to_owned(),
                 url_history: vec![],
                 icon: None,
                 last_used: 0,
+                ..Default::default()
             },
         ]);
         assert_eq!(
@@ -544,6 +1044,7 @@ mod tests {
                     url_history: vec!["https://foo.bar".to_owned()],
                     icon: None,
                     last_used: 0,
+                    ..Default::default()
                 },
                 RemoteTab {
                     title: "".to_owned(),
@@ -556,19 +1057,21 @@ mod tests {
                     ],
                     icon: None,
                     last_used: 0,
+                    ..Default::default()
                 },
             ])
         );
     }
     #[test]
     fn test_trimming_tab_title() {
-        let mut storage = TabsStorage::new_with_mem_path("test_prepare_local_tabs_for_upload");
+        let mut storage = TabsStorage::new_with_mem_path("test_trimming_tab_title");
         assert_eq!(storage.prepare_local_tabs_for_upload(), None);
         storage.update_local_state(vec![RemoteTab {
             title: "a".repeat(MAX_TITLE_CHAR_LENGTH + 10), // Fill a string more than max
             url_history: vec!["https://foo.bar".to_owned()],
             icon: None,
             last_used: 0,
+            ..Default::default()
         }]);
         let ellipsis_char = '\u{2026}';
         let mut truncated_title = "a".repeat(MAX_TITLE_CHAR_LENGTH - ellipsis_char.len_utf8());
@@ -582,13 +1085,14 @@ mod tests {
                     url_history: vec!["https://foo.bar".to_owned()],
                     icon: None,
                     last_used: 0,
+                    ..Default::default()
                 },
             ])
         );
     }
     #[test]
     fn test_utf8_safe_title_trim() {
-        let mut storage = TabsStorage::new_with_mem_path("test_prepare_local_tabs_for_upload");
+        let mut storage = TabsStorage::new_with_mem_path("test_utf8_safe_title_trim");
         assert_eq!(storage.prepare_local_tabs_for_upload(), None);
         storage.update_local_state(vec![
             RemoteTab {
@@ -596,12 +1100,14 @@ mod tests {
                 url_history: vec!["https://foo.bar".to_owned()],
                 icon: None,
                 last_used: 0,
+                ..Default::default()
             },
             RemoteTab {
                 title: "を".repeat(MAX_TITLE_CHAR_LENGTH + 5), // Fill a string more than max
                 url_history: vec!["https://foo_jp.bar".to_owned()],
                 icon: None,
                 last_used: 0,
+                ..Default::default()
             },
         ]);
         let ellipsis_char = '\u{2026}';
@@ -620,12 +1126,14 @@ mod tests {
                     url_history: vec!["https://foo.bar".to_owned()],
                     icon: None,
                     last_used: 0,
+                    ..Default::default()
                 },
                 RemoteTab {
                     title: truncated_jp_title, // title was trimmed to only max char length
                     url_history: vec!["https://foo_jp.bar".to_owned()],
                     icon: None,
                     last_used: 0,
+                    ..Default::default()
                 },
             ]
         );
@@ -635,7 +1143,7 @@ mod tests {
     }
     #[test]
     fn test_trim_tabs_length() {
-        let mut storage = TabsStorage::new_with_mem_path("test_prepare_local_tabs_for_upload");
+        let mut storage = TabsStorage::new_with_mem_path("test_trim_tabs_length");
         assert_eq!(storage.prepare_local_tabs_for_upload(), None);
         let mut too_many_tabs: Vec<RemoteTab> = Vec::new();
         for n in 1..5000 {
@@ -645,6 +1153,7 @@ mod tests {
                 url_history: vec![format!("https://foo{}.bar", n)],
                 icon: None,
                 last_used: 0,
+                ..Default::default()
             });
         }
         let tabs_mem_size = compute_serialized_size(&too_many_tabs);
@@ -681,7 +1190,11 @@ mod tests {
                         url_history: vec!["https://mozilla.org/".to_string()],
                         icon: Some("https://mozilla.org/icon".to_string()),
                         last_used: 1643764207000,
+                        ..Default::default()
                     }],
+                    close_tab_requests: vec![],
+                    recently_closed: vec![],
+                    unknown_fields: Default::default(),
                 },
                 last_modified: 1643764207000,
             },
@@ -695,7 +1208,11 @@ mod tests {
                         url_history: vec!["https://mozilla.org/".to_string()],
                         icon: Some("https://mozilla.org/icon".to_string()),
                         last_used: 1643764207000,
+                        ..Default::default()
                     }],
+                    close_tab_requests: vec![],
+                    recently_closed: vec![],
+                    unknown_fields: Default::default(),
                 },
                 last_modified: 1443764207000, // old
             },
@@ -724,4 +1241,311 @@ mod tests {
         // Assert the correct record is still being returned
         assert_eq!(remote_tabs[0].client_id, "device-1");
     }
+
+    #[test]
+    fn test_close_tab_requests() {
+        let mut storage = TabsStorage::new_with_mem_path("test_close_tab_requests");
+        let remote_clients: HashMap<String, RemoteClient> = [(
+            "device-1".to_string(),
+            RemoteClient {
+                fxa_device_id: Some("fxa-device-1".to_string()),
+                device_name: "Device #1".to_string(),
+                device_type: DeviceType::Desktop,
+            },
+        )]
+        .into_iter()
+        .collect();
+        storage
+            .put_meta(
+                schema::REMOTE_CLIENTS_KEY,
+                &serde_json::to_string(&remote_clients).unwrap(),
+            )
+            .unwrap();
+        storage
+            .add_close_tab_requests(
+                "fxa-device-1",
+                &[
+                    "https://mozilla.org/".to_string(),
+                    "https://example.com/".to_string(),
+                ],
+            )
+            .unwrap();
+        storage
+            .add_close_tab_requests("unknown-device", &["https://mozilla.org/".to_string()])
+            .unwrap();
+
+        let mut requests = storage.prepare_close_tab_requests_for_upload().unwrap();
+        requests.sort_by(|a, b| (&a.client_id, &a.url).cmp(&(&b.client_id, &b.url)));
+        let targets: Vec<(&str, &str)> = requests
+            .iter()
+            .map(|r| (r.client_id.as_str(), r.url.as_str()))
+            .collect();
+        // Known devices get the ID of their tabs record.
+        assert_eq!(
+            targets,
+            vec![
+                ("device-1", "https://example.com/"),
+                ("device-1", "https://mozilla.org/"),
+                ("unknown-device", "https://mozilla.org/"),
+            ]
+        );
+
+        // The device uploads a record which still has one of the tabs open.
+        storage
+            .replace_remote_tabs(vec![(
+                TabsRecord {
+                    id: "device-1".to_string(),
+                    client_name: "Device #1".to_string(),
+                    tabs: vec![TabsRecordTab {
+                        title: "the title".to_string(),
+                        url_history: vec!["https://mozilla.org/".to_string()],
+                        icon: None,
+                        last_used: 0,
+                        ..Default::default()
+                    }],
+                    close_tab_requests: vec![],
+                    recently_closed: vec![],
+                    unknown_fields: Default::default(),
+                },
+                ServerTimestamp::from_millis(now_millis() + 1000),
+            )])
+            .unwrap();
+        let requests = storage.prepare_close_tab_requests_for_upload().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests.iter().any(|r| r.url == "https://example.com/"));
+
+        // Expired requests are dropped.
+        storage
+            .open_if_exists()
+            .unwrap()
+            .unwrap()
+            .execute(
+                "UPDATE outgoing_close_tab_requests SET time_requested = 0
+                 WHERE device_id = 'unknown-device'",
+                [],
+            )
+            .unwrap();
+        let requests = storage.prepare_close_tab_requests_for_upload().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].client_id, "device-1");
+
+        storage.wipe_close_tab_requests().unwrap();
+        assert!(storage
+            .prepare_close_tab_requests_for_upload()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_close_tab_requests_limits() {
+        let mut storage = TabsStorage::new_with_mem_path("test_close_tab_requests_limits");
+        let urls: Vec<String> = (0..MAX_CLOSE_TAB_REQUESTS * 2)
+            .map(|i| format!("https://example.com/{}/{}", i, "a".repeat(500)))
+            .collect();
+        storage
+            .add_close_tab_requests("fxa-device-1", &urls)
+            .unwrap();
+        // Make the requests for the later URLs more recent.
+        storage
+            .open_if_exists()
+            .unwrap()
+            .unwrap()
+            .execute(
+                "UPDATE outgoing_close_tab_requests SET time_requested = time_requested + rowid",
+                [],
+            )
+            .unwrap();
+
+        let requests = storage.prepare_close_tab_requests_for_upload().unwrap();
+        assert!(!requests.is_empty());
+        assert!(requests.len() <= MAX_CLOSE_TAB_REQUESTS);
+        assert!(
+            serde_json::to_string(&requests).unwrap().len() <= MAX_CLOSE_TAB_REQUESTS_PAYLOAD_SIZE
+        );
+        // The oldest requests are the ones left out.
+        assert_eq!(requests[0].url, urls[urls.len() - 1]);
+        let expected: Vec<&String> = urls.iter().rev().take(requests.len()).collect();
+        let actual: Vec<&String> = requests.iter().map(|r| &r.url).collect();
+        assert_eq!(actual, expected);
+
+        // The requests we left out are still pending.
+        let count: usize = storage
+            .open_if_exists()
+            .unwrap()
+            .unwrap()
+            .query_row(
+                "SELECT COUNT(*) FROM outgoing_close_tab_requests",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, urls.len());
+
+        // Short requests are limited by number instead.
+        storage.wipe_close_tab_requests().unwrap();
+        let urls: Vec<String> = (0..MAX_CLOSE_TAB_REQUESTS * 2)
+            .map(|i| format!("https://example.com/{}", i))
+            .collect();
+        storage
+            .add_close_tab_requests("fxa-device-1", &urls)
+            .unwrap();
+        let requests = storage.prepare_close_tab_requests_for_upload().unwrap();
+        assert_eq!(requests.len(), MAX_CLOSE_TAB_REQUESTS);
+    }
+
+    #[test]
+    fn test_search_remote_tabs() {
+        let mut storage = TabsStorage::new_with_mem_path("test_search_remote_tabs");
+        let tab = |title: &str, url: &str, last_used: i64| TabsRecordTab {
+            title: title.to_string(),
+            url_history: vec![url.to_string()],
+            icon: None,
+            last_used,
+            ..Default::default()
+        };
+        let record = |id: &str, tabs: Vec<TabsRecordTab>| {
+            (
+                TabsRecord {
+                    id: id.to_string(),
+                    client_name: id.to_string(),
+                    tabs,
+                    close_tab_requests: vec![],
+                    recently_closed: vec![],
+                    unknown_fields: Default::default(),
+                },
+                ServerTimestamp::from_millis(1643764207000),
+            )
+        };
+        storage
+            .replace_remote_tabs(vec![
+                record(
+                    "device-1",
+                    vec![
+                        tab("Mozilla", "https://mozilla.org/", 5),
+                        tab("Firefox", "https://mozilla.org/firefox", 3),
+                        tab("MDN", "https://developer.mozilla.org/", 1),
+                        tab("Example", "https://example.com/", 4),
+                    ],
+                ),
+                record(
+                    "device-2",
+                    vec![
+                        tab("Mozilla Foundation", "https://foundation.mozilla.org/", 2),
+                        tab("Already open", "https://open.mozilla.org/", 6),
+                    ],
+                ),
+            ])
+            .unwrap();
+        storage.update_local_state(vec![RemoteTab {
+            title: "Already open here".to_string(),
+            url_history: vec!["https://open.mozilla.org/".to_string()],
+            ..Default::default()
+        }]);
+
+        let titles = |results: Vec<RemoteTabSearchResult>| -> Vec<String> {
+            results.into_iter().map(|r| r.tab.title).collect()
+        };
+        assert_eq!(
+            titles(storage.search_remote_tabs("MOZILLA", 10, 10)),
+            vec!["Mozilla", "Firefox", "Mozilla Foundation", "MDN"]
+        );
+        // Titles match too.
+        assert_eq!(
+            titles(storage.search_remote_tabs("fire", 10, 10)),
+            vec!["Firefox"]
+        );
+        assert_eq!(
+            titles(storage.search_remote_tabs("mozilla", 2, 10)),
+            vec!["Mozilla", "Firefox", "Mozilla Foundation"]
+        );
+        assert_eq!(
+            titles(storage.search_remote_tabs("mozilla", 10, 2)),
+            vec!["Mozilla", "Firefox"]
+        );
+        let results = storage.search_remote_tabs("foundation", 10, 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].client_id, "device-2");
+        assert_eq!(results[0].client_name, "device-2");
+        assert!(storage.search_remote_tabs("nothing", 10, 10).is_empty());
+    }
+
+    #[test]
+    fn test_persisted_local_tabs() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_name = dir.path().join("test_persisted_local_tabs.db");
+        let tabs = vec![RemoteTab {
+            title: "the title".to_string(),
+            url_history: vec!["https://mozilla.org/".to_string()],
+            ..Default::default()
+        }];
+
+        // Without a database, the tabs aren't persisted.
+        let mut storage = TabsStorage::new(db_name.clone());
+        storage.update_local_state(tabs.clone());
+        assert!(storage.open_if_exists().unwrap().is_none());
+
+        storage.open_or_create().unwrap();
+        storage.update_local_state(tabs.clone());
+        assert_eq!(storage.prepare_local_tabs_for_upload(), Some(tabs.clone()));
+
+        // A new storage, like after a restart, still has them.
+        let mut storage = TabsStorage::new(db_name.clone());
+        assert_eq!(storage.prepare_local_tabs_for_upload(), Some(tabs));
+
+        storage.wipe_local_tabs().unwrap();
+        let mut storage = TabsStorage::new(db_name);
+        assert_eq!(storage.prepare_local_tabs_for_upload(), None);
+    }
+
+    #[test]
+    fn test_should_upload() {
+        let mut storage = TabsStorage::new_with_mem_path("test_should_upload");
+        assert!(storage.should_upload("hash").unwrap());
+        storage.set_last_upload("hash").unwrap();
+        assert!(!storage.should_upload("hash").unwrap());
+        assert!(storage.should_upload("other hash").unwrap());
+
+        // We upload unchanged records once they're a day old.
+        storage
+            .put_meta(
+                schema::LAST_UPLOAD_TIME_KEY,
+                &(now_millis() - UNCHANGED_UPLOAD_INTERVAL_MS),
+            )
+            .unwrap();
+        assert!(storage.should_upload("hash").unwrap());
+
+        storage.set_last_upload("hash").unwrap();
+        storage.forget_last_upload().unwrap();
+        assert!(storage.should_upload("hash").unwrap());
+    }
+
+    #[test]
+    fn test_prepare_recently_closed_for_upload() {
+        let mut storage = TabsStorage::new_with_mem_path("test_prepare_recently_closed_for_upload");
+        assert!(storage.prepare_recently_closed_for_upload().is_empty());
+        let mut recently_closed: Vec<RecentlyClosedTab> = (0..MAX_RECENTLY_CLOSED_TABS as i64 + 5)
+            .map(|i| RecentlyClosedTab {
+                title: "a".repeat(MAX_TITLE_CHAR_LENGTH + 10),
+                url: format!("https://example.com/{}", i),
+                time_closed: i,
+            })
+            .collect();
+        recently_closed.push(RecentlyClosedTab {
+            title: "unsyncable".to_string(),
+            url: "about:blank".to_string(),
+            time_closed: 1000,
+        });
+        storage.update_recently_closed(recently_closed);
+        let to_upload = storage.prepare_recently_closed_for_upload();
+        assert_eq!(to_upload.len(), MAX_RECENTLY_CLOSED_TABS);
+        // The most recently closed are kept, and the titles are trimmed.
+        assert_eq!(
+            to_upload[0].url,
+            format!("https://example.com/{}", MAX_RECENTLY_CLOSED_TABS + 4)
+        );
+        assert_eq!(to_upload.last().unwrap().time_closed, 5);
+        assert!(to_upload
+            .iter()
+            .all(|tab| tab.title.len() <= MAX_TITLE_CHAR_LENGTH));
+    }
 }
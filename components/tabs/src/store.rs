@@ -2,7 +2,12 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::storage::{ClientRemoteTabs, RemoteTab, TabsStorage};
+use crate::error::*;
+use crate::storage::{
+    ClientRemoteTabs, PendingCloseTabRequest, RecentlyClosedTab, RemoteTab, RemoteTabSearchResult,
+    TabsStorage,
+};
+use error_support::handle_error;
 use std::path::Path;
 use std::sync::Mutex;
 
@@ -27,6 +32,22 @@ impl TabsStore {
         self.storage.lock().unwrap().update_local_state(local_state);
     }
 
+    /// The tabs the user recently closed on this device, which are synced
+    /// with our open tabs. Only the most recently closed are kept.
+    pub fn set_recently_closed_tabs(&self, recently_closed: Vec<RecentlyClosedTab>) {
+        self.storage
+            .lock()
+            .unwrap()
+            .update_recently_closed(recently_closed);
+    }
+
+    /// The tabs recently closed on the device with this `client_id` (as found
+    /// in `ClientRemoteTabs`), most recently closed first.
+    #[handle_error(Error)]
+    pub fn get_recently_closed(&self, client_id: String) -> ApiResult<Vec<RecentlyClosedTab>> {
+        self.storage.lock().unwrap().get_recently_closed(&client_id)
+    }
+
     // like remote_tabs, but serves the uniffi layer
     pub fn get_all(&self) -> Vec<ClientRemoteTabs> {
         match self.remote_tabs() {
@@ -38,4 +59,49 @@ impl TabsStore {
     pub fn remote_tabs(&self) -> Option<Vec<ClientRemoteTabs>> {
         self.storage.lock().unwrap().get_remote_tabs()
     }
+
+    /// Search the tabs open on other devices, skipping ones already open
+    /// locally, for suggesting "switch to tab" results.
+    pub fn search_remote_tabs(
+        &self,
+        query: String,
+        per_client_limit: u32,
+        total_limit: u32,
+    ) -> Vec<RemoteTabSearchResult> {
+        self.storage.lock().unwrap().search_remote_tabs(
+            &query,
+            per_client_limit as usize,
+            total_limit as usize,
+        )
+    }
+
+    /// Ask the device with this `client_id` (as found in `ClientRemoteTabs`)
+    /// to close its tabs with these URLs. The requests are sent with our tabs
+    /// on future syncs, until the device has closed the tabs or a week has
+    /// passed.
+    #[handle_error(Error)]
+    pub fn close_remote_tabs(&self, client_id: String, urls: Vec<String>) -> ApiResult<()> {
+        self.storage
+            .lock()
+            .unwrap()
+            .add_close_tab_requests(&client_id, &urls)
+    }
+
+    /// Requests from other devices to close our tabs. The app should close the
+    /// tabs and then call `acknowledge_close_tab_requests()`.
+    #[handle_error(Error)]
+    pub fn get_pending_close_tab_requests(&self) -> ApiResult<Vec<PendingCloseTabRequest>> {
+        self.storage
+            .lock()
+            .unwrap()
+            .get_pending_close_tab_requests()
+    }
+
+    #[handle_error(Error)]
+    pub fn acknowledge_close_tab_requests(&self, urls: Vec<String>) -> ApiResult<()> {
+        self.storage
+            .lock()
+            .unwrap()
+            .acknowledge_close_tab_requests(&urls)
+    }
 }
@@ -3,10 +3,13 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::schema;
-use crate::storage::{ClientRemoteTabs, RemoteTab, TABS_CLIENT_TTL};
+use crate::storage::{
+    ClientRemoteTabs, RecentlyClosedTab, RemoteTab, RemoteTabGroup, TABS_CLIENT_TTL,
+};
 use crate::store::TabsStore;
-use crate::sync::record::{TabsRecord, TabsRecordTab};
+use crate::sync::record::{TabsRecord, TabsRecordClosedTab, TabsRecordTab, TabsRecordTabGroup};
 use anyhow::Result;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex, RwLock, Weak};
 use sync15::bso::{IncomingBso, OutgoingBso, OutgoingEnvelope};
@@ -81,6 +84,9 @@ impl ClientRemoteTabs {
                 .iter()
                 .map(RemoteTab::to_record_tab)
                 .collect(),
+            close_tab_requests: vec![],
+            recently_closed: vec![],
+            unknown_fields: Default::default(),
         }
     }
 }
@@ -92,6 +98,13 @@ impl RemoteTab {
             url_history: tab.url_history.clone(),
             icon: tab.icon.clone(),
             last_used: tab.last_used.checked_mul(1000).unwrap_or_default(),
+            pinned: tab.pinned,
+            inactive: tab.inactive,
+            group: tab.group.as_ref().map(|group| RemoteTabGroup {
+                id: group.id.clone(),
+                name: group.name.clone(),
+                color: group.color.clone(),
+            }),
         }
     }
     pub(super) fn to_record_tab(&self) -> TabsRecordTab {
@@ -100,6 +113,26 @@ impl RemoteTab {
             url_history: self.url_history.clone(),
             icon: self.icon.clone(),
             last_used: self.last_used.checked_div(1000).unwrap_or_default(),
+            pinned: self.pinned,
+            inactive: self.inactive,
+            group: self.group.as_ref().map(|group| TabsRecordTabGroup {
+                id: group.id.clone(),
+                name: group.name.clone(),
+                color: group.color.clone(),
+                unknown_fields: Default::default(),
+            }),
+            unknown_fields: Default::default(),
+        }
+    }
+}
+
+impl RecentlyClosedTab {
+    fn to_record_closed_tab(&self) -> TabsRecordClosedTab {
+        TabsRecordClosedTab {
+            title: self.title.clone(),
+            url: self.url.clone(),
+            closed: self.time_closed.checked_div(1000).unwrap_or_default(),
+            unknown_fields: Default::default(),
         }
     }
 }
@@ -109,6 +142,15 @@ impl RemoteTab {
 pub struct TabsEngine {
     pub(super) store: Arc<TabsStore>,
     pub(super) local_id: RwLock<String>,
+    // The hash of the payload we handed out in `apply`, which we remember
+    // once it's uploaded.
+    pending_upload_hash: Mutex<Option<String>>,
+}
+
+// The hash is persisted, so it must not change between builds - which rules out
+// `DefaultHasher`.
+fn hash_payload(payload: &str) -> String {
+    format!("{:x}", Sha256::digest(payload.as_bytes()))
 }
 
 impl TabsEngine {
@@ -116,6 +158,7 @@ impl TabsEngine {
         Self {
             store,
             local_id: Default::default(),
+            pending_upload_hash: Default::default(),
         }
     }
 
@@ -185,6 +228,7 @@ impl SyncEngine for TabsEngine {
         // In desktop we might end up here with zero records when doing a quick-write, in
         // which case we don't want to wipe the DB.
         if !remote_tabs.is_empty() {
+            storage.record_incoming_close_tab_requests(local_id, &remote_tabs)?;
             storage.replace_remote_tabs(remote_tabs)?;
         }
         storage.remove_stale_clients()?;
@@ -197,16 +241,23 @@ impl SyncEngine for TabsEngine {
         _telem: &mut telemetry::Engine,
     ) -> Result<Vec<OutgoingBso>> {
         // We've already applied them - really we just need to fetch outgoing.
-        let (local_tabs, remote_clients) = {
+        let (local_tabs, recently_closed, close_tab_requests, remote_clients) = {
             let mut storage = self.store.storage.lock().unwrap();
             let local_tabs = storage.prepare_local_tabs_for_upload();
+            let recently_closed = storage.prepare_recently_closed_for_upload();
+            let close_tab_requests = storage.prepare_close_tab_requests_for_upload()?;
             let remote_clients: HashMap<String, RemoteClient> = {
                 match storage.get_meta::<String>(schema::REMOTE_CLIENTS_KEY)? {
                     None => HashMap::default(),
                     Some(json) => serde_json::from_str(&json).unwrap(),
                 }
             };
-            (local_tabs, remote_clients)
+            (
+                local_tabs,
+                recently_closed,
+                close_tab_requests,
+                remote_clients,
+            )
         };
 
         let local_id = &*self.local_id.read().unwrap();
@@ -230,10 +281,29 @@ impl SyncEngine for TabsEngine {
                 ttl: Some(TABS_CLIENT_TTL),
                 ..Default::default()
             };
-            vec![OutgoingBso::from_content(
-                envelope,
-                local_record.to_record(),
-            )?]
+            let record = TabsRecord {
+                close_tab_requests,
+                recently_closed: recently_closed
+                    .iter()
+                    .map(RecentlyClosedTab::to_record_closed_tab)
+                    .collect(),
+                ..local_record.to_record()
+            };
+            let bso = OutgoingBso::from_content(envelope, record)?;
+            let payload_hash = hash_payload(&bso.payload);
+            let should_upload = self
+                .store
+                .storage
+                .lock()
+                .unwrap()
+                .should_upload(&payload_hash)?;
+            if should_upload {
+                *self.pending_upload_hash.lock().unwrap() = Some(payload_hash);
+                vec![bso]
+            } else {
+                log::debug!("Our tabs haven't changed since the last upload; skipping it");
+                vec![]
+            }
         } else {
             vec![]
         };
@@ -243,6 +313,14 @@ impl SyncEngine for TabsEngine {
     fn set_uploaded(&self, new_timestamp: ServerTimestamp, ids: Vec<Guid>) -> Result<()> {
         log::info!("sync uploaded {} records", ids.len());
         self.set_last_sync(new_timestamp)?;
+        let pending_upload_hash = self.pending_upload_hash.lock().unwrap().take();
+        if let Some(payload_hash) = pending_upload_hash {
+            let local_id = &*self.local_id.read().unwrap();
+            if ids.iter().any(|id| id.as_str() == local_id) {
+                let mut storage = self.store.storage.lock().unwrap();
+                storage.set_last_upload(&payload_hash)?;
+            }
+        }
         Ok(())
     }
 
@@ -266,11 +344,13 @@ impl SyncEngine for TabsEngine {
         self.set_last_sync(ServerTimestamp(0))?;
         let mut storage = self.store.storage.lock().unwrap();
         storage.delete_meta(schema::REMOTE_CLIENTS_KEY)?;
+        storage.forget_last_upload()?;
         storage.wipe_remote_tabs()?;
         match assoc {
             EngineSyncAssociation::Disconnected => {
                 storage.delete_meta(schema::GLOBAL_SYNCID_META_KEY)?;
                 storage.delete_meta(schema::COLLECTION_SYNCID_META_KEY)?;
+                storage.wipe_close_tab_requests()?;
             }
             EngineSyncAssociation::Connected(ids) => {
                 storage.put_meta(schema::GLOBAL_SYNCID_META_KEY, &ids.global.to_string())?;
@@ -284,7 +364,7 @@ impl SyncEngine for TabsEngine {
         self.reset(&EngineSyncAssociation::Disconnected)?;
         // not clear why we need to wipe the local tabs - the app is just going
         // to re-add them?
-        self.store.storage.lock().unwrap().wipe_local_tabs();
+        self.store.storage.lock().unwrap().wipe_local_tabs()?;
         Ok(())
     }
 
@@ -321,6 +401,15 @@ pub mod test {
     use serde_json::json;
     use sync15::bso::IncomingBso;
 
+    #[test]
+    fn test_hash_payload() {
+        // The hash is persisted, so it must be the same on every build.
+        assert_eq!(
+            hash_payload("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
     #[test]
     fn test_incoming_tabs() {
         env_logger::try_init().ok();
@@ -404,6 +493,24 @@ pub mod test {
         assert_eq!(crt.remote_tabs.len(), 0);
     }
 
+    #[test]
+    fn test_tab_metadata_roundtrip() {
+        let tab = RemoteTab {
+            title: "the title".to_string(),
+            url_history: vec!["https://mozilla.org/".to_string()],
+            icon: None,
+            last_used: 1643764207000,
+            pinned: true,
+            inactive: true,
+            group: Some(RemoteTabGroup {
+                id: "group-1".to_string(),
+                name: "Work".to_string(),
+                color: Some("blue".to_string()),
+            }),
+        };
+        assert_eq!(RemoteTab::from_record_tab(&tab.to_record_tab()), tab);
+    }
+
     #[test]
     fn test_no_incoming_doesnt_write() {
         env_logger::try_init().ok();
@@ -455,6 +562,195 @@ pub mod test {
         }
     }
 
+    #[test]
+    fn test_close_tab_requests() {
+        env_logger::try_init().ok();
+
+        let engine = TabsEngine::new(Arc::new(TabsStore::new_with_mem_path(
+            "test_close_tab_requests",
+        )));
+        *engine.local_id.write().unwrap() = "local-device".to_string();
+        // Requests made within the last week are fine.
+        let requested = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let record = json!({
+            "id": "device-with-requests",
+            "clientName": "device with requests",
+            "tabs": [],
+            "closeTabRequests": [{
+                "clientId": "local-device",
+                "url": "https://mozilla.org/",
+                "requested": requested,
+            }, {
+                "clientId": "other-device",
+                "url": "https://example.com/",
+                "requested": requested,
+            }, {
+                "clientId": "local-device",
+                "url": "https://expired.example.com/",
+                "requested": 1643764207,
+            }]
+        });
+        let stage = |record: &serde_json::Value| {
+            engine
+                .stage_incoming(
+                    vec![IncomingBso::from_test_content(record.clone())],
+                    &mut telemetry::Engine::new("tabs"),
+                )
+                .expect("should stage");
+        };
+        stage(&record);
+        let pending = engine
+            .store
+            .get_pending_close_tab_requests()
+            .expect("should work");
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].url, "https://mozilla.org/");
+        assert_eq!(pending[0].time_requested, requested as i64 * 1000);
+
+        engine
+            .store
+            .acknowledge_close_tab_requests(vec!["https://mozilla.org/".to_string()])
+            .expect("should work");
+        assert!(engine
+            .store
+            .get_pending_close_tab_requests()
+            .expect("should work")
+            .is_empty());
+
+        // The other device keeps sending the request until it sees we've
+        // closed the tab, but we've already acknowledged it.
+        stage(&record);
+        assert!(engine
+            .store
+            .get_pending_close_tab_requests()
+            .expect("should work")
+            .is_empty());
+    }
+
+    #[test]
+    fn test_outgoing_close_tab_requests() {
+        env_logger::try_init().ok();
+
+        let store = Arc::new(TabsStore::new_with_mem_path(
+            "test_outgoing_close_tab_requests",
+        ));
+        store.set_local_tabs(vec![]);
+        store
+            .close_remote_tabs(
+                "other-device".to_string(),
+                vec!["https://mozilla.org/".to_string()],
+            )
+            .expect("should work");
+        let engine = TabsEngine::new(Arc::clone(&store));
+        *engine.local_id.write().unwrap() = "local-device".to_string();
+        let outgoing = engine
+            .apply(ServerTimestamp(0), &mut telemetry::Engine::new("tabs"))
+            .expect("should apply");
+        assert_eq!(outgoing.len(), 1);
+        let record: TabsRecord = serde_json::from_str(&outgoing[0].payload).unwrap();
+        assert_eq!(record.close_tab_requests.len(), 1);
+        assert_eq!(record.close_tab_requests[0].client_id, "other-device");
+        assert_eq!(record.close_tab_requests[0].url, "https://mozilla.org/");
+    }
+
+    #[test]
+    fn test_unchanged_tabs_not_reuploaded() {
+        env_logger::try_init().ok();
+
+        let store = Arc::new(TabsStore::new_with_mem_path(
+            "test_unchanged_tabs_not_reuploaded",
+        ));
+        store.set_local_tabs(vec![RemoteTab {
+            title: "the title".to_string(),
+            url_history: vec!["https://mozilla.org/".to_string()],
+            ..Default::default()
+        }]);
+        let engine = TabsEngine::new(Arc::clone(&store));
+        *engine.local_id.write().unwrap() = "local-device".to_string();
+        let sync = |engine: &TabsEngine| {
+            let outgoing = engine
+                .apply(ServerTimestamp(0), &mut telemetry::Engine::new("tabs"))
+                .expect("should apply");
+            let ids = outgoing.iter().map(|bso| bso.envelope.id.clone()).collect();
+            engine
+                .set_uploaded(ServerTimestamp(1234), ids)
+                .expect("should work");
+            outgoing.len()
+        };
+        assert_eq!(sync(&engine), 1);
+        assert_eq!(sync(&engine), 0);
+
+        store.set_local_tabs(vec![]);
+        assert_eq!(sync(&engine), 1);
+
+        // A reset means the server might not have our record any more.
+        engine
+            .reset(&EngineSyncAssociation::Disconnected)
+            .expect("should reset");
+        assert_eq!(sync(&engine), 1);
+    }
+
+    #[test]
+    fn test_recently_closed() {
+        env_logger::try_init().ok();
+
+        let store = Arc::new(TabsStore::new_with_mem_path("test_recently_closed"));
+        store.set_local_tabs(vec![]);
+        store.set_recently_closed_tabs(vec![RecentlyClosedTab {
+            title: "the title".to_string(),
+            url: "https://mozilla.org/".to_string(),
+            time_closed: 1643764207000,
+        }]);
+        let engine = TabsEngine::new(Arc::clone(&store));
+        *engine.local_id.write().unwrap() = "local-device".to_string();
+        let outgoing = engine
+            .apply(ServerTimestamp(0), &mut telemetry::Engine::new("tabs"))
+            .expect("should apply");
+        assert_eq!(outgoing.len(), 1);
+        let record: TabsRecord = serde_json::from_str(&outgoing[0].payload).unwrap();
+        assert_eq!(record.recently_closed.len(), 1);
+        assert_eq!(record.recently_closed[0].url, "https://mozilla.org/");
+        assert_eq!(record.recently_closed[0].closed, 1643764207);
+
+        // Other devices' lists are available by their ID.
+        engine
+            .stage_incoming(
+                vec![IncomingBso::from_test_content(json!({
+                    "id": "other-device",
+                    "clientName": "other device",
+                    "tabs": [],
+                    "recentlyClosed": [{
+                        "title": "older",
+                        "url": "https://example.com/older",
+                        "closed": 1643764200,
+                    }, {
+                        "title": "newer",
+                        "url": "https://example.com/newer",
+                        "closed": 1643764207,
+                    }],
+                }))],
+                &mut telemetry::Engine::new("tabs"),
+            )
+            .expect("should stage");
+        let recently_closed = store
+            .get_recently_closed("other-device".to_string())
+            .expect("should work");
+        assert_eq!(
+            recently_closed
+                .iter()
+                .map(|tab| (tab.title.as_str(), tab.time_closed))
+                .collect::<Vec<_>>(),
+            vec![("newer", 1643764207000), ("older", 1643764200000)]
+        );
+        assert!(store
+            .get_recently_closed("unknown-device".to_string())
+            .expect("should work")
+            .is_empty());
+    }
+
     #[test]
     fn test_sync_manager_registration() {
         let store = Arc::new(TabsStore::new_with_mem_path("test-registration"));
@@ -3,17 +3,65 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use serde_derive::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, Default)]
+// Fields we don't know about (eg, added by newer clients) are kept in
+// `unknown_fields` on the records and tabs, so we don't strip them when we
+// store the records.
+pub type UnknownFields = Map<String, Value>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct TabsRecordTab {
     pub title: String,
     pub url_history: Vec<String>,
     pub icon: Option<String>,
     pub last_used: i64, // Seconds since epoch!
+    // These are all optional, and omitted when they have the default value.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub inactive: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<TabsRecordTabGroup>,
+    #[serde(flatten)]
+    pub unknown_fields: UnknownFields,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TabsRecordTabGroup {
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(flatten)]
+    pub unknown_fields: UnknownFields,
+}
+
+// A request from the client which uploaded the record for another client to
+// close a tab.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TabsRecordCloseTabRequest {
+    // The ID of the tabs record of the client which should close the tab.
+    pub client_id: String,
+    pub url: String,
+    pub requested: i64, // Seconds since epoch!
+}
+
+// A tab the client which uploaded the record closed recently.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TabsRecordClosedTab {
+    pub title: String,
+    pub url: String,
+    pub closed: i64, // Seconds since epoch!
+    #[serde(flatten)]
+    pub unknown_fields: UnknownFields,
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 // This struct mirrors what is stored on the server
 pub struct TabsRecord {
@@ -22,6 +70,14 @@ pub struct TabsRecord {
     pub id: String,
     pub client_name: String,
     pub tabs: Vec<TabsRecordTab>,
+    // Older clients don't know about this, and it's omitted when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub close_tab_requests: Vec<TabsRecordCloseTabRequest>,
+    // As above, omitted when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recently_closed: Vec<TabsRecordClosedTab>,
+    #[serde(flatten)]
+    pub unknown_fields: UnknownFields,
 }
 
 #[cfg(test)]
@@ -63,7 +119,27 @@ pub mod test {
                 url_history: vec!["https://mozilla.org/".into()],
                 icon: Some("https://mozilla.org/icon".into()),
                 last_used: 1643764207,
+                pinned: true,
+                group: Some(TabsRecordTabGroup {
+                    id: "group-1".into(),
+                    name: "Work".into(),
+                    color: Some("blue".into()),
+                    ..Default::default()
+                }),
+                ..Default::default()
             }],
+            close_tab_requests: vec![TabsRecordCloseTabRequest {
+                client_id: "AbCdEfGhIjKl".into(),
+                url: "https://example.com/".into(),
+                requested: 1643764208,
+            }],
+            recently_closed: vec![TabsRecordClosedTab {
+                title: "closed".into(),
+                url: "https://example.com/closed".into(),
+                closed: 1643764209,
+                ..Default::default()
+            }],
+            unknown_fields: Default::default(),
         };
         let round_tripped =
             serde_json::from_value(serde_json::to_value(tab.clone()).unwrap()).unwrap();
@@ -89,9 +165,104 @@ pub mod test {
                 "ignoredField": "??",
             }]
         });
-        let record: TabsRecord = serde_json::from_value(payload).unwrap();
-        // The point of this test is really just to ensure the deser worked, so
-        // just check the ID.
+        let record: TabsRecord = serde_json::from_value(payload.clone()).unwrap();
         assert_eq!(record.id, "JkeBPC50ZI0m");
+        assert!(!record.tabs[0].pinned);
+        // The unknown fields are kept when we serialize it again.
+        assert_eq!(serde_json::to_value(record).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_tab_metadata() {
+        let payload = json!({
+            "id": "JkeBPC50ZI0m",
+            "clientName": "client name",
+            "tabs": [{
+                "title": "the title",
+                "urlHistory": ["https://mozilla.org/"],
+                "icon": null,
+                "lastUsed": 1643764207,
+                "pinned": true,
+                "inactive": true,
+                "group": {
+                    "id": "group-1",
+                    "name": "Work",
+                    "color": "blue",
+                    "collapsed": true
+                }
+            }]
+        });
+        let record: TabsRecord = serde_json::from_value(payload).unwrap();
+        let tab = &record.tabs[0];
+        assert!(tab.pinned);
+        assert!(tab.inactive);
+        let group = tab.group.as_ref().unwrap();
+        assert_eq!(group.id, "group-1");
+        assert_eq!(group.name, "Work");
+        assert_eq!(group.color.as_deref(), Some("blue"));
+        assert_eq!(group.unknown_fields["collapsed"], true);
+    }
+
+    #[test]
+    fn test_close_tab_requests() {
+        let payload = json!({
+            "id": "JkeBPC50ZI0m",
+            "clientName": "client name",
+            "tabs": [],
+            "closeTabRequests": [{
+                "clientId": "AbCdEfGhIjKl",
+                "url": "https://example.com/",
+                "requested": 1643764207
+            }]
+        });
+        let record: TabsRecord = serde_json::from_value(payload).unwrap();
+        assert_eq!(
+            record.close_tab_requests,
+            vec![TabsRecordCloseTabRequest {
+                client_id: "AbCdEfGhIjKl".into(),
+                url: "https://example.com/".into(),
+                requested: 1643764207,
+            }]
+        );
+
+        // Records without requests don't mention them, so we don't bloat every
+        // record.
+        let record = TabsRecord {
+            close_tab_requests: vec![],
+            ..record
+        };
+        let value = serde_json::to_value(record).unwrap();
+        assert!(value.get("closeTabRequests").is_none());
+    }
+
+    #[test]
+    fn test_recently_closed() {
+        let payload = json!({
+            "id": "JkeBPC50ZI0m",
+            "clientName": "client name",
+            "tabs": [],
+            "recentlyClosed": [{
+                "title": "the title",
+                "url": "https://example.com/",
+                "closed": 1643764207
+            }]
+        });
+        let record: TabsRecord = serde_json::from_value(payload).unwrap();
+        assert_eq!(
+            record.recently_closed,
+            vec![TabsRecordClosedTab {
+                title: "the title".into(),
+                url: "https://example.com/".into(),
+                closed: 1643764207,
+                ..Default::default()
+            }]
+        );
+
+        let record = TabsRecord {
+            recently_closed: vec![],
+            ..record
+        };
+        let value = serde_json::to_value(record).unwrap();
+        assert!(value.get("recentlyClosed").is_none());
     }
 }